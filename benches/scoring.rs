@@ -0,0 +1,97 @@
+//! Scoring throughput on a large synthetic master data set.
+//!
+//! `BENCH_ROWS` sets the number of rows (5M by default):
+//! `BENCH_ROWS=1000000 cargo bench --bench scoring --features parallel`
+
+use std::collections::HashSet;
+use std::fmt::Write;
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use dos_esfinges_bot::bootstrap;
+use dos_esfinges_bot::config::{BootstrapConfig, GainMatrix, IdType, MetricConfig};
+use dos_esfinges_bot::gain_curve::GainCurve;
+use dos_esfinges_bot::master_data::{MasterData, Split};
+use dos_esfinges_bot::metrics::{self, DensePredictions, MetricSet};
+use dos_esfinges_bot::models::{Id, Predictions};
+
+fn rows() -> i64 {
+    std::env::var("BENCH_ROWS")
+        .ok()
+        .and_then(|n| n.parse().ok())
+        .unwrap_or(5_000_000)
+}
+
+fn master_data(rows: i64) -> (tempfile::TempDir, MasterData) {
+    let dir = tempfile::TempDir::new().unwrap();
+    let path = dir.path().join("master_data.csv");
+
+    let mut csv = String::with_capacity(rows as usize * 16);
+    csv.push_str("id,clase_binaria,split\n");
+    for id in 0..rows {
+        let split = if id % 10 < 3 { "private" } else { "public" };
+        writeln!(csv, "{},{},{}", id, (id % 17 == 0) as i32, split).unwrap();
+    }
+    std::fs::write(&path, csv).unwrap();
+
+    let master_data = MasterData::load(path.to_str().unwrap(), IdType::Integer).unwrap();
+    (dir, master_data)
+}
+
+fn scoring(c: &mut Criterion) {
+    let rows = rows();
+    let (_dir, master_data) = master_data(rows);
+    let gain_matrix = GainMatrix {
+        tp: 117_000.0,
+        tn: 0.0,
+        fp: -3_000.0,
+        fn_: 0.0,
+    };
+    let gain = MetricSet::new(metrics::build(&MetricConfig::Gain, &gain_matrix, None), vec![]);
+
+    // A tenth of the rows predicted positive
+    let ids: HashSet<Id> = (0..rows).filter(|id| id % 10 == 0).map(Id::from).collect();
+    let ids = Predictions::Ids(ids);
+    let scores = Predictions::Scores((0..rows).map(|id| (Id::from(id), (id % 1000) as f64 / 1000.0)).collect());
+
+    let mut group = c.benchmark_group(format!("scoring_{}_rows", rows));
+    group.sample_size(10);
+
+    group.bench_function("ids_dense_predictions", |b| {
+        b.iter(|| DensePredictions::new(&ids, 0.0, &master_data))
+    });
+
+    let dense = DensePredictions::new(&ids, 0.0, &master_data);
+    group.bench_function("ids_gain_all_splits", |b| {
+        b.iter(|| {
+            for split in [None, Some(Split::Public), Some(Split::Private)] {
+                gain.evaluate_dense(&dense, &master_data, split);
+            }
+        })
+    });
+
+    group.bench_function("scores_gain", |b| {
+        b.iter(|| gain.evaluate(&scores, 0.9, &master_data, None))
+    });
+
+    if let Predictions::Scores(scores) = &scores {
+        group.bench_function("scores_gain_curve", |b| {
+            b.iter(|| GainCurve::compute(scores, &master_data, &gain_matrix))
+        });
+    }
+
+    // Cost per resample: the bootstrap of a submission runs `bootstrap.iterations`
+    // of these (1000 by default) for every scope, off the async runtime
+    let config = BootstrapConfig {
+        iterations: 10,
+        ..Default::default()
+    };
+    let all_rows = bootstrap::rows_in_scope(&master_data, None);
+    group.bench_function("ids_bootstrap_10_resamples", |b| {
+        b.iter(|| bootstrap::confidence_interval(gain.primary(), &dense, &master_data, &all_rows, &config))
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, scoring);
+criterion_main!(benches);
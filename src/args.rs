@@ -0,0 +1,206 @@
+//! Arguments of bot commands: words, "quoted phrases" and `--desc`. Zulip
+//! attachment links are removed first, since their names may contain spaces.
+
+use regex::Regex;
+
+/// Longest submission name accepted, in characters.
+pub const MAX_NAME_CHARS: usize = 80;
+
+/// Splits `text` into words, keeping `"quoted phrases"` (straight or curly
+/// quotes) together. Fails on an unclosed quote.
+pub fn split(text: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    // `""` is an empty argument, not nothing
+    let mut pending = false;
+
+    for c in text.chars() {
+        match c {
+            '"' | '“' | '”' => {
+                quoted = !quoted;
+                pending = true;
+            }
+            c if c.is_whitespace() && !quoted => {
+                if pending || !current.is_empty() {
+                    words.push(std::mem::take(&mut current));
+                }
+                pending = false;
+            }
+            c => current.push(c),
+        }
+    }
+    if quoted {
+        return Err("❌ Falta cerrar las comillas".to_string());
+    }
+    if pending || !current.is_empty() {
+        words.push(current);
+    }
+    Ok(words)
+}
+
+/// `text` without Zulip attachment links (`[archivo.csv](/user_uploads/...)`).
+pub fn strip_attachments(text: &str) -> String {
+    let re = Regex::new(r"\[[^\]]*\]\([^)]*\)").expect("valid regex");
+    re.replace_all(text, " ").into_owned()
+}
+
+/// Trimmed, non-empty name of at most [`MAX_NAME_CHARS`].
+pub fn parse_name(raw: &str) -> Result<String, String> {
+    let name = raw.split_whitespace().collect::<Vec<_>>().join(" ");
+    if name.is_empty() {
+        return Err("❌ El nombre del envío no puede estar vacío".to_string());
+    }
+    if name.chars().count() > MAX_NAME_CHARS {
+        return Err(format!(
+            "❌ El nombre del envío puede tener hasta {} caracteres",
+            MAX_NAME_CHARS
+        ));
+    }
+    Ok(name)
+}
+
+/// `submit <nombre> <ganancia_esperada> [corte] [track=<track>] [palabras...] [--desc <descripción>]`
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubmitArgs {
+    pub name: String,
+    pub expected_gain: f64,
+    pub cutoff: Option<f64>,
+    /// Value of `track=<track>`, `None` for the main track
+    pub track: Option<String>,
+    /// Other words after the gain and cut-off, which describe the model
+    pub rest: Vec<String>,
+    /// Everything after `--desc`
+    pub description: Option<String>,
+}
+
+/// Splits `track=<track>` out of `words`. Naming the track explicitly keeps a
+/// description word that happens to be a track name from choosing it.
+fn take_track(words: &[String]) -> Result<(Option<String>, Vec<String>), String> {
+    let mut track = None;
+    let mut rest = Vec::new();
+    for word in words {
+        match word.split_once('=') {
+            Some((key, value)) if key.eq_ignore_ascii_case("track") => {
+                if value.is_empty() || track.replace(value.to_string()).is_some() {
+                    return Err("❌ Indica un solo track, como `track=<nombre>`".to_string());
+                }
+            }
+            _ => rest.push(word.clone()),
+        }
+    }
+    Ok((track, rest))
+}
+
+/// Cut-off for `id,probability` files, when `word` is a number. It has to be
+/// a probability, so `2` is an error rather than a cut-off nothing reaches.
+fn parse_cutoff(word: Option<&String>) -> Result<Option<f64>, String> {
+    match word.and_then(|w| w.parse::<f64>().ok()) {
+        Some(cutoff) if !(0.0..=1.0).contains(&cutoff) => {
+            Err(format!("❌ El corte debe estar entre 0 y 1, no {}", cutoff))
+        }
+        cutoff => Ok(cutoff),
+    }
+}
+
+pub fn parse_submit(content: &str) -> Result<SubmitArgs, String> {
+    const USAGE: &str = "❌ Formato incorrecto. Uso: `submit <nombre_envio> <ganancia_esperada>` y adjunta el archivo CSV \
+        (usa comillas para nombres con espacios: `submit \"xgboost tuned\" 1200`)";
+
+    let words = split(&strip_attachments(content))?;
+    let (positional, description) = match words.iter().position(|w| w.eq_ignore_ascii_case("--desc")) {
+        Some(i) => (&words[..i], Some(words[i + 1..].join(" "))),
+        None => (&words[..], None),
+    };
+    if positional.len() < 3 {
+        return Err(USAGE.to_string());
+    }
+
+    let name = parse_name(&positional[1])?;
+    let expected_gain: f64 = positional[2]
+        .parse()
+        .map_err(|_| "❌ La ganancia esperada debe ser un número".to_string())?;
+
+    // Optional cut-off for `id,probability` files
+    let cutoff = parse_cutoff(positional.get(3))?;
+    let skip = 3 + cutoff.is_some() as usize;
+
+    let (track, rest) = take_track(&positional[skip.min(positional.len())..])?;
+
+    Ok(SubmitArgs {
+        name,
+        expected_gain,
+        cutoff,
+        track,
+        rest,
+        description: description.filter(|d| !d.trim().is_empty()),
+    })
+}
+
+/// Name and expected gain from an attachment name: `rf_900.csv` is `rf` with
+/// an expected gain of 900, `rf.csv.gz` is `rf` without one.
+pub fn from_filename(filename: &str) -> (String, Option<f64>) {
+    let lower = filename.to_lowercase();
+    let stem_len = [".csv.gz", ".csv", ".zip"]
+        .iter()
+        .find(|ext| lower.ends_with(*ext))
+        .map_or(filename.len(), |ext| filename.len() - ext.len());
+    let stem = &filename[..stem_len];
+
+    let re = Regex::new(r"^(.+?)[_-](-?\d+(?:\.\d+)?)$").expect("valid regex");
+    match re.captures(stem) {
+        Some(caps) => (caps[1].to_string(), caps[2].parse().ok()),
+        None => (stem.to_string(), None),
+    }
+}
+
+/// Arguments of one file of a batch `submit`, from the text on the line of its
+/// link: `[nombre] [ganancia_esperada] [corte] [track=<track>] [palabras...] [--desc <descripción>]`.
+/// The name and gain missing from the line come from the file name. When
+/// several links share the line, its words only give the gain and what follows.
+pub fn parse_attachment_line(line: &str, filename: &str, shared_line: bool) -> Result<SubmitArgs, String> {
+    let mut words = split(&strip_attachments(line))?;
+    if words.first().is_some_and(|w| w.eq_ignore_ascii_case("submit")) {
+        words.remove(0);
+    }
+    let (mut positional, description) = match words.iter().position(|w| w.eq_ignore_ascii_case("--desc")) {
+        Some(i) => (&words[..i], Some(words[i + 1..].join(" "))),
+        None => (&words[..], None),
+    };
+
+    let (file_name, file_gain) = from_filename(filename);
+    let name = match positional.first() {
+        Some(first) if !shared_line && first.parse::<f64>().is_err() => {
+            positional = &positional[1..];
+            parse_name(first)?
+        }
+        _ => parse_name(&file_name)?,
+    };
+    let expected_gain = match positional.first().map(|w| w.parse::<f64>()) {
+        Some(Ok(gain)) => {
+            positional = &positional[1..];
+            gain
+        }
+        _ => file_gain.ok_or_else(|| {
+            format!(
+                "❌ Falta la ganancia esperada de `{}`: escríbela en su línea (`{} 1200`) o en el nombre del archivo (`{}_1200.csv`)",
+                filename, name, file_name
+            )
+        })?,
+    };
+    let cutoff = parse_cutoff(positional.first())?;
+    if cutoff.is_some() {
+        positional = &positional[1..];
+    }
+
+    let (track, rest) = take_track(positional)?;
+
+    Ok(SubmitArgs {
+        name,
+        expected_gain,
+        cutoff,
+        track,
+        rest,
+        description: description.filter(|d| !d.trim().is_empty()),
+    })
+}
@@ -0,0 +1,170 @@
+//! Prediction files arrive as plain `.csv`, `.csv.gz` or a `.zip` holding a
+//! single CSV. Downloads are streamed and compressed files expanded up to a
+//! size cap, so a large upload or a small archive cannot fill the disk or
+//! the memory of the bot.
+
+use std::io::{Cursor, Read};
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+use flate2::read::GzDecoder;
+use thiserror::Error;
+use zip::ZipArchive;
+
+const MB: u64 = 1024 * 1024;
+
+/// A limit hit while fetching or expanding an attachment, worded for the student.
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum LimitError {
+    #[error("el archivo supera el máximo de {max_mb} MB")]
+    Download { max_mb: u64 },
+    #[error("el archivo descomprimido supera el máximo de {max_mb} MB")]
+    Decompressed { max_mb: u64 },
+    #[error("la descarga tardó más de {secs} segundos")]
+    Timeout { secs: u64 },
+}
+
+/// An attachment that is not a valid archive, worded for the student with how
+/// to fix it.
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum ArchiveError {
+    #[error("el archivo .gz no es un gzip válido. Comprime el CSV de nuevo, p. ej. con `gzip envio.csv`")]
+    InvalidGzip,
+    #[error("el archivo .zip no es un zip válido. Comprime el CSV de nuevo o envíalo sin comprimir")]
+    InvalidZip,
+    #[error("el .zip debe contener exactamente un CSV (contiene {files}). Deja en el .zip solo el CSV de predicciones")]
+    EntryCount { files: usize },
+    #[error("el archivo dentro del .zip debe ser un CSV, no `{filename}`. Exporta las predicciones como `.csv`")]
+    NotCsv { filename: String },
+}
+
+/// Streams the response to `request` into memory, failing as soon as it
+/// exceeds `max_bytes` or takes longer than `timeout`.
+pub async fn download(request: reqwest::RequestBuilder, max_bytes: u64, timeout: Duration) -> Result<Vec<u8>> {
+    let too_large = LimitError::Download { max_mb: max_bytes / MB };
+    let fetch = async {
+        let mut response = request.send().await?.error_for_status()?;
+        // Zulip announces the size, which rejects most large files before reading them
+        if response.content_length().is_some_and(|len| len > max_bytes) {
+            return Err(too_large.clone().into());
+        }
+
+        let mut content = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            if (content.len() + chunk.len()) as u64 > max_bytes {
+                return Err(too_large.clone().into());
+            }
+            content.extend_from_slice(&chunk);
+        }
+        Ok(content)
+    };
+
+    match tokio::time::timeout(timeout, fetch).await {
+        Ok(result) => result,
+        Err(_) => Err(LimitError::Timeout {
+            secs: timeout.as_secs(),
+        }
+        .into()),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zip,
+}
+
+impl Compression {
+    /// Format of an attachment from its name, `None` when it is not accepted.
+    pub fn of(filename: &str) -> Option<Self> {
+        let filename = filename.to_lowercase();
+        if filename.ends_with(".csv.gz") {
+            Some(Compression::Gzip)
+        } else if filename.ends_with(".zip") {
+            Some(Compression::Zip)
+        } else if filename.ends_with(".csv") {
+            Some(Compression::None)
+        } else {
+            None
+        }
+    }
+}
+
+/// The CSV inside an attachment, with the name to store it under.
+pub struct Expanded {
+    pub filename: String,
+    pub content: Vec<u8>,
+}
+
+/// Expands `content` according to the extension of `filename`. Fails when the
+/// CSV would exceed `max_bytes`.
+pub fn expand(filename: &str, content: Vec<u8>, max_bytes: u64) -> Result<Expanded> {
+    match Compression::of(filename) {
+        Some(Compression::None) => Ok(Expanded {
+            filename: filename.to_string(),
+            content,
+        }),
+        Some(Compression::Gzip) => {
+            // Anything but the size cap means the stream is not gzip
+            let content = read_capped(GzDecoder::new(content.as_slice()), max_bytes).map_err(|e| {
+                if e.is::<LimitError>() {
+                    e
+                } else {
+                    ArchiveError::InvalidGzip.into()
+                }
+            })?;
+            Ok(Expanded {
+                // `predicciones.csv.gz` -> `predicciones.csv`
+                filename: filename[..filename.len() - ".gz".len()].to_string(),
+                content,
+            })
+        }
+        Some(Compression::Zip) => expand_zip(content, max_bytes),
+        None => bail!("tipo de archivo no admitido: {}", filename),
+    }
+}
+
+fn expand_zip(content: Vec<u8>, max_bytes: u64) -> Result<Expanded> {
+    let mut archive = ZipArchive::new(Cursor::new(content)).map_err(|_| ArchiveError::InvalidZip)?;
+
+    // Folders and the metadata macOS adds to every archive are not entries
+    let entries: Vec<usize> = (0..archive.len())
+        .filter(|i| {
+            archive.by_index_raw(*i).is_ok_and(|entry| {
+                !entry.is_dir() && !entry.name().starts_with("__MACOSX/")
+            })
+        })
+        .collect();
+    let index = match entries[..] {
+        [index] => index,
+        _ => return Err(ArchiveError::EntryCount { files: entries.len() }.into()),
+    };
+
+    let entry = archive.by_index(index).map_err(|_| ArchiveError::InvalidZip)?;
+    let filename = entry
+        .name()
+        .rsplit('/')
+        .next()
+        .unwrap_or_default()
+        .to_string();
+    if Compression::of(&filename) != Some(Compression::None) {
+        return Err(ArchiveError::NotCsv { filename }.into());
+    }
+    if entry.size() > max_bytes {
+        return Err(LimitError::Decompressed { max_mb: max_bytes / MB }.into());
+    }
+    let content = read_capped(entry, max_bytes)?;
+
+    Ok(Expanded { filename, content })
+}
+
+/// Reads at most `max_bytes`, failing instead of truncating.
+fn read_capped(reader: impl Read, max_bytes: u64) -> Result<Vec<u8>> {
+    let mut content = Vec::new();
+    reader.take(max_bytes.saturating_add(1)).read_to_end(&mut content)?;
+    if content.len() as u64 > max_bytes {
+        return Err(LimitError::Decompressed { max_mb: max_bytes / MB }.into());
+    }
+    Ok(content)
+}
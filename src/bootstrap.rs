@@ -0,0 +1,149 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::config::BootstrapConfig;
+use crate::master_data::{MasterData, Split};
+use crate::metrics::{self, DensePredictions, Metric};
+
+/// Percentile interval of a metric over bootstrap resamples of the master data.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Interval {
+    pub low: f64,
+    pub high: f64,
+}
+
+/// Paired bootstrap comparison of two submissions scored on the same resamples.
+#[derive(Debug, Clone, Copy)]
+pub struct PairedComparison {
+    /// `first - second` on the original rows, in metric units
+    pub difference: f64,
+    /// Two-sided p-value for "both submissions score the same"
+    pub p_value: f64,
+    /// Share of resamples where the first submission scores better
+    pub win_rate: f64,
+    pub iterations: usize,
+}
+
+/// Rows of the master data inside `split`, or all of them.
+pub fn rows_in_scope(master_data: &MasterData, split: Option<Split>) -> Vec<usize> {
+    match master_data.split_rows(split) {
+        Some(rows) => rows.iter().collect(),
+        None => (0..master_data.total_count()).collect(),
+    }
+}
+
+/// Bootstrap confidence interval for `metric`. Returns `None` when bootstrap
+/// is disabled or the metric is undefined on almost every resample.
+pub fn confidence_interval(
+    metric: &dyn Metric,
+    predictions: &DensePredictions,
+    master_data: &MasterData,
+    rows: &[usize],
+    config: &BootstrapConfig,
+) -> Option<Interval> {
+    let mut values = map_resamples(rows, config, |sample| {
+        metrics::evaluate_rows(metric, predictions, master_data, sample)
+    });
+
+    if values.is_empty() || values.len() < config.iterations / 2 {
+        return None;
+    }
+    values.sort_by(|a, b| a.total_cmp(b));
+
+    let tail = (1.0 - config.confidence) / 2.0;
+    Some(Interval {
+        low: quantile(&values, tail),
+        high: quantile(&values, 1.0 - tail),
+    })
+}
+
+/// Paired bootstrap test: both submissions are scored on the same resamples,
+/// so the shared noise of the rows cancels out in the difference.
+pub fn paired_test(
+    metric: &dyn Metric,
+    first: &DensePredictions,
+    second: &DensePredictions,
+    master_data: &MasterData,
+    rows: &[usize],
+    config: &BootstrapConfig,
+) -> Option<PairedComparison> {
+    let difference = |sample: &[usize]| {
+        let a = metrics::evaluate_rows(metric, first, master_data, sample)?;
+        let b = metrics::evaluate_rows(metric, second, master_data, sample)?;
+        Some(a - b)
+    };
+
+    let observed = difference(rows)?;
+    let resampled = map_resamples(rows, config, difference);
+    if resampled.is_empty() {
+        return None;
+    }
+
+    // Centering the resampled differences on 0 approximates the null distribution
+    let extreme = resampled
+        .iter()
+        .filter(|d| (*d - observed).abs() >= observed.abs())
+        .count();
+    let wins = resampled
+        .iter()
+        .filter(|d| {
+            if metric.higher_is_better() {
+                **d > 0.0
+            } else {
+                **d < 0.0
+            }
+        })
+        .count();
+
+    let n = resampled.len();
+    Some(PairedComparison {
+        difference: observed,
+        p_value: ((extreme + 1) as f64 / (n + 1) as f64).min(1.0),
+        win_rate: wins as f64 / n as f64,
+        iterations: n,
+    })
+}
+
+/// Linear interpolation between the closest ranks of sorted `values`.
+fn quantile(values: &[f64], q: f64) -> f64 {
+    let position = q * (values.len() - 1) as f64;
+    let lower = position.floor() as usize;
+    let upper = position.ceil() as usize;
+    values[lower] + (values[upper] - values[lower]) * (position - lower as f64)
+}
+
+/// Applies `f` to `config.iterations` resamples of `rows`, in parallel with
+/// the `parallel` feature. Each resample has its own seed, so the results do
+/// not depend on how iterations are scheduled.
+fn map_resamples<T: Send>(
+    rows: &[usize],
+    config: &BootstrapConfig,
+    f: impl Fn(&[usize]) -> Option<T> + Sync,
+) -> Vec<T> {
+    if rows.is_empty() {
+        return Vec::new();
+    }
+
+    #[cfg(feature = "parallel")]
+    {
+        use rayon::prelude::*;
+        (0..config.iterations)
+            .into_par_iter()
+            .filter_map(|i| f(&resample(rows, config.seed, i)))
+            .collect()
+    }
+
+    #[cfg(not(feature = "parallel"))]
+    {
+        (0..config.iterations)
+            .filter_map(|i| f(&resample(rows, config.seed, i)))
+            .collect()
+    }
+}
+
+/// `rows` drawn with replacement.
+fn resample(rows: &[usize], seed: u64, iteration: usize) -> Vec<usize> {
+    let mut rng = StdRng::seed_from_u64(seed ^ (iteration as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15));
+    let n = rows.len();
+    (0..n).map(|_| rows[rng.gen_range(0..n)]).collect()
+}
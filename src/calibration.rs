@@ -0,0 +1,101 @@
+use std::collections::HashMap;
+
+use crate::models::Submission;
+
+/// Students need at least this many submissions to enter the estimator ranking,
+/// so a single lucky guess does not top it.
+pub const MIN_RANKED_SUBMISSIONS: usize = 3;
+
+/// How well a student predicts the gain of their own models: every submission
+/// pairs the declared `expected_gain` with the scored `actual_gain`.
+#[derive(Debug, Clone)]
+pub struct Calibration {
+    /// Zulip user id; email and name are the ones of the latest submission
+    pub user_id: i64,
+    pub user_email: String,
+    pub user_full_name: String,
+    pub submissions: usize,
+    /// Mean of `|expected - actual|`
+    pub mae: f64,
+    /// Mean of `expected - actual`; positive means the student overestimates
+    pub bias: f64,
+    /// Pearson correlation between expected and actual; `None` with fewer than
+    /// two submissions or when either side is constant
+    pub correlation: Option<f64>,
+}
+
+impl Calibration {
+    /// Calibration over the submissions of a single student.
+    pub fn of_student(submissions: &[&Submission]) -> Option<Self> {
+        let latest = submissions.iter().max_by(|a, b| a.timestamp.cmp(&b.timestamp))?;
+        let pairs: Vec<(f64, f64)> = submissions
+            .iter()
+            .map(|sub| (sub.expected_gain, sub.actual_gain))
+            .collect();
+        let n = pairs.len() as f64;
+
+        Some(Self {
+            user_id: latest.user_id,
+            user_email: latest.user_email.clone(),
+            user_full_name: latest.user_full_name.clone(),
+            submissions: pairs.len(),
+            mae: pairs.iter().map(|(e, a)| (e - a).abs()).sum::<f64>() / n,
+            bias: pairs.iter().map(|(e, a)| e - a).sum::<f64>() / n,
+            correlation: correlation(&pairs),
+        })
+    }
+
+    pub fn is_ranked(&self) -> bool {
+        self.submissions >= MIN_RANKED_SUBMISSIONS
+    }
+}
+
+fn correlation(pairs: &[(f64, f64)]) -> Option<f64> {
+    if pairs.len() < 2 {
+        return None;
+    }
+    let n = pairs.len() as f64;
+    let mean_e = pairs.iter().map(|(e, _)| e).sum::<f64>() / n;
+    let mean_a = pairs.iter().map(|(_, a)| a).sum::<f64>() / n;
+
+    let (mut cov, mut var_e, mut var_a) = (0.0, 0.0, 0.0);
+    for (e, a) in pairs {
+        cov += (e - mean_e) * (a - mean_a);
+        var_e += (e - mean_e).powi(2);
+        var_a += (a - mean_a).powi(2);
+    }
+    if var_e == 0.0 || var_a == 0.0 {
+        return None;
+    }
+    Some(cov / (var_e * var_a).sqrt())
+}
+
+/// Calibration of every student in `submissions`, ordered by name. Students
+/// are told apart by Zulip user id, so a changed email keeps a single row.
+pub fn by_student(submissions: &[Submission], teachers: &[String]) -> Vec<Calibration> {
+    let mut by_user: HashMap<i64, Vec<&Submission>> = HashMap::new();
+    for sub in submissions {
+        if !teachers.contains(&sub.user_email) {
+            by_user.entry(sub.user_id).or_default().push(sub);
+        }
+    }
+
+    let mut calibrations: Vec<Calibration> = by_user
+        .values()
+        .filter_map(|subs| Calibration::of_student(subs))
+        .collect();
+    calibrations.sort_by(|a, b| a.user_full_name.cmp(&b.user_full_name));
+    calibrations
+}
+
+/// Best estimators first: lowest MAE, ties broken by the smaller absolute bias.
+/// Students below [`MIN_RANKED_SUBMISSIONS`] are left out.
+pub fn estimator_ranking(calibrations: &[Calibration]) -> Vec<&Calibration> {
+    let mut ranked: Vec<&Calibration> = calibrations.iter().filter(|c| c.is_ranked()).collect();
+    ranked.sort_by(|a, b| {
+        a.mae
+            .total_cmp(&b.mae)
+            .then(a.bias.abs().total_cmp(&b.bias.abs()))
+    });
+    ranked
+}
@@ -1,7 +1,11 @@
 use anyhow::{Context, Result};
+use chrono::NaiveTime;
+use chrono_tz::Tz;
 use serde::{Deserialize, Serialize};
 use std::fs;
 
+use crate::master_data::{Cell, CellAmounts};
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BotConfig {
     pub zulip: ZulipConfig,
@@ -13,6 +17,13 @@ pub struct BotConfig {
     pub gain_matrix: GainMatrix,
     pub gain_thresholds: Vec<GainThreshold>,
     pub competition: CompetitionConfig,
+    #[serde(default)]
+    pub metrics: MetricsConfig,
+    /// Matriz NxN de ganancias, requerida en competencias multiclase
+    #[serde(default)]
+    pub class_gain_matrix: Option<ClassGainMatrix>,
+    #[serde(default)]
+    pub bootstrap: BootstrapConfig,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -35,11 +46,165 @@ pub struct LogsConfig {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MasterDataConfig {
     pub path: String,
+    #[serde(default)]
+    pub id_type: IdType,
+}
+
+/// Tipo de los IDs del dataset maestro y de los envíos.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IdType {
+    /// Enteros de hasta 64 bits
+    #[default]
+    Integer,
+    /// Texto libre: códigos de cliente, UUIDs, etc.
+    String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SubmissionsConfig {
     pub path: String,
+    /// Corte aplicado a envíos `id,probability` cuando el estudiante no declara uno
+    #[serde(default = "default_cutoff")]
+    pub default_cutoff: f64,
+    /// Tamaño máximo en MB de un `.csv.gz` o `.zip` una vez descomprimido
+    #[serde(default = "default_max_decompressed_mb")]
+    pub max_decompressed_mb: u64,
+    /// Tamaño máximo en MB de un adjunto al descargarlo
+    #[serde(default = "default_max_download_mb")]
+    pub max_download_mb: u64,
+    /// Segundos máximos para descargar un adjunto
+    #[serde(default = "default_download_timeout_secs")]
+    pub download_timeout_secs: u64,
+    /// Filas máximas de un archivo de envío, sin contar el encabezado
+    #[serde(default = "default_max_rows")]
+    pub max_rows: usize,
+    /// Chequeos del archivo antes de puntuarlo
+    #[serde(default)]
+    pub validation: ValidationConfig,
+    /// Cuántos envíos puede hacer cada estudiante
+    #[serde(default)]
+    pub quota: QuotaConfig,
+}
+
+/// Cuotas de envíos por estudiante; sin límite cuando se omiten.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuotaConfig {
+    /// Envíos por día, contados desde `reset_time`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub daily: Option<u32>,
+    /// Envíos en toda la competencia
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total: Option<u32>,
+    /// Hora `HH:MM` de la zona horaria de la competencia a la que se renueva la cuota diaria
+    #[serde(default = "default_reset_time")]
+    pub reset_time: String,
+}
+
+fn default_reset_time() -> String {
+    "00:00".to_string()
+}
+
+impl Default for QuotaConfig {
+    fn default() -> Self {
+        Self {
+            daily: None,
+            total: None,
+            reset_time: default_reset_time(),
+        }
+    }
+}
+
+impl QuotaConfig {
+    pub fn reset_time(&self) -> Result<NaiveTime> {
+        NaiveTime::parse_from_str(&self.reset_time, "%H:%M")
+            .with_context(|| format!("Invalid quota reset_time '{}' (expected HH:MM)", self.reset_time))
+    }
+}
+
+/// Qué hacer cuando un chequeo de validación falla.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    /// El envío se rechaza
+    Error,
+    /// El envío se puntúa y la respuesta incluye el aviso
+    Warning,
+    Off,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ValidationConfig {
+    /// Cuántos IDs desconocidos o repetidos se listan como máximo
+    #[serde(default = "default_listed_ids")]
+    pub listed_ids: usize,
+    #[serde(default = "default_error")]
+    pub duplicate_ids: Severity,
+    #[serde(default = "default_error")]
+    pub unknown_ids: Severity,
+    /// Porcentaje de positivos (0-100) sobre el dataset por debajo del cual se avisa
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_positive_pct: Option<f64>,
+    /// Porcentaje de positivos (0-100) sobre el dataset por encima del cual se avisa
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_positive_pct: Option<f64>,
+    #[serde(default = "default_warning")]
+    pub positive_share: Severity,
+}
+
+fn default_listed_ids() -> usize {
+    10
+}
+
+fn default_error() -> Severity {
+    Severity::Error
+}
+
+fn default_warning() -> Severity {
+    Severity::Warning
+}
+
+impl Default for ValidationConfig {
+    fn default() -> Self {
+        Self {
+            listed_ids: default_listed_ids(),
+            duplicate_ids: default_error(),
+            unknown_ids: default_error(),
+            min_positive_pct: None,
+            max_positive_pct: None,
+            positive_share: default_warning(),
+        }
+    }
+}
+
+fn default_cutoff() -> f64 {
+    0.5
+}
+
+fn default_max_decompressed_mb() -> u64 {
+    200
+}
+
+fn default_max_download_mb() -> u64 {
+    50
+}
+
+fn default_download_timeout_secs() -> u64 {
+    60
+}
+
+fn default_max_rows() -> usize {
+    5_000_000
+}
+
+impl SubmissionsConfig {
+    pub fn max_decompressed_bytes(&self) -> u64 {
+        self.max_decompressed_mb.saturating_mul(1024 * 1024)
+    }
+
+    pub fn max_download_bytes(&self) -> u64 {
+        self.max_download_mb.saturating_mul(1024 * 1024)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -50,21 +215,269 @@ pub struct GainMatrix {
     pub fn_: f64,
 }
 
+impl GainMatrix {
+    pub fn evaluate(&self, tp: i32, tn: i32, fp: i32, fn_: i32) -> f64 {
+        (tp as f64) * self.tp
+            + (tn as f64) * self.tn
+            + (fp as f64) * self.fp
+            + (fn_ as f64) * self.fn_
+    }
+
+    /// Gain when some rows carry their own amounts in the master data.
+    pub fn evaluate_amounts(&self, amounts: &CellAmounts) -> f64 {
+        [
+            (Cell::Tp, self.tp),
+            (Cell::Tn, self.tn),
+            (Cell::Fp, self.fp),
+            (Cell::Fn, self.fn_),
+        ]
+        .iter()
+        .map(|(cell, gain)| {
+            let amount = amounts.get(*cell);
+            amount.fixed + amount.weight * gain
+        })
+        .sum()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClassGainMatrix {
+    /// Etiquetas de clase, en el orden de filas y columnas de `gains`
+    pub classes: Vec<String>,
+    /// `gains[real][predicha]`
+    pub gains: Vec<Vec<f64>>,
+    /// Clase asumida para los IDs que no aparecen en el envío (por defecto la primera)
+    #[serde(default)]
+    pub default_class: Option<String>,
+}
+
+impl ClassGainMatrix {
+    pub fn class_index(&self, label: &str) -> Option<usize> {
+        self.classes.iter().position(|c| c == label.trim())
+    }
+
+    pub fn default_class_index(&self) -> usize {
+        self.default_class
+            .as_deref()
+            .and_then(|c| self.class_index(c))
+            .unwrap_or(0)
+    }
+
+    pub fn evaluate(&self, confusion: &[Vec<i32>]) -> f64 {
+        confusion
+            .iter()
+            .zip(&self.gains)
+            .flat_map(|(counts, gains)| counts.iter().zip(gains))
+            .map(|(count, gain)| *count as f64 * gain)
+            .sum()
+    }
+}
+
+/// Métrica configurable en `metrics.primary` / `metrics.secondary`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MetricConfig {
+    /// Ganancia según `gain_matrix`
+    Gain,
+    F1,
+    PrecisionAtK { k: usize },
+    RocAuc,
+    LogLoss,
+    Accuracy,
+    Rmse,
+    Mae,
+    Mape,
+    /// Costo total por unidad de error: `over_cost` cuando se predice de más,
+    /// `under_cost` cuando se predice de menos
+    MonetaryLoss { over_cost: f64, under_cost: f64 },
+}
+
+impl MetricConfig {
+    pub fn is_regression(&self) -> bool {
+        matches!(
+            self,
+            MetricConfig::Rmse
+                | MetricConfig::Mae
+                | MetricConfig::Mape
+                | MetricConfig::MonetaryLoss { .. }
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetricsConfig {
+    pub primary: MetricConfig,
+    #[serde(default)]
+    pub secondary: Vec<MetricConfig>,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            primary: MetricConfig::Gain,
+            secondary: Vec::new(),
+        }
+    }
+}
+
+/// Remuestreo de filas del dataset maestro para intervalos de confianza y
+/// comparaciones entre envíos. `iterations: 0` desactiva los intervalos.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BootstrapConfig {
+    #[serde(default = "default_bootstrap_iterations")]
+    pub iterations: usize,
+    #[serde(default = "default_bootstrap_confidence")]
+    pub confidence: f64,
+    /// Semilla fija para que los intervalos sean reproducibles
+    #[serde(default)]
+    pub seed: u64,
+}
+
+fn default_bootstrap_iterations() -> usize {
+    1000
+}
+
+fn default_bootstrap_confidence() -> f64 {
+    0.95
+}
+
+impl Default for BootstrapConfig {
+    fn default() -> Self {
+        Self {
+            iterations: default_bootstrap_iterations(),
+            confidence: default_bootstrap_confidence(),
+            seed: 0,
+        }
+    }
+}
+
+/// Categoría de feedback. Cada umbral usa exactamente uno de `min_gain`,
+/// `min_pct_of_oracle`, `min_percentile` o `max_rank`, y todos los umbrales
+/// de la configuración usan el mismo.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GainThreshold {
-    pub min_gain: f64,
+    /// Puntaje mínimo de la métrica principal (máximo si menos es mejor)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_gain: Option<f64>,
+    /// Porcentaje mínimo del puntaje del oráculo
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_pct_of_oracle: Option<f64>,
+    /// Percentil mínimo (0-100) entre los envíos actuales
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_percentile: Option<f64>,
+    /// Posición máxima entre los envíos actuales (1 = el mejor)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_rank: Option<usize>,
     pub category: String,
     pub message: String,
     #[serde(default)]
     pub gifs: Vec<String>,
 }
 
+/// Criterio de un [`GainThreshold`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ThresholdKind {
+    Gain(f64),
+    PctOfOracle(f64),
+    Percentile(f64),
+    Rank(usize),
+}
+
+impl ThresholdKind {
+    fn name(&self) -> &'static str {
+        match self {
+            ThresholdKind::Gain(_) => "min_gain",
+            ThresholdKind::PctOfOracle(_) => "min_pct_of_oracle",
+            ThresholdKind::Percentile(_) => "min_percentile",
+            ThresholdKind::Rank(_) => "max_rank",
+        }
+    }
+}
+
+impl GainThreshold {
+    /// The criterion of the threshold, or `None` unless exactly one is set.
+    pub fn kind(&self) -> Option<ThresholdKind> {
+        let kinds: Vec<ThresholdKind> = [
+            self.min_gain.map(ThresholdKind::Gain),
+            self.min_pct_of_oracle.map(ThresholdKind::PctOfOracle),
+            self.min_percentile.map(ThresholdKind::Percentile),
+            self.max_rank.map(ThresholdKind::Rank),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        match kinds[..] {
+            [kind] => Some(kind),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CompetitionConfig {
     pub name: String,
     pub description: String,
     pub deadline: String,
     pub results_reveal_date: String,
+    /// Zona horaria IANA de la competencia, p. ej. `America/Argentina/Buenos_Aires`
+    #[serde(default = "default_timezone")]
+    pub timezone: String,
+    /// Envíos que cada estudiante puede elegir con `select` para el ranking final, por track
+    #[serde(default = "default_max_selected")]
+    pub max_selected: usize,
+    #[serde(default)]
+    pub task: TaskType,
+    /// Tracks adicionales al principal (`main`), que usa `master_data`,
+    /// `gain_matrix` y `gain_thresholds` de la raíz
+    #[serde(default)]
+    pub tracks: Vec<TrackConfig>,
+}
+
+fn default_timezone() -> String {
+    "UTC".to_string()
+}
+
+fn default_max_selected() -> usize {
+    2
+}
+
+impl CompetitionConfig {
+    pub fn timezone(&self) -> Result<Tz> {
+        self.timezone
+            .parse()
+            .map_err(|e| anyhow::anyhow!("Invalid competition timezone '{}': {}", self.timezone, e))
+    }
+}
+
+/// Nombre del track definido en la raíz de la configuración.
+pub const MAIN_TRACK: &str = "main";
+
+/// Track con su propio dataset maestro, matriz de ganancias, umbrales y
+/// leaderboard. Tipo de tarea y métricas se heredan de la raíz si se omiten.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrackConfig {
+    pub name: String,
+    pub master_data: MasterDataConfig,
+    pub gain_matrix: GainMatrix,
+    pub gain_thresholds: Vec<GainThreshold>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub task: Option<TaskType>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metrics: Option<MetricsConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub class_gain_matrix: Option<ClassGainMatrix>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskType {
+    /// `id,clase_binaria`; envíos con IDs positivos o `id,probability`
+    #[default]
+    Binary,
+    /// `id,clase`; envíos `id,clase` puntuados con `class_gain_matrix`
+    Multiclass,
+    /// `id,target` numérico; envíos `id,value` con una predicción por cada ID
+    Regression,
 }
 
 impl BotConfig {
@@ -75,8 +488,187 @@ impl BotConfig {
         let config: BotConfig =
             serde_json::from_str(&content).with_context(|| "Failed to parse config file")?;
 
+        config.validate()?;
+
         Ok(config)
     }
+
+    pub fn validate(&self) -> Result<()> {
+        self.validate_scoring()?;
+
+        let validation = &self.submissions.validation;
+        for pct in [validation.min_positive_pct, validation.max_positive_pct].into_iter().flatten() {
+            if !(0.0..=100.0).contains(&pct) {
+                anyhow::bail!("Positive share limits must be between 0 and 100, got {}", pct);
+            }
+        }
+        if let (Some(min), Some(max)) = (validation.min_positive_pct, validation.max_positive_pct) {
+            if min > max {
+                anyhow::bail!("min_positive_pct ({}) is above max_positive_pct ({})", min, max);
+            }
+        }
+
+        self.competition.timezone()?;
+        if self.competition.max_selected == 0 {
+            anyhow::bail!("competition.max_selected must be at least 1");
+        }
+        self.submissions.quota.reset_time()?;
+
+        let mut names = std::collections::HashSet::from([MAIN_TRACK]);
+        for track in &self.competition.tracks {
+            if track.name.is_empty() || track.name.contains(char::is_whitespace) {
+                anyhow::bail!("Track names must be a single word, got '{}'", track.name);
+            }
+            if !names.insert(track.name.as_str()) {
+                anyhow::bail!("Duplicated track '{}'", track.name);
+            }
+            self.for_track(&track.name)
+                .context("Unknown track")?
+                .validate_scoring()
+                .with_context(|| format!("Invalid track '{}'", track.name))?;
+        }
+
+        Ok(())
+    }
+
+    /// Nombres de todos los tracks, empezando por el principal.
+    pub fn track_names(&self) -> Vec<&str> {
+        std::iter::once(MAIN_TRACK)
+            .chain(self.competition.tracks.iter().map(|t| t.name.as_str()))
+            .collect()
+    }
+
+    /// Configuración efectiva de un track: la de la raíz con el dataset,
+    /// la matriz, los umbrales y, si los define, la tarea y las métricas del track.
+    pub fn for_track(&self, name: &str) -> Option<BotConfig> {
+        if name == MAIN_TRACK {
+            return Some(self.clone());
+        }
+        let track = self.competition.tracks.iter().find(|t| t.name == name)?;
+
+        let mut config = self.clone();
+        config.master_data = track.master_data.clone();
+        config.gain_matrix = track.gain_matrix.clone();
+        config.gain_thresholds = track.gain_thresholds.clone();
+        if let Some(task) = &track.task {
+            config.competition.task = task.clone();
+        }
+        if let Some(metrics) = &track.metrics {
+            config.metrics = metrics.clone();
+        }
+        if track.class_gain_matrix.is_some() {
+            config.class_gain_matrix = track.class_gain_matrix.clone();
+        }
+        Some(config)
+    }
+
+    /// Checks of the settings a track can override.
+    fn validate_scoring(&self) -> Result<()> {
+        if self.competition.task == TaskType::Multiclass {
+            let matrix = self
+                .class_gain_matrix
+                .as_ref()
+                .context("Multiclass competitions require a class_gain_matrix")?;
+
+            let n = matrix.classes.len();
+            if n < 2 {
+                anyhow::bail!("class_gain_matrix needs at least 2 classes");
+            }
+            let unique: std::collections::HashSet<_> = matrix.classes.iter().collect();
+            if unique.len() != n {
+                anyhow::bail!("class_gain_matrix has duplicated classes");
+            }
+            if matrix.gains.len() != n || matrix.gains.iter().any(|row| row.len() != n) {
+                anyhow::bail!("class_gain_matrix.gains must be a {}x{} matrix", n, n);
+            }
+            if let Some(default) = &matrix.default_class {
+                if matrix.class_index(default).is_none() {
+                    anyhow::bail!("class_gain_matrix.default_class '{}' is not a class", default);
+                }
+            }
+        }
+
+        let regression = self.competition.task == TaskType::Regression;
+        for metric in std::iter::once(&self.metrics.primary).chain(&self.metrics.secondary) {
+            if metric.is_regression() != regression {
+                anyhow::bail!(
+                    "Metric {:?} cannot be used in a {:?} competition",
+                    metric,
+                    self.competition.task
+                );
+            }
+        }
+
+        if !(self.bootstrap.confidence > 0.0 && self.bootstrap.confidence < 1.0) {
+            anyhow::bail!("bootstrap.confidence must be between 0 and 1");
+        }
+
+        self.validate_thresholds()?;
+
+        Ok(())
+    }
+
+    fn validate_thresholds(&self) -> Result<()> {
+        if self.gain_thresholds.is_empty() {
+            anyhow::bail!("gain_thresholds needs at least one threshold");
+        }
+
+        let mut first_kind: Option<ThresholdKind> = None;
+        let mut categories = std::collections::HashSet::new();
+        for threshold in &self.gain_thresholds {
+            let kind = threshold.kind().with_context(|| {
+                format!(
+                    "Threshold '{}' must set exactly one of min_gain, min_pct_of_oracle, \
+                     min_percentile or max_rank",
+                    threshold.category
+                )
+            })?;
+            if !categories.insert(&threshold.category) {
+                anyhow::bail!("Duplicated threshold category '{}'", threshold.category);
+            }
+
+            match kind {
+                ThresholdKind::Gain(v) | ThresholdKind::PctOfOracle(v) if !v.is_finite() => {
+                    anyhow::bail!("Threshold '{}' has a non-finite value", threshold.category)
+                }
+                ThresholdKind::Percentile(v) if !(0.0..=100.0).contains(&v) => {
+                    anyhow::bail!(
+                        "Threshold '{}': min_percentile must be between 0 and 100",
+                        threshold.category
+                    )
+                }
+                ThresholdKind::Rank(0) => {
+                    anyhow::bail!("Threshold '{}': max_rank starts at 1", threshold.category)
+                }
+                _ => {}
+            }
+
+            match first_kind {
+                Some(first) if first.name() != kind.name() => anyhow::bail!(
+                    "All gain_thresholds must use the same criterion ({} and {} are mixed)",
+                    first.name(),
+                    kind.name()
+                ),
+                _ => first_kind = Some(kind),
+            }
+        }
+
+        // The oracle is only defined for binary competitions scored with a higher-is-better metric
+        if let Some(ThresholdKind::PctOfOracle(_)) = first_kind {
+            let primary = crate::metrics::build(
+                &self.metrics.primary,
+                &self.gain_matrix,
+                self.class_gain_matrix.as_ref(),
+            );
+            if self.competition.task != TaskType::Binary || !primary.higher_is_better() {
+                anyhow::bail!(
+                    "min_pct_of_oracle thresholds need a binary competition with a higher-is-better primary metric"
+                );
+            }
+        }
+
+        Ok(())
+    }
 }
 
 pub fn create_config_template() -> Result<()> {
@@ -98,9 +690,25 @@ pub fn create_config_template() -> Result<()> {
         ],
         master_data: MasterDataConfig {
             path: "master_data.csv".to_string(),
+            id_type: IdType::Integer,
         },
         submissions: SubmissionsConfig {
             path: "./submissions".to_string(),
+            default_cutoff: default_cutoff(),
+            max_decompressed_mb: default_max_decompressed_mb(),
+            max_download_mb: default_max_download_mb(),
+            download_timeout_secs: default_download_timeout_secs(),
+            max_rows: default_max_rows(),
+            validation: ValidationConfig {
+                min_positive_pct: Some(0.1),
+                max_positive_pct: Some(90.0),
+                ..Default::default()
+            },
+            quota: QuotaConfig {
+                daily: Some(5),
+                total: None,
+                reset_time: default_reset_time(),
+            },
         },
         gain_matrix: GainMatrix {
             tp: 1.0,
@@ -110,7 +718,10 @@ pub fn create_config_template() -> Result<()> {
         },
         gain_thresholds: vec![
             GainThreshold {
-                min_gain: 100.0,
+                min_gain: Some(100.0),
+                min_pct_of_oracle: None,
+                min_percentile: None,
+                max_rank: None,
                 category: "excellent".to_string(),
                 message: "¡Modelo excepcional!".to_string(),
                 gifs: vec![
@@ -119,7 +730,10 @@ pub fn create_config_template() -> Result<()> {
                 ],
             },
             GainThreshold {
-                min_gain: 50.0,
+                min_gain: Some(50.0),
+                min_pct_of_oracle: None,
+                min_percentile: None,
+                max_rank: None,
                 category: "good".to_string(),
                 message: "Buen trabajo".to_string(),
                 gifs: vec![
@@ -127,7 +741,10 @@ pub fn create_config_template() -> Result<()> {
                 ],
             },
             GainThreshold {
-                min_gain: 0.0,
+                min_gain: Some(0.0),
+                min_pct_of_oracle: None,
+                min_percentile: None,
+                max_rank: None,
                 category: "basic".to_string(),
                 message: "Sigue intentando".to_string(),
                 gifs: vec![
@@ -140,7 +757,17 @@ pub fn create_config_template() -> Result<()> {
             description: "Competencia de machine learning usando DosEsfingesBot".to_string(),
             deadline: "2025-12-31T23:59:59".to_string(),
             results_reveal_date: "2026-01-01T23:59:59".to_string(),
+            timezone: "America/Argentina/Buenos_Aires".to_string(),
+            max_selected: default_max_selected(),
+            task: TaskType::Binary,
+            tracks: Vec::new(),
+        },
+        metrics: MetricsConfig {
+            primary: MetricConfig::Gain,
+            secondary: vec![MetricConfig::F1, MetricConfig::Accuracy],
         },
+        class_gain_matrix: None,
+        bootstrap: BootstrapConfig::default(),
     };
 
     let json = serde_json::to_string_pretty(&config)?;
@@ -0,0 +1,313 @@
+//! Tolerant reading of the CSV files students upload: UTF-8 BOM, CRLF line
+//! endings, `,`/`;`/tab/`|` delimiters, quotes, padding, blank lines and
+//! trailing empty columns are all accepted. Anything else becomes a
+//! [`CsvError`] pointing at the offending line.
+
+use csv::{ReaderBuilder, Trim};
+use thiserror::Error;
+
+use crate::config::IdType;
+use crate::models::Id;
+
+/// Delimiters tried in order; `,` goes last so `1;0,5` is read as a decimal comma.
+const DELIMITERS: [u8; 4] = [b';', b'\t', b'|', b','];
+
+/// Lines looked at to detect the delimiter.
+const SNIFF_LINES: usize = 20;
+
+/// A problem in an uploaded CSV, worded for the student who has to fix it.
+#[derive(Error, Debug, Clone, PartialEq)]
+#[error("línea {line}: {problem} `{value}`")]
+pub struct CsvError {
+    /// 1-based line number in the file
+    pub line: usize,
+    pub value: String,
+    pub problem: String,
+    pub hint: String,
+}
+
+impl CsvError {
+    pub fn new(line: usize, value: impl Into<String>, problem: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self {
+            line,
+            value: value.into(),
+            problem: problem.into(),
+            hint: hint.into(),
+        }
+    }
+
+    /// Markdown report for the bot reply.
+    pub fn report(&self) -> String {
+        format!(
+            "❌ **Error en el CSV (línea {})**\n\
+            • **Problema:** {}\n\
+            • **Valor:** `{}`\n\
+            • 💡 **Sugerencia:** {}",
+            self.line, self.problem, self.value, self.hint
+        )
+    }
+}
+
+/// A non-blank row with its trimmed fields.
+#[derive(Debug, Clone)]
+pub struct Row {
+    pub line: usize,
+    pub fields: Vec<String>,
+}
+
+impl Row {
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    /// The row as the student wrote it, for error messages.
+    pub fn raw(&self) -> String {
+        self.fields.join(",")
+    }
+}
+
+/// Splits `content` into rows, detecting the delimiter. Rows are parsed one
+/// at a time as the iterator advances; blank lines are skipped and trailing
+/// empty fields (`123,`) dropped.
+pub fn read_rows(content: &[u8]) -> Result<Rows<'_>, CsvError> {
+    let content = content.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(content);
+    let text = std::str::from_utf8(content).map_err(|e| {
+        let line = content[..e.valid_up_to()].iter().filter(|b| **b == b'\n').count() + 1;
+        CsvError::new(
+            line,
+            String::from_utf8_lossy(&content[e.valid_up_to()..][..e.error_len().unwrap_or(1)]),
+            "el archivo no está en UTF-8",
+            "Guarda el archivo como \"CSV UTF-8\"",
+        )
+    })?;
+
+    let reader = ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .trim(Trim::All)
+        .delimiter(detect_delimiter(text))
+        .from_reader(text.as_bytes());
+
+    Ok(Rows {
+        records: reader.into_records(),
+        lines: LineCounter {
+            text,
+            counted_to: 0,
+            line: 1,
+        },
+        peeked: None,
+        max_rows: None,
+        returned: 0,
+    })
+}
+
+/// The reader does not count the blank lines it skips, so lines are counted
+/// from the byte offsets of the records instead.
+struct LineCounter<'a> {
+    text: &'a str,
+    /// Newlines before `counted_to` are already in `line`
+    counted_to: usize,
+    line: usize,
+}
+
+impl LineCounter<'_> {
+    /// Line of the record starting at `byte`; offsets only move forward.
+    fn line_at(&mut self, byte: usize) -> usize {
+        let byte = byte.min(self.text.len());
+        let skipped = self.text[byte..].bytes().take_while(|b| *b == b'\r' || *b == b'\n');
+        let blank = skipped.filter(|b| *b == b'\n').count();
+        self.line += self.text.as_bytes()[self.counted_to..byte]
+            .iter()
+            .filter(|b| **b == b'\n')
+            .count();
+        self.counted_to = byte;
+        self.line + blank
+    }
+}
+
+/// Rows of a CSV, see [`read_rows`].
+pub struct Rows<'a> {
+    records: csv::StringRecordsIntoIter<&'a [u8]>,
+    lines: LineCounter<'a>,
+    peeked: Option<Result<Row, CsvError>>,
+    /// See [`Rows::limit`]
+    max_rows: Option<usize>,
+    /// Rows handed out by the iterator, a skipped header not included
+    returned: usize,
+}
+
+impl Rows<'_> {
+    fn read(&mut self) -> Option<Result<Row, CsvError>> {
+        for result in self.records.by_ref() {
+            let record = match result {
+                Ok(record) => record,
+                Err(e) => {
+                    let byte = e.position().map_or(usize::MAX, |p| p.byte() as usize);
+                    let line = self.lines.line_at(byte);
+                    return Some(Err(CsvError::new(
+                        line,
+                        e.to_string(),
+                        "la línea no es CSV válido",
+                        "Revisa que las comillas estén cerradas",
+                    )));
+                }
+            };
+            let byte = record.position().map_or(usize::MAX, |p| p.byte() as usize);
+            let line = self.lines.line_at(byte);
+
+            let mut fields: Vec<String> = record.iter().map(|f| f.to_string()).collect();
+            while fields.last().is_some_and(|f| f.is_empty()) {
+                fields.pop();
+            }
+            if !fields.is_empty() {
+                return Some(Ok(Row { line, fields }));
+            }
+        }
+        None
+    }
+
+    /// Fails on the row after the first `max_rows`, so an oversized file is
+    /// rejected as soon as it goes over. A skipped header does not count.
+    pub fn limit(mut self, max_rows: usize) -> Self {
+        self.max_rows = Some(max_rows);
+        self
+    }
+
+    /// Next row without consuming it.
+    pub fn peek(&mut self) -> Result<Option<&Row>, CsvError> {
+        if self.peeked.is_none() {
+            self.peeked = self.read();
+        }
+        match &self.peeked {
+            Some(Ok(row)) => Ok(Some(row)),
+            Some(Err(e)) => Err(e.clone()),
+            None => Ok(None),
+        }
+    }
+
+    /// Drops the first row when it is a header: an ID column (see
+    /// [`is_id_header`]) followed by one of the names in `value_columns` per
+    /// column, case-insensitive. A first row that is neither that header nor
+    /// data per `is_data` is reported instead of dropped.
+    pub fn skip_header(&mut self, value_columns: &[&[&str]], is_data: impl Fn(&Row) -> bool) -> Result<(), CsvError> {
+        let Some(first) = self.peek()? else {
+            return Ok(());
+        };
+        let is_header = first.len() == value_columns.len() + 1
+            && is_id_header(&first.fields[0])
+            && first.fields[1..]
+                .iter()
+                .zip(value_columns)
+                .all(|(field, names)| names.iter().any(|name| field.eq_ignore_ascii_case(name)));
+        if is_header {
+            self.peeked = None;
+        } else if !is_data(first) {
+            let expected: Vec<&str> = std::iter::once("id").chain(value_columns.iter().map(|names| names[0])).collect();
+            return Err(CsvError::new(
+                first.line,
+                first.raw(),
+                "la primera fila no es un encabezado válido",
+                format!("Usa el encabezado `{}` o borra la primera fila", expected.join(",")),
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl Iterator for Rows<'_> {
+    type Item = Result<Row, CsvError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let row = self.peeked.take().or_else(|| self.read())?;
+        if let (Ok(row), Some(max_rows)) = (&row, self.max_rows) {
+            self.returned += 1;
+            if self.returned > max_rows {
+                return Some(Err(CsvError::new(
+                    row.line,
+                    row.raw(),
+                    format!("el archivo supera el máximo de {} filas", max_rows),
+                    "Envía una fila por ID del dataset, sin filas repetidas ni extra",
+                )));
+            }
+        }
+        Some(row)
+    }
+}
+
+/// First delimiter found the same number of times on every sampled line.
+fn detect_delimiter(text: &str) -> u8 {
+    let sample: Vec<&str> = text
+        .lines()
+        .map(|line| line.trim_end_matches('\r'))
+        .filter(|line| !line.trim().is_empty())
+        .take(SNIFF_LINES)
+        .collect();
+
+    DELIMITERS
+        .into_iter()
+        .find(|delimiter| {
+            let counts: Vec<usize> = sample
+                .iter()
+                .map(|line| {
+                    // A trailing delimiter is an empty column, not a separator
+                    let line = line.trim_end().trim_end_matches(*delimiter as char);
+                    line.bytes().filter(|b| b == delimiter).count()
+                })
+                .collect();
+            counts.first().is_some_and(|first| *first > 0) && counts.iter().all(|c| c == &counts[0])
+        })
+        .unwrap_or(b',')
+}
+
+/// Column names that are never IDs, even when IDs are free text.
+pub fn is_id_header(raw: &str) -> bool {
+    let raw = raw.to_lowercase();
+    !raw.chars().any(|c| c.is_ascii_digit())
+        && (raw == "id" || raw == "ids" || raw.starts_with("id_") || raw.ends_with("_id"))
+}
+
+pub fn parse_id(row: &Row, column: usize, id_type: IdType) -> Result<Id, CsvError> {
+    let raw = &row.fields[column];
+    Id::parse(raw, id_type).ok_or_else(|| {
+        let hint = match raw.parse::<f64>() {
+            _ if raw.is_empty() => "Completa el ID o borra la línea".to_string(),
+            Ok(v) if id_type == IdType::Integer && v.fract() == 0.0 => {
+                format!("Escribe el ID sin decimales: `{}`", v as i64)
+            }
+            _ => "Los IDs son números enteros, sin letras ni separadores de miles".to_string(),
+        };
+        CsvError::new(row.line, raw.as_str(), "ID inválido", hint)
+    })
+}
+
+/// Number in `column`, accepting a decimal comma (`0,75`).
+pub fn parse_number(row: &Row, column: usize, what: &str) -> Result<f64, CsvError> {
+    let raw = row.fields.get(column).map(String::as_str).unwrap_or("");
+    raw.parse()
+        .ok()
+        .or_else(|| raw.replacen(',', ".", 1).parse().ok())
+        .filter(|v: &f64| v.is_finite())
+        .ok_or_else(|| {
+            CsvError::new(
+                row.line,
+                raw,
+                format!("{} inválida", what),
+                "Usa un número con punto decimal, p. ej. `0.75`",
+            )
+        })
+}
+
+/// Error for a row with the wrong number of columns; `expected` is the
+/// format in markdown, e.g. ``"`id,probability`"``.
+pub fn wrong_columns(row: &Row, expected: &str) -> CsvError {
+    CsvError::new(
+        row.line,
+        row.raw(),
+        format!("la línea tiene {} columnas", row.len()),
+        format!("Usa el formato {}, con una fila por ID", expected),
+    )
+}
@@ -1,7 +1,56 @@
-use crate::models::Submission;
-use anyhow::Result;
-use rusqlite::{params, Connection};
+use crate::bootstrap::Interval;
+use crate::config::MAIN_TRACK;
+use crate::master_data::Split;
+use crate::metrics::MetricValue;
+use crate::models::{GainResult, Submission, Withdrawal};
+use anyhow::{Context, Result};
+use rusqlite::{params, Connection, Row};
 
+const SUBMISSION_COLUMNS: &str = "id, user_id, user_email, user_full_name, submission_name,
+                    timestamp, file_checksum, file_path, expected_gain, actual_gain,
+                    tp, tn, fp, fn, positives_predicted, threshold_category, after_deadline,
+                    public_gain, public_tp, public_tn, public_fp, public_fn,
+                    private_gain, private_tp, private_tn, private_fp, private_fn,
+                    cutoff, best_gain, best_cutoff, confusion_matrix, track,
+                    withdrawn_at, withdraw_reason, description";
+
+#[derive(Debug, Clone)]
+pub struct LeaderboardEntry {
+    pub submission_id: i64,
+    pub user_full_name: String,
+    pub user_email: String,
+    pub timestamp: String,
+    pub final_gain: f64,
+    pub final_expected_gain: f64,
+    pub total_submissions: i32,
+    pub max_gain: Option<f64>,
+    /// Bootstrap interval of `final_gain`, when one was computed
+    pub interval: Option<Interval>,
+    /// Whether the ranked submission was nominated by the student with `select`
+    pub selected: bool,
+}
+
+/// A `submit` refused before scoring, e.g. because the student ran out of quota.
+#[derive(Debug, Clone)]
+pub struct RejectedAttempt {
+    pub user_id: i64,
+    pub user_email: String,
+    pub user_full_name: String,
+    pub submission_name: String,
+    pub timestamp: String,
+    pub track: String,
+    pub reason: String,
+}
+
+/// A stored submission file, named by the SHA-256 of its content.
+#[derive(Debug, Clone)]
+pub struct Blob {
+    pub checksum: String,
+    pub size: u64,
+    pub stored_at: String,
+}
+
+#[derive(Clone)]
 pub struct Database {
     path: String,
 }
@@ -43,6 +92,149 @@ impl Database {
             [],
         )?;
 
+        // Public/private split columns, added to databases created before the split existed
+        for split in [Split::Public, Split::Private] {
+            let prefix = split.as_str();
+            add_column_if_missing(&conn, "submissions", &format!("{}_gain", prefix), "REAL")?;
+            for cell in ["tp", "tn", "fp", "fn"] {
+                add_column_if_missing(
+                    &conn,
+                    "submissions",
+                    &format!("{}_{}", prefix, cell),
+                    "INTEGER",
+                )?;
+            }
+            // Old rows were scored against the whole dataset, which is both splits
+            conn.execute(
+                &format!(
+                    "UPDATE submissions
+                     SET {p}_gain = actual_gain, {p}_tp = tp, {p}_tn = tn, {p}_fp = fp, {p}_fn = fn
+                     WHERE {p}_gain IS NULL",
+                    p = prefix
+                ),
+                [],
+            )?;
+        }
+
+        // Probability submissions
+        add_column_if_missing(&conn, "submissions", "cutoff", "REAL")?;
+        add_column_if_missing(&conn, "submissions", "best_gain", "REAL")?;
+        add_column_if_missing(&conn, "submissions", "best_cutoff", "REAL")?;
+
+        // Multiclass confusion matrix, stored as JSON
+        add_column_if_missing(&conn, "submissions", "confusion_matrix", "TEXT")?;
+
+        // Competition track; submissions from before tracks existed belong to the main one
+        add_column_if_missing(
+            &conn,
+            "submissions",
+            "track",
+            &format!("TEXT NOT NULL DEFAULT '{}'", MAIN_TRACK),
+        )?;
+
+        // Soft delete by the author; withdrawn submissions stay for teachers
+        add_column_if_missing(&conn, "submissions", "withdrawn_at", "TEXT")?;
+        add_column_if_missing(&conn, "submissions", "withdraw_reason", "TEXT")?;
+
+        // Free-text description given by the student
+        add_column_if_missing(&conn, "submissions", "description", "TEXT")?;
+
+        // One row per metric and scope (`all`, `public`, `private`) of each submission
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS submission_metrics (
+                submission_id INTEGER NOT NULL,
+                metric TEXT NOT NULL,
+                scope TEXT NOT NULL,
+                value REAL,
+                PRIMARY KEY (submission_id, metric, scope)
+            )",
+            [],
+        )?;
+
+        // Bootstrap confidence interval of the primary metric, per scope
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS submission_intervals (
+                submission_id INTEGER NOT NULL,
+                scope TEXT NOT NULL,
+                low REAL NOT NULL,
+                high REAL NOT NULL,
+                PRIMARY KEY (submission_id, scope)
+            )",
+            [],
+        )?;
+
+        // Scores replaced by `rescore`, one row per submission and rescore
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS score_history (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                submission_id INTEGER NOT NULL,
+                replaced_at TEXT NOT NULL,
+                actual_gain REAL,
+                tp INTEGER,
+                tn INTEGER,
+                fp INTEGER,
+                fn INTEGER,
+                positives_predicted INTEGER,
+                threshold_category TEXT,
+                public_gain REAL,
+                private_gain REAL,
+                best_gain REAL,
+                confusion_matrix TEXT
+            )",
+            [],
+        )?;
+
+        // Extra submissions granted by teachers, on top of the quota of `day`
+        // (YYYY-MM-DD in the competition timezone) and of the total quota
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS quota_grants (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                user_id INTEGER,
+                user_full_name TEXT NOT NULL,
+                extra INTEGER NOT NULL,
+                day TEXT NOT NULL,
+                granted_by TEXT NOT NULL,
+                granted_at TEXT NOT NULL
+            )",
+            [],
+        )?;
+        // Grants follow the Zulip user, whatever name they go by
+        add_column_if_missing(&conn, "quota_grants", "user_id", "INTEGER")?;
+
+        // Submissions nominated by their authors for the final ranking
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS final_selections (
+                submission_id INTEGER PRIMARY KEY,
+                selected_at TEXT NOT NULL
+            )",
+            [],
+        )?;
+
+        // Stored submission files, keyed by the `file_checksum` of their submissions
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS blobs (
+                checksum TEXT PRIMARY KEY,
+                size INTEGER NOT NULL,
+                stored_at TEXT NOT NULL
+            )",
+            [],
+        )?;
+
+        // Submits refused without scoring them
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS rejected_attempts (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                user_id INTEGER,
+                user_email TEXT,
+                user_full_name TEXT,
+                submission_name TEXT,
+                timestamp TEXT,
+                track TEXT,
+                reason TEXT
+            )",
+            [],
+        )?;
+
         Ok(())
     }
 
@@ -53,8 +245,12 @@ impl Database {
             "INSERT INTO submissions (
                 user_id, user_email, user_full_name, submission_name,
                 timestamp, file_checksum, file_path, expected_gain, actual_gain,
-                tp, tn, fp, fn, positives_predicted, threshold_category, after_deadline
-            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16)",
+                tp, tn, fp, fn, positives_predicted, threshold_category, after_deadline,
+                public_gain, public_tp, public_tn, public_fp, public_fn,
+                private_gain, private_tp, private_tn, private_fp, private_fn,
+                cutoff, best_gain, best_cutoff, confusion_matrix, track, description
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16,
+                      ?17, ?18, ?19, ?20, ?21, ?22, ?23, ?24, ?25, ?26, ?27, ?28, ?29, ?30, ?31, ?32)",
             params![
                 submission.user_id,
                 submission.user_email,
@@ -72,48 +268,378 @@ impl Database {
                 submission.positives_predicted,
                 submission.threshold_category,
                 submission.after_deadline as i32,
+                submission.public.gain,
+                submission.public.tp,
+                submission.public.tn,
+                submission.public.fp,
+                submission.public.fn_,
+                submission.private.gain,
+                submission.private.tp,
+                submission.private.tn,
+                submission.private.fp,
+                submission.private.fn_,
+                submission.cutoff,
+                submission.best_gain,
+                submission.best_cutoff,
+                submission
+                    .confusion_matrix
+                    .as_ref()
+                    .map(serde_json::to_string)
+                    .transpose()?,
+                submission.track,
+                submission.description,
             ],
         )?;
 
         Ok(conn.last_insert_rowid())
     }
 
-    pub fn get_user_submissions(&self, user_name: &str) -> Result<Vec<Submission>> {
+    /// Replaces the scores of `old` with those of `new`, archiving the old
+    /// values in `score_history` within the same transaction.
+    pub fn update_submission_scores(&self, old: &Submission, new: &Submission, replaced_at: &str) -> Result<()> {
+        let id = old.id.context("Submission without id")?;
+        let mut conn = self.get_connection()?;
+        let tx = conn.transaction()?;
+
+        tx.execute(
+            "INSERT INTO score_history (
+                submission_id, replaced_at, actual_gain, tp, tn, fp, fn, positives_predicted,
+                threshold_category, public_gain, private_gain, best_gain, confusion_matrix
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+            params![
+                id,
+                replaced_at,
+                old.actual_gain,
+                old.tp,
+                old.tn,
+                old.fp,
+                old.fn_,
+                old.positives_predicted,
+                old.threshold_category,
+                old.public.gain,
+                old.private.gain,
+                old.best_gain,
+                old.confusion_matrix
+                    .as_ref()
+                    .map(serde_json::to_string)
+                    .transpose()?,
+            ],
+        )?;
+
+        tx.execute(
+            "UPDATE submissions SET
+                actual_gain = ?2, tp = ?3, tn = ?4, fp = ?5, fn = ?6,
+                positives_predicted = ?7, threshold_category = ?8,
+                public_gain = ?9, public_tp = ?10, public_tn = ?11, public_fp = ?12, public_fn = ?13,
+                private_gain = ?14, private_tp = ?15, private_tn = ?16, private_fp = ?17, private_fn = ?18,
+                cutoff = ?19, best_gain = ?20, best_cutoff = ?21, confusion_matrix = ?22
+             WHERE id = ?1",
+            params![
+                id,
+                new.actual_gain,
+                new.tp,
+                new.tn,
+                new.fp,
+                new.fn_,
+                new.positives_predicted,
+                new.threshold_category,
+                new.public.gain,
+                new.public.tp,
+                new.public.tn,
+                new.public.fp,
+                new.public.fn_,
+                new.private.gain,
+                new.private.tp,
+                new.private.tn,
+                new.private.fp,
+                new.private.fn_,
+                new.cutoff,
+                new.best_gain,
+                new.best_cutoff,
+                new.confusion_matrix
+                    .as_ref()
+                    .map(serde_json::to_string)
+                    .transpose()?,
+            ],
+        )?;
+
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Returns `(replaced_at, actual_gain)` for every archived score of a submission.
+    pub fn get_score_history(&self, submission_id: i64) -> Result<Vec<(String, f64)>> {
         let conn = self.get_connection()?;
         let mut stmt = conn.prepare(
-            "SELECT id, user_id, user_email, user_full_name, submission_name,
-                    timestamp, file_checksum, file_path, expected_gain, actual_gain,
-                    tp, tn, fp, fn, positives_predicted, threshold_category, after_deadline
+            "SELECT replaced_at, actual_gain
+             FROM score_history
+             WHERE submission_id = ?1
+             ORDER BY id",
+        )?;
+
+        let history = stmt
+            .query_map([submission_id], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(history)
+    }
+
+    pub fn save_submission_metrics(
+        &self,
+        submission_id: i64,
+        scope: &str,
+        values: &[MetricValue],
+    ) -> Result<()> {
+        let conn = self.get_connection()?;
+
+        for metric in values {
+            conn.execute(
+                "INSERT OR REPLACE INTO submission_metrics (submission_id, metric, scope, value)
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![submission_id, metric.name, scope, metric.value],
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns `(metric, scope, value)` rows for a submission.
+    pub fn get_submission_metrics(&self, submission_id: i64) -> Result<Vec<(String, String, Option<f64>)>> {
+        let conn = self.get_connection()?;
+        let mut stmt = conn.prepare(
+            "SELECT metric, scope, value
+             FROM submission_metrics
+             WHERE submission_id = ?1
+             ORDER BY rowid",
+        )?;
+
+        let metrics = stmt
+            .query_map([submission_id], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, Option<f64>>(2)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(metrics)
+    }
+
+    pub fn save_submission_interval(&self, submission_id: i64, scope: &str, interval: &Interval) -> Result<()> {
+        let conn = self.get_connection()?;
+        conn.execute(
+            "INSERT OR REPLACE INTO submission_intervals (submission_id, scope, low, high)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![submission_id, scope, interval.low, interval.high],
+        )?;
+
+        Ok(())
+    }
+
+    /// Returns `(scope, interval)` rows for a submission.
+    pub fn get_submission_intervals(&self, submission_id: i64) -> Result<Vec<(String, Interval)>> {
+        let conn = self.get_connection()?;
+        let mut stmt = conn.prepare(
+            "SELECT scope, low, high
+             FROM submission_intervals
+             WHERE submission_id = ?1
+             ORDER BY rowid",
+        )?;
+
+        let intervals = stmt
+            .query_map([submission_id], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    Interval {
+                        low: row.get(1)?,
+                        high: row.get(2)?,
+                    },
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(intervals)
+    }
+
+    pub fn get_user_submissions(&self, user_name: &str) -> Result<Vec<Submission>> {
+        let conn = self.get_connection()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {}
              FROM submissions
+             WHERE user_full_name = ?1 AND withdrawn_at IS NULL
+             ORDER BY timestamp DESC",
+            SUBMISSION_COLUMNS
+        ))?;
+
+        let submissions = stmt
+            .query_map([user_name], submission_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(submissions)
+    }
+
+    /// `(id, name)` of every submission of the Zulip user `user_id` not withdrawn.
+    pub fn get_submission_names(&self, user_id: i64) -> Result<Vec<(i64, String)>> {
+        let conn = self.get_connection()?;
+        let mut stmt = conn.prepare(
+            "SELECT id, submission_name FROM submissions WHERE user_id = ?1 AND withdrawn_at IS NULL",
+        )?;
+
+        let names = stmt
+            .query_map([user_id], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(names)
+    }
+
+    /// Timestamps of every submission of a user not withdrawn, to count them against the quotas.
+    pub fn get_submission_timestamps(&self, user_id: i64) -> Result<Vec<String>> {
+        let conn = self.get_connection()?;
+        let mut stmt = conn.prepare(
+            "SELECT timestamp FROM submissions WHERE user_id = ?1 AND withdrawn_at IS NULL",
+        )?;
+
+        let timestamps = stmt
+            .query_map([user_id], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(timestamps)
+    }
+
+    pub fn save_quota_grant(
+        &self,
+        user_id: i64,
+        user_name: &str,
+        extra: u32,
+        day: &str,
+        granted_by: &str,
+        granted_at: &str,
+    ) -> Result<()> {
+        let conn = self.get_connection()?;
+        conn.execute(
+            "INSERT INTO quota_grants (user_id, user_full_name, extra, day, granted_by, granted_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![user_id, user_name, extra, day, granted_by, granted_at],
+        )?;
+
+        Ok(())
+    }
+
+    /// Returns `(day, extra)` for every grant to a user.
+    pub fn get_quota_grants(&self, user_id: i64) -> Result<Vec<(String, u32)>> {
+        let conn = self.get_connection()?;
+        let mut stmt = conn.prepare(
+            "SELECT day, extra FROM quota_grants WHERE user_id = ?1 ORDER BY id",
+        )?;
+
+        let grants = stmt
+            .query_map([user_id], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(grants)
+    }
+
+    pub fn save_rejected_attempt(&self, attempt: &RejectedAttempt) -> Result<i64> {
+        let conn = self.get_connection()?;
+        conn.execute(
+            "INSERT INTO rejected_attempts (
+                user_id, user_email, user_full_name, submission_name, timestamp, track, reason
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                attempt.user_id,
+                attempt.user_email,
+                attempt.user_full_name,
+                attempt.submission_name,
+                attempt.timestamp,
+                attempt.track,
+                attempt.reason,
+            ],
+        )?;
+
+        Ok(conn.last_insert_rowid())
+    }
+
+    pub fn get_rejected_attempts(&self, user_name: &str) -> Result<Vec<RejectedAttempt>> {
+        let conn = self.get_connection()?;
+        let mut stmt = conn.prepare(
+            "SELECT user_id, user_email, user_full_name, submission_name, timestamp, track, reason
+             FROM rejected_attempts
              WHERE user_full_name = ?1
              ORDER BY timestamp DESC",
         )?;
 
-        let submissions = stmt
+        let attempts = stmt
             .query_map([user_name], |row| {
-                Ok(Submission {
-                    id: Some(row.get(0)?),
-                    user_id: row.get(1)?,
-                    user_email: row.get(2)?,
-                    user_full_name: row.get(3)?,
-                    submission_name: row.get(4)?,
-                    timestamp: row.get(5)?,
-                    file_checksum: row.get(6)?,
-                    file_path: row.get(7)?,
-                    expected_gain: row.get(8)?,
-                    actual_gain: row.get(9)?,
-                    tp: row.get(10)?,
-                    tn: row.get(11)?,
-                    fp: row.get(12)?,
-                    fn_: row.get(13)?,
-                    positives_predicted: row.get(14)?,
-                    threshold_category: row.get(15)?,
-                    after_deadline: row.get::<_, i32>(16)? != 0,
+                Ok(RejectedAttempt {
+                    user_id: row.get(0)?,
+                    user_email: row.get(1)?,
+                    user_full_name: row.get(2)?,
+                    submission_name: row.get(3)?,
+                    timestamp: row.get(4)?,
+                    track: row.get(5)?,
+                    reason: row.get(6)?,
                 })
             })?
             .collect::<Result<Vec<_>, _>>()?;
 
-        Ok(submissions)
+        Ok(attempts)
+    }
+
+    /// Records `blob`; a checksum already stored keeps its first record.
+    pub fn save_blob(&self, blob: &Blob) -> Result<()> {
+        let conn = self.get_connection()?;
+        conn.execute(
+            "INSERT OR IGNORE INTO blobs (checksum, size, stored_at) VALUES (?1, ?2, ?3)",
+            params![blob.checksum, blob.size as i64, blob.stored_at],
+        )?;
+
+        Ok(())
+    }
+
+    pub fn get_blobs(&self) -> Result<Vec<Blob>> {
+        let conn = self.get_connection()?;
+        let mut stmt = conn.prepare("SELECT checksum, size, stored_at FROM blobs ORDER BY stored_at")?;
+
+        let blobs = stmt
+            .query_map([], |row| {
+                Ok(Blob {
+                    checksum: row.get(0)?,
+                    size: row.get::<_, i64>(1)? as u64,
+                    stored_at: row.get(2)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(blobs)
+    }
+
+    pub fn rename_submission(&self, submission_id: i64, name: &str) -> Result<()> {
+        let conn = self.get_connection()?;
+        conn.execute(
+            "UPDATE submissions SET submission_name = ?2 WHERE id = ?1",
+            params![submission_id, name],
+        )?;
+
+        Ok(())
+    }
+
+    /// Marks a submission as withdrawn by its author and drops it from the final selection.
+    pub fn withdraw_submission(&self, submission_id: i64, withdrawal: &Withdrawal) -> Result<()> {
+        let mut conn = self.get_connection()?;
+        let tx = conn.transaction()?;
+
+        tx.execute(
+            "UPDATE submissions SET withdrawn_at = ?2, withdraw_reason = ?3 WHERE id = ?1",
+            params![submission_id, withdrawal.at, withdrawal.reason],
+        )?;
+        tx.execute(
+            "DELETE FROM final_selections WHERE submission_id = ?1",
+            [submission_id],
+        )?;
+
+        tx.commit()?;
+        Ok(())
     }
 
     pub fn get_duplicates(&self) -> Result<Vec<(String, i32, String, String)>> {
@@ -123,6 +649,7 @@ impl Database {
                     GROUP_CONCAT(DISTINCT user_full_name) as users,
                     GROUP_CONCAT(submission_name) as names
              FROM submissions
+             WHERE withdrawn_at IS NULL
              GROUP BY file_checksum
              HAVING COUNT(DISTINCT user_id) > 1",
         )?;
@@ -141,112 +668,185 @@ impl Database {
         Ok(duplicates)
     }
 
-    pub fn get_leaderboard(&self, order_by: &str) -> Result<Vec<(String, String, String, f64, f64, i32, Option<f64>)>> {
+    /// Replaces the submissions `user_id` nominated in `track` with `submission_ids`.
+    pub fn select_final_submissions(
+        &self,
+        user_id: i64,
+        track: &str,
+        submission_ids: &[i64],
+        selected_at: &str,
+    ) -> Result<()> {
+        let mut conn = self.get_connection()?;
+        let tx = conn.transaction()?;
+
+        tx.execute(
+            "DELETE FROM final_selections
+             WHERE submission_id IN (SELECT id FROM submissions WHERE user_id = ?1 AND track = ?2)",
+            params![user_id, track],
+        )?;
+        for id in submission_ids {
+            tx.execute(
+                "INSERT INTO final_selections (submission_id, selected_at) VALUES (?1, ?2)",
+                params![id, selected_at],
+            )?;
+        }
+
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// IDs of the submissions `user_id` nominated and did not withdraw, in every track.
+    pub fn get_selected_submissions(&self, user_id: i64) -> Result<Vec<i64>> {
+        let conn = self.get_connection()?;
+        let mut stmt = conn.prepare(
+            "SELECT fs.submission_id
+             FROM final_selections fs
+             JOIN submissions s ON s.id = fs.submission_id
+             WHERE s.user_id = ?1 AND s.withdrawn_at IS NULL
+             ORDER BY fs.submission_id",
+        )?;
+
+        let ids = stmt
+            .query_map([user_id], |row| row.get::<_, i64>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(ids)
+    }
+
+    /// Ranks every user of `track` by the best of the on-time submissions they
+    /// selected, or by their last on-time submission when they selected none,
+    /// scored on `split`.
+    pub fn get_leaderboard(
+        &self,
+        track: &str,
+        order_by: &str,
+        split: Split,
+        higher_is_better: bool,
+    ) -> Result<Vec<LeaderboardEntry>> {
         let conn = self.get_connection()?;
-        
-        let order_clause = match order_by {
-            "datetime" => "ORDER BY timestamp DESC",
-            _ => "ORDER BY final_gain DESC", // default to gain
+
+        let (order_clause, best) = match (order_by, higher_is_better) {
+            ("datetime", true) => ("ORDER BY timestamp DESC", "MAX"),
+            ("datetime", false) => ("ORDER BY timestamp DESC", "MIN"),
+            (_, true) => ("ORDER BY final_gain DESC", "MAX"), // default to gain
+            (_, false) => ("ORDER BY final_gain ASC", "MIN"),
         };
-        
+        let best_order = if higher_is_better { "DESC" } else { "ASC" };
+        let gain_column = format!("{}_gain", split.as_str());
+
         let query = format!(
             "WITH last_valid_submission AS (
-                SELECT 
+                SELECT
+                    id,
                     user_id,
                     user_full_name,
                     user_email,
-                    actual_gain,
+                    {gain} as gain,
                     expected_gain,
                     timestamp,
+                    fs.submission_id IS NOT NULL as selected,
                     ROW_NUMBER() OVER (
-                        PARTITION BY user_id 
-                        ORDER BY timestamp DESC
+                        PARTITION BY user_id
+                        ORDER BY fs.submission_id IS NOT NULL DESC,
+                                 CASE WHEN fs.submission_id IS NOT NULL THEN {gain} END {best_order},
+                                 timestamp DESC
                     ) as rn
                 FROM submissions
-                WHERE after_deadline = 0
+                LEFT JOIN final_selections fs ON fs.submission_id = submissions.id
+                WHERE after_deadline = 0 AND track = ?1 AND withdrawn_at IS NULL
             ),
             user_stats AS (
                 SELECT
+                    lvs.id as submission_id,
                     s.user_id,
                     s.user_full_name,
                     s.user_email,
-                    s.timestamp,
+                    lvs.timestamp,
                     COUNT(*) as total_submissions,
-                    lvs.actual_gain as final_gain,
+                    lvs.gain as final_gain,
                     lvs.expected_gain as final_expected_gain,
-                    MAX(CASE WHEN s.after_deadline = 0 THEN s.actual_gain END) as max_gain
+                    lvs.selected,
+                    {best}(CASE WHEN s.after_deadline = 0 THEN s.{gain} END) as max_gain
                 FROM submissions s
-                LEFT JOIN last_valid_submission lvs 
+                LEFT JOIN last_valid_submission lvs
                     ON s.user_id = lvs.user_id AND lvs.rn = 1
-                GROUP BY s.user_id, s.user_full_name, s.user_email, lvs.actual_gain
+                WHERE s.track = ?1 AND s.withdrawn_at IS NULL
+                GROUP BY s.user_id, s.user_full_name, s.user_email, lvs.id, lvs.timestamp, lvs.gain, lvs.selected
             )
             SELECT
-                user_full_name,
-                user_email,
-                timestamp,
-                final_gain,
-                final_expected_gain,
-                total_submissions,
-                max_gain
-            FROM user_stats
-            WHERE final_gain IS NOT NULL
-            {}",
-            order_clause
+                us.user_full_name,
+                us.user_email,
+                us.timestamp,
+                us.final_gain,
+                us.final_expected_gain,
+                us.total_submissions,
+                us.max_gain,
+                us.submission_id,
+                si.low,
+                si.high,
+                us.selected
+            FROM user_stats us
+            LEFT JOIN submission_intervals si
+                ON si.submission_id = us.submission_id AND si.scope = '{scope}'
+            WHERE us.final_gain IS NOT NULL
+            {order}",
+            gain = gain_column,
+            best = best,
+            best_order = best_order,
+            scope = split.as_str(),
+            order = order_clause
         );
-        
+
         let mut stmt = conn.prepare(&query)?;
 
         let results = stmt
-            .query_map([], |row| {
-                Ok((
-                    row.get::<_, String>(0)?,
-                    row.get::<_, String>(1)?,
-                    row.get::<_, String>(2)?,
-                    row.get::<_, f64>(3)?,
-                    row.get::<_, f64>(4)?,
-                    row.get::<_, i32>(5)?,
-                    row.get::<_, Option<f64>>(6)?,
-                ))
+            .query_map([track], |row| {
+                let low: Option<f64> = row.get(8)?;
+                let high: Option<f64> = row.get(9)?;
+                Ok(LeaderboardEntry {
+                    submission_id: row.get(7)?,
+                    user_full_name: row.get(0)?,
+                    user_email: row.get(1)?,
+                    timestamp: row.get(2)?,
+                    final_gain: row.get(3)?,
+                    final_expected_gain: row.get(4)?,
+                    total_submissions: row.get(5)?,
+                    max_gain: row.get(6)?,
+                    interval: low.zip(high).map(|(low, high)| Interval { low, high }),
+                    selected: row.get(10)?,
+                })
             })?
             .collect::<Result<Vec<_>, _>>()?;
 
         Ok(results)
     }
 
+    pub fn get_submission(&self, id: i64) -> Result<Option<Submission>> {
+        let conn = self.get_connection()?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {}
+             FROM submissions
+             WHERE id = ?1",
+            SUBMISSION_COLUMNS
+        ))?;
+
+        let mut rows = stmt.query_map([id], submission_from_row)?;
+        Ok(rows.next().transpose()?)
+    }
+
     pub fn get_user_submissions_by_identifier(&self, identifier: &str) -> Result<Vec<Submission>> {
         let conn = self.get_connection()?;
         let pattern = format!("%{}%", identifier);
-        let mut stmt = conn.prepare(
-            "SELECT id, user_id, user_email, user_full_name, submission_name,
-                    timestamp, file_checksum, file_path, expected_gain, actual_gain,
-                    tp, tn, fp, fn, positives_predicted, threshold_category, after_deadline
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {}
              FROM submissions
              WHERE user_email LIKE ?1 OR user_full_name LIKE ?1
              ORDER BY timestamp DESC",
-        )?;
+            SUBMISSION_COLUMNS
+        ))?;
 
         let submissions = stmt
-            .query_map([&pattern], |row| {
-                Ok(Submission {
-                    id: Some(row.get(0)?),
-                    user_id: row.get(1)?,
-                    user_email: row.get(2)?,
-                    user_full_name: row.get(3)?,
-                    submission_name: row.get(4)?,
-                    timestamp: row.get(5)?,
-                    file_checksum: row.get(6)?,
-                    file_path: row.get(7)?,
-                    expected_gain: row.get(8)?,
-                    actual_gain: row.get(9)?,
-                    tp: row.get(10)?,
-                    tn: row.get(11)?,
-                    fp: row.get(12)?,
-                    fn_: row.get(13)?,
-                    positives_predicted: row.get(14)?,
-                    threshold_category: row.get(15)?,
-                    after_deadline: row.get::<_, i32>(16)? != 0,
-                })
-            })?
+            .query_map([&pattern], submission_from_row)?
             .collect::<Result<Vec<_>, _>>()?;
 
         Ok(submissions)
@@ -254,36 +854,15 @@ impl Database {
 
     pub fn get_all_submissions(&self) -> Result<Vec<Submission>> {
         let conn = self.get_connection()?;
-        let mut stmt = conn.prepare(
-            "SELECT id, user_id, user_email, user_full_name, submission_name,
-                    timestamp, file_checksum, file_path, expected_gain, actual_gain,
-                    tp, tn, fp, fn, positives_predicted, threshold_category, after_deadline
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {}
              FROM submissions
              ORDER BY timestamp DESC",
-        )?;
+            SUBMISSION_COLUMNS
+        ))?;
 
         let submissions = stmt
-            .query_map([], |row| {
-                Ok(Submission {
-                    id: Some(row.get(0)?),
-                    user_id: row.get(1)?,
-                    user_email: row.get(2)?,
-                    user_full_name: row.get(3)?,
-                    submission_name: row.get(4)?,
-                    timestamp: row.get(5)?,
-                    file_checksum: row.get(6)?,
-                    file_path: row.get(7)?,
-                    expected_gain: row.get(8)?,
-                    actual_gain: row.get(9)?,
-                    tp: row.get(10)?,
-                    tn: row.get(11)?,
-                    fp: row.get(12)?,
-                    fn_: row.get(13)?,
-                    positives_predicted: row.get(14)?,
-                    threshold_category: row.get(15)?,
-                    after_deadline: row.get::<_, i32>(16)? != 0,
-                })
-            })?
+            .query_map([], submission_from_row)?
             .collect::<Result<Vec<_>, _>>()?;
 
         Ok(submissions)
@@ -302,3 +881,76 @@ impl Database {
         Ok(user_ids)
     }
 }
+
+fn add_column_if_missing(conn: &Connection, table: &str, column: &str, sql_type: &str) -> Result<()> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let exists = stmt
+        .query_map([], |row| row.get::<_, String>(1))?
+        .collect::<Result<Vec<_>, _>>()?
+        .iter()
+        .any(|name| name == column);
+
+    if !exists {
+        conn.execute(
+            &format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, sql_type),
+            [],
+        )?;
+    }
+
+    Ok(())
+}
+
+fn submission_from_row(row: &Row) -> rusqlite::Result<Submission> {
+    Ok(Submission {
+        id: Some(row.get(0)?),
+        user_id: row.get(1)?,
+        user_email: row.get(2)?,
+        user_full_name: row.get(3)?,
+        submission_name: row.get(4)?,
+        timestamp: row.get(5)?,
+        file_checksum: row.get(6)?,
+        file_path: row.get(7)?,
+        expected_gain: row.get(8)?,
+        actual_gain: row.get(9)?,
+        tp: row.get(10)?,
+        tn: row.get(11)?,
+        fp: row.get(12)?,
+        fn_: row.get(13)?,
+        positives_predicted: row.get(14)?,
+        threshold_category: row.get(15)?,
+        after_deadline: row.get::<_, i32>(16)? != 0,
+        public: GainResult {
+            gain: row.get(17)?,
+            tp: row.get(18)?,
+            tn: row.get(19)?,
+            fp: row.get(20)?,
+            fn_: row.get(21)?,
+            confusion: None,
+        },
+        private: GainResult {
+            gain: row.get(22)?,
+            tp: row.get(23)?,
+            tn: row.get(24)?,
+            fp: row.get(25)?,
+            fn_: row.get(26)?,
+            confusion: None,
+        },
+        cutoff: row.get(27)?,
+        best_gain: row.get(28)?,
+        best_cutoff: row.get(29)?,
+        confusion_matrix: row
+            .get::<_, Option<String>>(30)?
+            .and_then(|json| serde_json::from_str(&json).ok()),
+        track: row.get(31)?,
+        withdrawal: row
+            .get::<_, Option<String>>(32)?
+            .map(|at| -> rusqlite::Result<Withdrawal> {
+                Ok(Withdrawal {
+                    at,
+                    reason: row.get::<_, Option<String>>(33)?.unwrap_or_default(),
+                })
+            })
+            .transpose()?,
+        description: row.get(34)?,
+    })
+}
@@ -0,0 +1,220 @@
+//! Dense row index over the master data: every ID maps to a row number in
+//! `0..len`, so per-row data lives in plain vectors and sets of rows in bitsets.
+
+use crate::models::Id;
+
+/// Fixed-size set of row numbers, one bit per row.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BitSet {
+    words: Vec<u64>,
+    len: usize,
+}
+
+impl BitSet {
+    pub fn new(len: usize) -> Self {
+        Self {
+            words: vec![0; len.div_ceil(64)],
+            len,
+        }
+    }
+
+    /// Number of rows the set can hold, not the number of rows in it.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn insert(&mut self, row: usize) {
+        self.words[row / 64] |= 1 << (row % 64);
+    }
+
+    pub fn contains(&self, row: usize) -> bool {
+        self.words[row / 64] & (1 << (row % 64)) != 0
+    }
+
+    pub fn count(&self) -> usize {
+        self.words.iter().map(|w| w.count_ones() as usize).sum()
+    }
+
+    pub fn words(&self) -> &[u64] {
+        &self.words
+    }
+
+    /// Rows in the set, in increasing order.
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.words.iter().enumerate().flat_map(|(i, &word)| {
+            let mut word = word;
+            std::iter::from_fn(move || {
+                if word == 0 {
+                    return None;
+                }
+                let bit = word.trailing_zeros() as usize;
+                word &= word - 1;
+                Some(i * 64 + bit)
+            })
+        })
+    }
+
+    /// Complement within `0..len`.
+    pub fn complement(&self) -> Self {
+        let mut words: Vec<u64> = self.words.iter().map(|w| !w).collect();
+        if let Some(last) = words.last_mut() {
+            let used = self.len % 64;
+            if used != 0 {
+                *last &= (1 << used) - 1;
+            }
+        }
+        Self {
+            words,
+            len: self.len,
+        }
+    }
+}
+
+/// Maps IDs to dense row numbers. Rows follow the sorted order of the IDs,
+/// which are interned in compact storage instead of one allocation per ID.
+#[derive(Debug, Clone)]
+pub struct IdIndex {
+    keys: Keys,
+}
+
+#[derive(Debug, Clone)]
+enum Keys {
+    Int {
+        ids: Vec<i64>,
+        /// `(min, slots)`: `slots[id - min]` holds the row, or `u32::MAX`;
+        /// only built when IDs are close to contiguous so the table stays small
+        offsets: Option<(i64, Vec<u32>)>,
+    },
+    /// Text IDs concatenated in `bytes`; ID `i` spans `ends[i - 1]..ends[i]`
+    Text { bytes: String, ends: Vec<usize> },
+}
+
+impl IdIndex {
+    /// Builds the index from sorted, unique IDs, all of the same kind.
+    pub fn new(ids: Vec<Id>) -> Self {
+        debug_assert!(ids.windows(2).all(|w| w[0] < w[1]));
+
+        if ids.iter().all(|id| matches!(id, Id::Int(_))) {
+            let ids: Vec<i64> = ids
+                .into_iter()
+                .filter_map(|id| match id {
+                    Id::Int(id) => Some(id),
+                    Id::Text(_) => None,
+                })
+                .collect();
+            let offsets = Self::offsets(&ids);
+            return Self {
+                keys: Keys::Int { ids, offsets },
+            };
+        }
+
+        let mut bytes = String::new();
+        let mut ends = Vec::with_capacity(ids.len());
+        for id in &ids {
+            match id {
+                Id::Text(text) => bytes.push_str(text),
+                Id::Int(id) => bytes.push_str(&id.to_string()),
+            }
+            ends.push(bytes.len());
+        }
+        bytes.shrink_to_fit();
+
+        Self {
+            keys: Keys::Text { bytes, ends },
+        }
+    }
+
+    fn offsets(ids: &[i64]) -> Option<(i64, Vec<u32>)> {
+        let (first, last) = (*ids.first()?, *ids.last()?);
+        let span = usize::try_from(last.checked_sub(first)?).ok()?.checked_add(1)?;
+        // An offset table costs 4 bytes per slot; only worth it without large gaps
+        if span > ids.len().saturating_mul(2).saturating_add(1024) || ids.len() >= u32::MAX as usize {
+            return None;
+        }
+
+        let mut slots = vec![u32::MAX; span];
+        for (row, id) in ids.iter().enumerate() {
+            slots[(id - first) as usize] = row as u32;
+        }
+        Some((first, slots))
+    }
+
+    pub fn len(&self) -> usize {
+        match &self.keys {
+            Keys::Int { ids, .. } => ids.len(),
+            Keys::Text { ends, .. } => ends.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// ID of `row`.
+    pub fn id(&self, row: usize) -> Id {
+        match &self.keys {
+            Keys::Int { ids, .. } => Id::Int(ids[row]),
+            Keys::Text { .. } => Id::Text(self.text(row).into()),
+        }
+    }
+
+    fn text(&self, row: usize) -> &str {
+        match &self.keys {
+            Keys::Text { bytes, ends } => {
+                let start = if row == 0 { 0 } else { ends[row - 1] };
+                &bytes[start..ends[row]]
+            }
+            Keys::Int { .. } => "",
+        }
+    }
+
+    pub fn row_of(&self, id: &Id) -> Option<usize> {
+        match (&self.keys, id) {
+            (Keys::Int { offsets: Some((min, slots)), .. }, Id::Int(id)) => {
+                let slot = usize::try_from(id.checked_sub(*min)?).ok()?;
+                slots
+                    .get(slot)
+                    .filter(|row| **row != u32::MAX)
+                    .map(|row| *row as usize)
+            }
+            (Keys::Int { ids, .. }, Id::Int(id)) => ids.binary_search(id).ok(),
+            (Keys::Text { ends, .. }, Id::Text(text)) => {
+                // Binary search over the interned IDs, which are sorted like `str`
+                let (mut low, mut high) = (0, ends.len());
+                while low < high {
+                    let mid = (low + high) / 2;
+                    match self.text(mid).cmp(&**text) {
+                        std::cmp::Ordering::Less => low = mid + 1,
+                        std::cmp::Ordering::Greater => high = mid,
+                        std::cmp::Ordering::Equal => return Some(mid),
+                    }
+                }
+                None
+            }
+            _ => None,
+        }
+    }
+
+    /// Rows of the known IDs in `ids`; unknown IDs are ignored.
+    pub fn bitset_of<'a>(&self, ids: impl IntoIterator<Item = &'a Id>) -> BitSet {
+        let mut set = BitSet::new(self.len());
+        for id in ids {
+            if let Some(row) = self.row_of(id) {
+                set.insert(row);
+            }
+        }
+        set
+    }
+
+    /// IDs of `ids` that are not in the index.
+    pub fn unknown<'a>(&self, ids: impl IntoIterator<Item = &'a Id>) -> Vec<Id> {
+        ids.into_iter()
+            .filter(|id| self.row_of(id).is_none())
+            .cloned()
+            .collect()
+    }
+}
@@ -0,0 +1,79 @@
+//! What-if ensembles: stored binary submissions combined row by row with a
+//! voting rule, scored without becoming a submission.
+
+use anyhow::{bail, Result};
+
+use crate::dense::BitSet;
+
+/// How many members must predict a row positive for the ensemble to do so.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rule {
+    /// More than half of the members
+    Majority,
+    /// Any member
+    Union,
+    /// Every member
+    Intersection,
+    /// At least `k` members
+    AtLeast(usize),
+}
+
+impl Rule {
+    /// `majority`, `union`, `intersection` or `<k>-of-n`, e.g. `3-of-n`.
+    pub fn parse(raw: &str) -> Result<Self> {
+        let raw = raw.to_lowercase();
+        match raw.as_str() {
+            "majority" => Ok(Rule::Majority),
+            "union" => Ok(Rule::Union),
+            "intersection" => Ok(Rule::Intersection),
+            _ => match raw.strip_suffix("-of-n").map(str::parse) {
+                Some(Ok(k)) => Ok(Rule::AtLeast(k)),
+                _ => bail!(
+                    "Unknown rule '{}' (expected majority, union, intersection or <k>-of-n)",
+                    raw
+                ),
+            },
+        }
+    }
+
+    /// Votes needed out of `members`.
+    pub fn min_votes(&self, members: usize) -> Result<usize> {
+        match *self {
+            Rule::Majority => Ok(members / 2 + 1),
+            Rule::Union => Ok(1),
+            Rule::Intersection => Ok(members),
+            Rule::AtLeast(k) if (1..=members).contains(&k) => Ok(k),
+            Rule::AtLeast(k) => bail!("k must be between 1 and {}, got {}", members, k),
+        }
+    }
+
+    pub fn label(&self) -> String {
+        match self {
+            Rule::Majority => "mayoría".to_string(),
+            Rule::Union => "unión".to_string(),
+            Rule::Intersection => "intersección".to_string(),
+            Rule::AtLeast(k) => format!("{} de n", k),
+        }
+    }
+}
+
+/// Rows predicted positive by at least `min_votes` of `members`, which must
+/// all cover the same rows.
+pub fn combine(members: &[&BitSet], min_votes: usize) -> BitSet {
+    let len = members.first().map_or(0, |m| m.len());
+    let mut votes = vec![0usize; len];
+    for member in members {
+        debug_assert_eq!(member.len(), len);
+        for row in member.iter() {
+            votes[row] += 1;
+        }
+    }
+
+    let mut combined = BitSet::new(len);
+    for (row, count) in votes.iter().enumerate() {
+        if *count >= min_votes {
+            combined.insert(row);
+        }
+    }
+    combined
+}
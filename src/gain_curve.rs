@@ -0,0 +1,171 @@
+use crate::config::GainMatrix;
+use crate::master_data::{Cell, CellAmounts, MasterData, Split};
+use crate::models::Id;
+
+/// Gain obtained when every ID scored at or above `threshold` is predicted positive.
+#[derive(Debug, Clone)]
+pub struct CurvePoint {
+    pub threshold: f64,
+    pub positives: usize,
+    pub gain: f64,
+    pub public_gain: f64,
+    pub private_gain: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct GainCurve {
+    pub points: Vec<CurvePoint>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Counts {
+    tp: i32,
+    tn: i32,
+    fp: i32,
+    fn_: i32,
+    /// Only tracked when the master data carries per-row amounts
+    amounts: Option<CellAmounts>,
+}
+
+impl Counts {
+    fn gain(&self, gain_matrix: &GainMatrix) -> f64 {
+        match &self.amounts {
+            Some(amounts) => gain_matrix.evaluate_amounts(amounts),
+            None => gain_matrix.evaluate(self.tp, self.tn, self.fp, self.fn_),
+        }
+    }
+
+    /// Counts one row as predicted negative.
+    fn add_negative(&mut self, row: usize, is_positive: bool, master_data: &MasterData) {
+        let cell = Cell::of(is_positive, false);
+        if is_positive {
+            self.fn_ += 1;
+        } else {
+            self.tn += 1;
+        }
+        if let Some(amounts) = self.amounts.as_mut() {
+            amounts.add(cell, master_data.row_amount(row, cell));
+        }
+    }
+
+    /// Moves one row from predicted negative to predicted positive.
+    fn flip(&mut self, row: usize, is_positive: bool, master_data: &MasterData) {
+        if is_positive {
+            self.fn_ -= 1;
+            self.tp += 1;
+        } else {
+            self.tn -= 1;
+            self.fp += 1;
+        }
+        if let Some(amounts) = self.amounts.as_mut() {
+            let (from, to) = (Cell::of(is_positive, false), Cell::of(is_positive, true));
+            amounts.remove(from, master_data.row_amount(row, from));
+            amounts.add(to, master_data.row_amount(row, to));
+        }
+    }
+}
+
+impl GainCurve {
+    /// Sweeps the cut-off over `scores` from the highest score down, scoring
+    /// every distinct threshold. IDs without a score are never predicted positive.
+    pub fn compute(scores: &[(Id, f64)], master_data: &MasterData, gain_matrix: &GainMatrix) -> Self {
+        let empty = Counts {
+            amounts: master_data.has_row_gains().then(CellAmounts::default),
+            ..Default::default()
+        };
+        let mut full = empty;
+        let mut public = empty;
+        let mut private = empty;
+
+        // Start with nothing predicted positive
+        for row in 0..master_data.total_count() {
+            let is_positive = master_data.is_positive(row);
+            for (counts, split) in [
+                (&mut full, None),
+                (&mut public, Some(Split::Public)),
+                (&mut private, Some(Split::Private)),
+            ] {
+                if split.is_some_and(|s| !master_data.in_split(row, s)) {
+                    continue;
+                }
+                counts.add_negative(row, is_positive, master_data);
+            }
+        }
+
+        // Unknown IDs are never predicted positive either
+        let mut sorted: Vec<(usize, f64)> = scores
+            .iter()
+            .filter_map(|(id, score)| Some((master_data.row_of(id)?, *score)))
+            .collect();
+        let descending = |a: &(usize, f64), b: &(usize, f64)| b.1.total_cmp(&a.1);
+        #[cfg(feature = "parallel")]
+        {
+            use rayon::slice::ParallelSliceMut;
+            sorted.par_sort_unstable_by(descending);
+        }
+        #[cfg(not(feature = "parallel"))]
+        sorted.sort_unstable_by(descending);
+
+        let mut points = vec![CurvePoint {
+            threshold: f64::INFINITY,
+            positives: 0,
+            gain: full.gain(gain_matrix),
+            public_gain: public.gain(gain_matrix),
+            private_gain: private.gain(gain_matrix),
+        }];
+
+        let mut i = 0;
+        while i < sorted.len() {
+            let threshold = sorted[i].1;
+            // Rows with the same score always land on the same side of the cut-off
+            while i < sorted.len() && sorted[i].1 == threshold {
+                let row = sorted[i].0;
+                let is_positive = master_data.is_positive(row);
+                full.flip(row, is_positive, master_data);
+                if master_data.in_split(row, Split::Public) {
+                    public.flip(row, is_positive, master_data);
+                }
+                if master_data.in_split(row, Split::Private) {
+                    private.flip(row, is_positive, master_data);
+                }
+                i += 1;
+            }
+
+            points.push(CurvePoint {
+                threshold,
+                positives: i,
+                gain: full.gain(gain_matrix),
+                public_gain: public.gain(gain_matrix),
+                private_gain: private.gain(gain_matrix),
+            });
+        }
+
+        Self { points }
+    }
+
+    /// Point with the highest gain over the whole dataset.
+    pub fn best(&self) -> &CurvePoint {
+        self.points
+            .iter()
+            .max_by(|a, b| a.gain.total_cmp(&b.gain))
+            .expect("a gain curve always has the empty-prediction point")
+    }
+
+    /// Evenly spaced subset of at most `max_points` points, always keeping the
+    /// best one, for displaying in chat.
+    pub fn sample(&self, max_points: usize) -> Vec<&CurvePoint> {
+        let best = self.best();
+        let len = self.points.len();
+        let step = len.div_ceil(max_points.max(1)).max(1);
+
+        let mut indices: Vec<usize> = (0..len).step_by(step).collect();
+        indices.push(len - 1);
+        if let Some(best_index) = self.points.iter().position(|p| std::ptr::eq(p, best)) {
+            indices.push(best_index);
+        }
+        indices.sort_unstable();
+        indices.dedup();
+
+        indices.into_iter().map(|i| &self.points[i]).collect()
+    }
+}
@@ -1,10 +1,25 @@
+pub mod args;
+pub mod attachment;
+pub mod bootstrap;
+pub mod calibration;
 pub mod config;
+pub mod csv_dialect;
 pub mod database;
+pub mod dense;
+pub mod ensemble;
 pub mod error;
+pub mod gain_curve;
 pub mod master_data;
+pub mod metrics;
 pub mod models;
+pub mod quota;
+pub mod reference;
+pub mod storage;
 pub mod submission;
+pub mod track;
+pub mod validation;
 pub mod zulip;
 
 #[cfg(test)]
+#[allow(clippy::module_inception, clippy::useless_vec)]
 mod tests;
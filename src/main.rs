@@ -1,4 +1,4 @@
-use dos_esfinges_bot::{config, database, master_data, models, submission, zulip};
+use dos_esfinges_bot::{args, config, database, ensemble, master_data, models, submission, track, zulip};
 
 use anyhow::Result;
 use clap::{Parser, Subcommand};
@@ -8,9 +8,10 @@ use chrono::Local;
 use std::fs;
 use tracing_subscriber::{fmt, layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
 
-use config::BotConfig;
+use config::{BotConfig, TaskType};
 use database::Database;
-use master_data::MasterData;
+use ensemble::Rule;
+use track::Track;
 use zulip::ZulipClient;
 
 use regex::Regex;
@@ -37,6 +38,33 @@ enum Commands {
         #[arg(short, long)]
         config: String,
     },
+    /// Recompute the scores of every stored submission
+    Rescore {
+        /// Config file path
+        #[arg(short, long)]
+        config: String,
+    },
+    /// Export every submission as CSV, with its score as a % of the oracle
+    Export {
+        /// Config file path
+        #[arg(short, long)]
+        config: String,
+        /// Output CSV file (stdout when omitted)
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// Score a combination of stored binary submissions without saving it
+    Ensemble {
+        /// Config file path
+        #[arg(short, long)]
+        config: String,
+        /// Combination rule: majority, union, intersection or <k>-of-n
+        #[arg(short, long, default_value = "majority")]
+        rule: String,
+        /// Submission IDs to combine
+        #[arg(required = true, num_args = 2..)]
+        ids: Vec<i64>,
+    },
 }
 
 #[tokio::main]
@@ -87,6 +115,9 @@ async fn main() -> Result<()> {
             Ok(())
         }
         Some(Commands::Run { config }) => run_bot(&config).await,
+        Some(Commands::Rescore { config }) => rescore(&config),
+        Some(Commands::Export { config, output }) => export(&config, output.as_deref()),
+        Some(Commands::Ensemble { config, rule, ids }) => ensemble(&config, &rule, &ids),
         None => {
             if let Some(config_path) = cli.config {
                 run_bot(&config_path).await
@@ -98,6 +129,46 @@ async fn main() -> Result<()> {
     }
 }
 
+/// Rescores every stored submission from the command line, e.g. after fixing
+/// a label in the master data or a weight in `gain_matrix`.
+fn rescore(config_path: &str) -> Result<()> {
+    let config = BotConfig::load(config_path)?;
+    let db = Database::new(&config.database.path)?;
+    db.init()?;
+
+    for track in Track::load_all(&config)? {
+        println!("{}", submission::process_rescore(&db, &track));
+    }
+    Ok(())
+}
+
+/// Writes every stored submission as CSV to `output`, or to stdout.
+fn export(config_path: &str, output: Option<&str>) -> Result<()> {
+    let config = BotConfig::load(config_path)?;
+    let db = Database::new(&config.database.path)?;
+    db.init()?;
+    let tracks = Track::load_all(&config)?;
+
+    let rows = match output {
+        Some(path) => submission::export_submissions(&db, &tracks, fs::File::create(path)?)?,
+        None => submission::export_submissions(&db, &tracks, std::io::stdout())?,
+    };
+    info!("Exported {} submissions", rows);
+    Ok(())
+}
+
+/// Prints the scores of an ensemble of stored submissions.
+fn ensemble(config_path: &str, rule: &str, ids: &[i64]) -> Result<()> {
+    let config = BotConfig::load(config_path)?;
+    let rule = Rule::parse(rule)?;
+    let db = Database::new(&config.database.path)?;
+    db.init()?;
+    let tracks = Track::load_all(&config)?;
+
+    println!("{}", submission::process_ensemble(ids, rule, &db, &tracks));
+    Ok(())
+}
+
 async fn run_bot(config_path: &str) -> Result<()> {
     info!("Starting DosEsfingesBot with config: {}", config_path);
 
@@ -110,13 +181,31 @@ async fn run_bot(config_path: &str) -> Result<()> {
     db.init()?;
     info!("Database initialized at: {}", config.database.path);
 
-    // Load master data
-    let master_data = MasterData::load(&config.master_data.path)?;
-    info!(
-        "Master data loaded: {} records, {} positives",
-        master_data.total_count(),
-        master_data.positive_count()
-    );
+    // Load the master data of every track
+    let tracks = Track::load_all(&config)?;
+    for track in &tracks {
+        let master_data = &track.master_data;
+        info!(
+            "Track '{}' master data loaded: {} records, {} positives",
+            track.name,
+            master_data.total_count(),
+            master_data.positive_count()
+        );
+        if master_data.has_split() {
+            info!(
+                "Public/private split: {} public, {} private",
+                master_data.split_count(master_data::Split::Public),
+                master_data.split_count(master_data::Split::Private)
+            );
+        }
+        info!(
+            "Primary metric: {}",
+            track.metrics.primary().name()
+        );
+        if let Some(oracle) = track.references.as_ref().and_then(|r| r.all.oracle) {
+            info!("Oracle score: {:.4}", oracle);
+        }
+    }
 
     // Create Zulip client
     let client = ZulipClient::new(
@@ -132,10 +221,11 @@ async fn run_bot(config_path: &str) -> Result<()> {
 
     // Start message loop
     let bot = Bot {
+        config_path: config_path.to_string(),
         config,
         client,
         db,
-        master_data,
+        tracks,
     };
 
     let mut bot = bot;
@@ -145,10 +235,12 @@ async fn run_bot(config_path: &str) -> Result<()> {
 }
 
 struct Bot {
+    config_path: String,
     config: BotConfig,
     client: ZulipClient,
     db: Database,
-    master_data: MasterData,
+    /// Tracks de la competencia, el principal primero; se recargan junto con la config
+    tracks: Vec<Track>,
 }
 
 impl Bot {
@@ -181,7 +273,17 @@ impl Bot {
         }
     }
 
-    async fn handle_message(&self, message: models::Message) {
+    /// Reloads the config and the master data of every track from disk,
+    /// keeping the current ones if any fails to load.
+    fn reload(&mut self) -> Result<()> {
+        let config = BotConfig::load(&self.config_path)?;
+        self.tracks = Track::load_all(&config)?;
+        self.config = config;
+        info!("Config and master data reloaded from {}", self.config_path);
+        Ok(())
+    }
+
+    async fn handle_message(&mut self, message: models::Message) {
         let sender_email = message.sender_email.clone();
         let content = message.content.trim().to_lowercase();
 
@@ -198,29 +300,91 @@ impl Bot {
         let is_teacher = self.config.teachers.contains(&sender_email);
         info!("User is teacher: {}", is_teacher);
 
-        let response = if content.starts_with("submit ") && !is_teacher {
+        // A batch `submit` puts each attachment on its own line, right after the command
+        let is_submit = content
+            .strip_prefix("submit")
+            .is_some_and(|rest| rest.starts_with(char::is_whitespace));
+        // Commands matched by their first word, so `withdrawal` is not `withdraw`
+        // and `granted` is not `grant`
+        let command = content.split_whitespace().next().unwrap_or_default();
+
+        let response = if is_submit && !is_teacher {
             info!("Processing submit command (student)");
-            submission::process_submit(
-                &message,
-                &self.config,
-                &self.db,
-                &self.master_data,
-                is_teacher,
-            )
-            .await
-        } else if content.starts_with("submit ") && is_teacher {
+            submission::process_submit(&message, &self.tracks, &self.db, is_teacher).await
+        } else if is_submit && is_teacher {
             info!("Submit command blocked for teacher");
             "⚠️ Los profesores no pueden enviar submissions. Usa los comandos de administración."
                 .to_string()
         } else if content == "list submits" && !is_teacher {
             info!("Processing list submits command");
-            submission::process_list_submits(&message.sender_full_name, &self.db, &self.config)
+            submission::process_list_submits(&message.sender_full_name, &self.db, &self.tracks)
+        } else if (content == "select" || content.starts_with("select ")) && !is_teacher {
+            info!("Processing select command");
+            let ids: Option<Vec<i64>> = content
+                .split_whitespace()
+                .skip(1)
+                .map(|id| id.trim_start_matches('#').parse().ok())
+                .collect();
+            match ids {
+                Some(ids) => submission::process_select(message.sender_id, &ids, &self.db, &self.tracks),
+                None => "❌ Uso: select <id_envío> [<id_envío>]".to_string(),
+            }
+        } else if command == "withdraw" && !is_teacher {
+            info!("Processing withdraw command");
+            let mut args = message.content.split_whitespace().skip(1);
+            let id = args.next().and_then(|id| id.trim_start_matches('#').parse().ok());
+            let reason = args.collect::<Vec<_>>().join(" ");
+            match id {
+                Some(id) => submission::process_withdraw(message.sender_id, id, &reason, &self.db, &self.tracks),
+                None => "❌ Uso: withdraw <id_envío> <motivo>".to_string(),
+            }
+        } else if content.starts_with("rename ") && !is_teacher {
+            info!("Processing rename command");
+            match args::split(&message.content) {
+                Ok(words) => match words.get(1).and_then(|id| id.trim_start_matches('#').parse().ok()) {
+                    Some(id) if words.len() > 2 => {
+                        submission::process_rename(message.sender_id, id, &words[2..].join(" "), &self.db)
+                    }
+                    _ => "❌ Uso: rename <id_envío> <nuevo nombre>".to_string(),
+                },
+                Err(e) => e,
+            }
+        } else if content == "quota" && !is_teacher {
+            info!("Processing quota command");
+            submission::process_quota(message.sender_id, &self.db, &self.config)
+        } else if command == "grant" && is_teacher {
+            info!("Processing grant command (teacher)");
+            let extra = content
+                .split_whitespace()
+                .last()
+                .and_then(|n| n.parse::<u32>().ok())
+                .filter(|n| *n > 0);
+            match extra {
+                Some(extra) => match self.resolve_mentioned_user(&message.content).await {
+                    Ok((user_id, user_name)) => submission::process_grant(
+                        user_id,
+                        &user_name,
+                        extra,
+                        &sender_email,
+                        &self.db,
+                        &self.config,
+                    ),
+                    Err(e) => e,
+                },
+                None => "❌ Uso: grant @usuario <cantidad> (usa la mención de Zulip)".to_string(),
+            }
         } else if content == "duplicates" && is_teacher {
             info!("Processing duplicates command (teacher)");
             submission::process_duplicates(&self.db)
+        } else if content == "verify-storage" && is_teacher {
+            info!("Processing verify-storage command (teacher)");
+            submission::process_verify_storage(&self.db, &self.config)
+        } else if content == "leaderboard estimators" && is_teacher {
+            info!("Processing estimator leaderboard command (teacher)");
+            submission::process_estimator_leaderboard(&self.db, &self.config)
         } else if content.starts_with("leaderboard") && is_teacher {
             info!("Processing leaderboard command (teacher)");
-            let parts: Vec<&str> = message.content.trim().split_whitespace().collect();
+            let parts: Vec<&str> = message.content.split_whitespace().collect();
             let order_by = if parts.len() >= 2 {
                 match parts[1].to_lowercase().as_str() {
                     "datetime" => "datetime",
@@ -230,17 +394,92 @@ impl Bot {
             } else {
                 "gain" // default to gain
             };
-            submission::process_leaderboard_full(&self.db, &self.config, order_by)
+            match self.requested_track(&parts[1..]) {
+                Ok(track) => submission::process_leaderboard_full(&self.db, track, order_by),
+                Err(e) => e,
+            }
         } else if content == "all submits" && is_teacher {
             info!("Processing all submits command (teacher)");
-            submission::process_all_submits(&self.db)
+            submission::process_all_submits(&self.db, &self.tracks)
         } else if content == "no submits" && is_teacher {
             info!("Processing no submits command (teacher)");
             submission::process_no_submits(&self.db, &self.client, &self.config).await
+        } else if content.starts_with("metrics") && is_teacher {
+            info!("Processing metrics command (teacher)");
+            match content.split_whitespace().nth(1).and_then(|id| id.parse().ok()) {
+                Some(id) => submission::process_metrics(id, &self.db, &self.tracks),
+                None => "❌ Uso: metrics <id_envío>".to_string(),
+            }
+        } else if content.starts_with("curve") && is_teacher {
+            info!("Processing curve command (teacher)");
+            match content.split_whitespace().nth(1).and_then(|id| id.parse().ok()) {
+                Some(id) => submission::process_gain_curve(id, &self.db, &self.tracks),
+                None => "❌ Uso: curve <id_envío>".to_string(),
+            }
+        } else if content.starts_with("significance") && is_teacher {
+            info!("Processing significance command (teacher)");
+            let ids: Vec<i64> = content
+                .split_whitespace()
+                .skip(1)
+                .filter_map(|id| id.parse().ok())
+                .collect();
+            match ids[..] {
+                // The paired bootstrap scores both submissions on every resample
+                [first, second] => {
+                    let db = self.db.clone();
+                    let tracks = self.tracks.clone();
+                    self.reply_later(&sender_email, move || {
+                        submission::process_significance(first, second, &db, &tracks)
+                    })
+                }
+                _ => "❌ Uso: significance <id_envío_1> <id_envío_2>".to_string(),
+            }
+        } else if content.starts_with("ensemble") && is_teacher {
+            info!("Processing ensemble command (teacher)");
+            let mut args = content.split_whitespace().skip(1);
+            let rule = args.next().map(Rule::parse);
+            let ids: Result<Vec<i64>, String> = args
+                .map(|id| id.parse().map_err(|_| format!("❌ ID de envío inválido: `{}`", id)))
+                .collect();
+            match (rule, ids) {
+                (Some(Err(e)), _) => format!("❌ {}", e),
+                (_, Err(e)) => e,
+                (Some(Ok(rule)), Ok(ids)) if ids.len() >= 2 => {
+                    submission::process_ensemble(&ids, rule, &self.db, &self.tracks)
+                }
+                _ => "❌ Uso: ensemble <majority|union|intersection|k-of-n> <id1> <id2> [...]".to_string(),
+            }
+        } else if content.starts_with("calibration") && is_teacher {
+            info!("Processing calibration command (teacher)");
+            let user_name = self.extract_mentioned_user_name(&message.content);
+            submission::process_calibration(user_name.as_deref(), &self.db, &self.config)
+        } else if (content == "baselines" || content.starts_with("baselines ")) && is_teacher {
+            info!("Processing baselines command (teacher)");
+            let parts: Vec<&str> = content.split_whitespace().collect();
+            match self.requested_track(&parts[1..]) {
+                Ok(track) => submission::process_references(track),
+                Err(e) => e,
+            }
+        } else if content == "rescore" && is_teacher {
+            info!("Processing rescore command (teacher)");
+            match self.reload() {
+                Ok(()) => {
+                    let db = self.db.clone();
+                    let tracks = self.tracks.clone();
+                    self.reply_later(&sender_email, move || {
+                        tracks
+                            .iter()
+                            .map(|track| submission::process_rescore(&db, track))
+                            .collect::<Vec<_>>()
+                            .join("\n\n")
+                    })
+                }
+                Err(e) => format!("❌ Error recargando configuración o dataset maestro: {}", e),
+            }
         } else if content.starts_with("user submits") && is_teacher {
             info!("Processing user submits command (teacher)");
             if let Some(user_name) = self.extract_mentioned_user_name(&message.content) {
-                submission::process_user_submits(&user_name, &self.db)
+                submission::process_user_submits(&user_name, &self.db, &self.tracks)
             } else {
                 "❌ Uso: user submits @usuario (usa la mención de Zulip)".to_string()
             }
@@ -265,6 +504,50 @@ impl Bot {
         }
     }
 
+    /// Runs `job` on the blocking thread pool and sends its result to
+    /// `recipient` when it finishes, so the bot keeps answering meanwhile.
+    /// Returns the immediate reply.
+    fn reply_later(&self, recipient: &str, job: impl FnOnce() -> String + Send + 'static) -> String {
+        let client = self.client.clone();
+        let recipient = recipient.to_string();
+        tokio::spawn(async move {
+            let response = tokio::task::spawn_blocking(job)
+                .await
+                .unwrap_or_else(|e| format!("❌ Error en el cálculo: {}", e));
+            if let Err(e) = client.send_message(&recipient, &response).await {
+                error!("❌ Error sending message to {}: {}", recipient, e);
+            }
+        });
+        "⏳ Calculando en segundo plano, te respondo cuando termine".to_string()
+    }
+
+    /// Track named among `args`, or the main one when none is named.
+    fn requested_track(&self, args: &[&str]) -> Result<&Track, String> {
+        let named: Vec<&str> = args
+            .iter()
+            .copied()
+            .filter(|arg| !matches!(arg.to_lowercase().as_str(), "gain" | "datetime"))
+            .collect();
+        match named.first() {
+            None => Ok(&self.tracks[0]),
+            Some(name) => track::find(&self.tracks, name).ok_or_else(|| {
+                format!(
+                    "❌ No existe el track '{}'. Tracks disponibles: {}",
+                    name,
+                    self.track_list()
+                )
+            }),
+        }
+    }
+
+    fn track_list(&self) -> String {
+        self.tracks
+            .iter()
+            .map(|t| t.name.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
     fn extract_mentioned_user_name(&self, content: &str) -> Option<String> {
     let re = Regex::new(r"@\*\*([\w|\s]+)\*\*").ok()?;
 
@@ -280,6 +563,52 @@ impl Bot {
     }
 }
 
+    /// Zulip user `(id, name)` of the first mention in `content`. A
+    /// `@**Nombre|id**` mention carries the id; a plain one must match a single user.
+    async fn resolve_mentioned_user(&self, content: &str) -> Result<(i64, String), String> {
+        let mention = self
+            .extract_mentioned_user_name(content)
+            .ok_or_else(|| "❌ Uso: grant @usuario <cantidad> (usa la mención de Zulip)".to_string())?;
+        if let Some((name, id)) = mention.rsplit_once('|') {
+            if let Ok(id) = id.trim().parse() {
+                return Ok((id, name.trim().to_string()));
+            }
+        }
+
+        let users = self
+            .client
+            .get_all_users()
+            .await
+            .map_err(|e| format!("❌ Error obteniendo usuarios de Zulip: {}", e))?;
+        let matches: Vec<_> = users.iter().filter(|user| user.full_name == mention.trim()).collect();
+        match matches[..] {
+            [user] => Ok((user.user_id, user.full_name.clone())),
+            [] => Err(format!("❌ No encontré al usuario {} en Zulip", mention)),
+            _ => Err(format!(
+                "❌ Hay varios usuarios llamados {}; usa la mención completa `@**{}|id**`",
+                mention, mention
+            )),
+        }
+    }
+
+    fn csv_format_help(&self) -> String {
+        match (&self.config.competition.task, &self.config.class_gain_matrix) {
+            (TaskType::Multiclass, Some(matrix)) => format!(
+                "2 columnas `id,class` con la clase predicha ({}); los IDs ausentes se asumen `{}`",
+                matrix.classes.join(", "),
+                matrix.classes[matrix.default_class_index()]
+            ),
+            (TaskType::Regression, _) => {
+                "2 columnas `id,value` con una predicción numérica para cada ID del dataset".to_string()
+            }
+            _ => format!(
+                "1 columna con los IDs que predices como positivos (sin encabezado), \
+                o `id,probability` para que el bot aplique el corte (por defecto {})",
+                self.config.submissions.default_cutoff
+            ),
+        }
+    }
+
     fn get_help_message(&self, is_teacher: bool) -> String {
         let comp = &self.config.competition;
 
@@ -288,29 +617,53 @@ impl Bot {
                 "🤖 **DosEsfingesBot - Ayuda para Profesores**\n\n\
                 **Competencia:** {}\n\
                 **Descripción:** {}\n\
-                **Fecha límite:** {}\n\n\
+                **Fecha límite:** {}\n\
+                **Tracks:** {}\n\n\
                 **Comandos disponibles:**\n\
                 • `duplicates` - Listar envíos duplicados\n\
-                • `leaderboard [gain|datetime]` - Leaderboard completo con estadísticas (ordenado por ganancia o fecha)\n\
+                • `verify-storage` - Verificar que los archivos guardados estén completos e intactos\n\
+                • `leaderboard [gain|datetime] [track]` - Leaderboard completo con estadísticas (ordenado por ganancia o fecha)\n\
+                • `leaderboard estimators` - Ranking de quienes mejor estiman la ganancia de sus modelos\n\
                 • `all submits` - Ver todos los envíos del sistema\n\
                 • `no submits` - USAR POCO. Ver usuarios sin envíos ordenados por última conexión\n\
                 • `user submits @usuario` - Ver envíos de un usuario (usa mención @)\n\
+                • `grant @usuario <n>` - Otorgar n envíos extra hoy y sobre la cuota total\n\
+                • `curve <id>` - Curva ganancia vs. corte de un envío con probabilidades\n\
+                • `metrics <id>` - Métricas principal y secundarias de un envío\n\
+                • `significance <id1> <id2>` - p-valor de bootstrap pareado entre dos envíos\n\
+                • `ensemble <majority|union|intersection|k-of-n> <id1> <id2> [...]` - Puntaje de combinar envíos (no se guarda)\n\
+                • `calibration [@usuario]` - Ganancia esperada vs. real por estudiante (MAE, sesgo, correlación)\n\
+                • `baselines [track]` - Oráculo y puntajes de referencia (nada, todo, azar)\n\
+                • `rescore` - Recargar config y dataset maestro y recalcular todos los envíos\n\
                 • `help` - Mostrar esta ayuda\n\n\
                 **Nota:** Los profesores no pueden enviar submissions.",
-                comp.name, comp.description, comp.deadline
+                comp.name, comp.description, comp.deadline, self.track_list()
             )
         } else {
             format!(
                 "🤖 **DosEsfingesBot - Ayuda para Estudiantes**\n\n\
                 **Competencia:** {}\n\
                 **Descripción:** {}\n\
-                **Fecha límite:** {}\n\n\
+                **Fecha límite:** {}\n\
+                **Tracks:** {}\n\n\
                 **Comandos disponibles:**\n\
-                • `submit <nombre> <ganancia_esperada>` - Enviar modelo (adjuntar CSV)\n\
+                • `submit <nombre> <ganancia_esperada> [corte] [track=<track>] [descripción]` - Enviar modelo (adjuntar CSV; sin `track=` va a `{}`). \
+                Usa comillas para nombres con espacios: `submit \"xgboost tuned\" 1200 --desc más árboles`. \
+                Para varios archivos, adjunta cada uno en su línea con su nombre y ganancia (o llámalo `nombre_ganancia.csv`)\n\
+                • `rename <id> <nuevo nombre>` - Renombrar un envío; cada nombre se usa una sola vez\n\
                 • `list submits` - Listar tus envíos\n\
+                • `select <id> [<id>]` - Elegir hasta {} envíos por track para el ranking final (sin IDs, ver la elección)\n\
+                • `withdraw <id> <motivo>` - Retirar un envío antes de la fecha límite (ya no compite ni cuenta en la cuota)\n\
+                • `quota` - Ver cuántos envíos te quedan hoy y en total\n\
                 • `help` - Mostrar esta ayuda\n\n\
-                **Formato CSV:** 1 columna con los IDs que predices como positivos (sin encabezado)",
-                comp.name, comp.description, comp.deadline
+                **Formato CSV:** {}",
+                comp.name,
+                comp.description,
+                comp.deadline,
+                self.track_list(),
+                self.tracks[0].name,
+                comp.max_selected,
+                self.csv_format_help()
             )
         }
     }
@@ -1,22 +1,257 @@
 use anyhow::{Context, Result};
 use csv::ReaderBuilder;
-use std::collections::HashSet;
 use std::fs::File;
 
+use crate::config::{BotConfig, IdType, TaskType};
+use crate::dense::{BitSet, IdIndex};
+use crate::models::Id;
+
+/// Partición del dataset maestro a la que pertenece cada fila.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Split {
+    Public,
+    Private,
+}
+
+impl Split {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "public" | "publico" | "público" => Some(Split::Public),
+            "private" | "privado" => Some(Split::Private),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Split::Public => "public",
+            Split::Private => "private",
+        }
+    }
+}
+
+/// Celda de la matriz de confusión binaria.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cell {
+    Tp,
+    Tn,
+    Fp,
+    Fn,
+}
+
+impl Cell {
+    pub fn of(is_positive: bool, predicted_positive: bool) -> Self {
+        match (is_positive, predicted_positive) {
+            (true, true) => Cell::Tp,
+            (true, false) => Cell::Fn,
+            (false, true) => Cell::Fp,
+            (false, false) => Cell::Tn,
+        }
+    }
+
+    fn index(self) -> usize {
+        match self {
+            Cell::Tp => 0,
+            Cell::Tn => 1,
+            Cell::Fp => 2,
+            Cell::Fn => 3,
+        }
+    }
+
+    fn suffix(self) -> &'static str {
+        match self {
+            Cell::Tp => "tp",
+            Cell::Tn => "tn",
+            Cell::Fp => "fp",
+            Cell::Fn => "fn",
+        }
+    }
+}
+
+/// Aporte de una o más filas a una celda: `fixed` es la suma de importes
+/// propios de cada fila y `weight` la suma de pesos de las filas que usan
+/// el valor de `gain_matrix`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CellAmount {
+    pub fixed: f64,
+    pub weight: f64,
+}
+
+/// Importes acumulados por celda de la matriz de confusión.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CellAmounts {
+    cells: [CellAmount; 4],
+}
+
+impl CellAmounts {
+    pub fn get(&self, cell: Cell) -> CellAmount {
+        self.cells[cell.index()]
+    }
+
+    pub fn add(&mut self, cell: Cell, amount: CellAmount) {
+        let total = &mut self.cells[cell.index()];
+        total.fixed += amount.fixed;
+        total.weight += amount.weight;
+    }
+
+    pub fn remove(&mut self, cell: Cell, amount: CellAmount) {
+        let total = &mut self.cells[cell.index()];
+        total.fixed -= amount.fixed;
+        total.weight -= amount.weight;
+    }
+}
+
+/// Columnas opcionales con importes por fila: `weight` multiplica la celda
+/// de `gain_matrix`; `gain_if_<celda>` / `cost_if_<celda>` la reemplazan.
+#[derive(Debug, Clone, Copy, Default)]
+struct RowGains {
+    weight: Option<f64>,
+    cells: [Option<f64>; 4],
+}
+
+#[derive(Debug, Default)]
+struct RowGainColumns {
+    weight: Option<usize>,
+    /// `(columna, signo)` por celda; los costos se guardan como ganancias negativas
+    cells: [Option<(usize, f64)>; 4],
+}
+
+impl RowGainColumns {
+    fn from_headers(headers: &csv::StringRecord) -> Self {
+        let find = |name: &str| {
+            headers
+                .iter()
+                .position(|h| h.trim().eq_ignore_ascii_case(name))
+        };
+
+        let mut columns = RowGainColumns {
+            weight: find("weight"),
+            ..Default::default()
+        };
+        for cell in [Cell::Tp, Cell::Tn, Cell::Fp, Cell::Fn] {
+            columns.cells[cell.index()] = find(&format!("gain_if_{}", cell.suffix()))
+                .map(|i| (i, 1.0))
+                .or_else(|| find(&format!("cost_if_{}", cell.suffix())).map(|i| (i, -1.0)));
+        }
+        columns
+    }
+
+    fn is_empty(&self) -> bool {
+        self.weight.is_none() && self.cells.iter().all(Option::is_none)
+    }
+
+    fn read(&self, id: &Id, record: &csv::StringRecord) -> Result<RowGains> {
+        let parse = |index: usize| -> Result<Option<f64>> {
+            match record.get(index).map(str::trim) {
+                None | Some("") => Ok(None),
+                Some(value) => value
+                    .parse()
+                    .ok()
+                    .filter(|v: &f64| v.is_finite())
+                    .map(Some)
+                    .with_context(|| format!("Invalid amount for ID {}: '{}'", id, value)),
+            }
+        };
+
+        let mut gains = RowGains {
+            weight: self.weight.map(parse).transpose()?.flatten(),
+            ..Default::default()
+        };
+        for (slot, column) in gains.cells.iter_mut().zip(&self.cells) {
+            if let Some((index, sign)) = column {
+                *slot = parse(*index)?.map(|v| v * sign);
+            }
+        }
+        Ok(gains)
+    }
+}
+
+/// Cómo interpretar la segunda columna del dataset maestro.
+enum TargetKind<'a> {
+    Binary,
+    Classes(&'a [String]),
+    Numeric,
+}
+
+/// Datos de una fila mientras se lee el CSV, antes de ordenar por ID.
+struct RawRow {
+    id: Id,
+    positive: bool,
+    class: u32,
+    target: f64,
+    private: bool,
+    gains: RowGains,
+}
+
+/// Dataset maestro indexado por fila: cada ID tiene un número de fila denso
+/// (ver [`IdIndex`]) y los datos por fila viven en vectores y bitsets.
 pub struct MasterData {
-    all_ids: HashSet<i32>,
-    positive_ids: HashSet<i32>,
+    index: IdIndex,
+    positives: BitSet,
+    /// Filas de cada partición, solo si `has_split`
+    public: BitSet,
+    private: BitSet,
+    has_split: bool,
+    /// Índice de clase de cada fila, solo en competencias multiclase
+    classes: Vec<u32>,
+    class_labels: Vec<String>,
+    /// Valor objetivo de cada fila, solo en competencias de regresión
+    targets: Vec<f64>,
+    /// Importes por fila, solo si el dataset trae columnas de importes
+    row_gains: Vec<RowGains>,
 }
 
 impl MasterData {
-    pub fn load(path: &str) -> Result<Self> {
+    /// Carga el dataset maestro según el tipo de competencia configurado.
+    pub fn load_for(config: &BotConfig) -> Result<Self> {
+        let path = &config.master_data.path;
+        let id_type = config.master_data.id_type;
+        match (&config.competition.task, &config.class_gain_matrix) {
+            (TaskType::Multiclass, Some(matrix)) => {
+                Self::load_multiclass(path, &matrix.classes, id_type)
+            }
+            (TaskType::Multiclass, None) => {
+                anyhow::bail!("Multiclass competitions require a class_gain_matrix")
+            }
+            (TaskType::Regression, _) => Self::load_regression(path, id_type),
+            (TaskType::Binary, _) => Self::load(path, id_type),
+        }
+    }
+
+    /// Carga `id,clase_binaria[,split]`. Sin columna `split`, todas las
+    /// filas cuentan tanto para el leaderboard público como para el privado.
+    /// Acepta además columnas de importes por fila (`weight`, `gain_if_tp`,
+    /// `cost_if_fp`, ...) identificadas por su encabezado.
+    pub fn load(path: &str, id_type: IdType) -> Result<Self> {
+        Self::read(path, TargetKind::Binary, id_type)
+    }
+
+    /// Carga `id,clase[,split]`, donde `clase` debe ser una de `classes`.
+    pub fn load_multiclass(path: &str, classes: &[String], id_type: IdType) -> Result<Self> {
+        Self::read(path, TargetKind::Classes(classes), id_type)
+    }
+
+    /// Carga `id,target[,split]` con un objetivo numérico.
+    pub fn load_regression(path: &str, id_type: IdType) -> Result<Self> {
+        Self::read(path, TargetKind::Numeric, id_type)
+    }
+
+    fn read(path: &str, kind: TargetKind, id_type: IdType) -> Result<Self> {
         let file = File::open(path)
             .with_context(|| format!("Failed to open master data file: {}", path))?;
 
         let mut reader = ReaderBuilder::new().has_headers(true).from_reader(file);
 
-        let mut all_ids = HashSet::new();
-        let mut positive_ids = HashSet::new();
+        let mut rows = Vec::new();
+
+        let headers = reader.headers()?.clone();
+        let gain_columns = match kind {
+            TargetKind::Binary => RowGainColumns::from_headers(&headers),
+            _ => RowGainColumns::default(),
+        };
+        // Only a column named `split` splits the rows; other extra columns are ignored
+        let split_column = headers.iter().position(|h| h.trim().eq_ignore_ascii_case("split"));
+        let has_split = split_column.is_some();
 
         for result in reader.records() {
             let record = result?;
@@ -27,48 +262,203 @@ impl MasterData {
                 );
             }
 
-            let id: i32 = record[0]
-                .parse()
+            let id = Id::parse(&record[0], id_type)
                 .with_context(|| format!("Invalid ID: {}", &record[0]))?;
 
-            let clase: i32 = record[1]
-                .parse()
-                .with_context(|| format!("Invalid clase_binaria: {}", &record[1]))?;
+            let mut row = RawRow {
+                id: id.clone(),
+                positive: false,
+                class: 0,
+                target: 0.0,
+                private: false,
+                gains: RowGains::default(),
+            };
+
+            match kind {
+                TargetKind::Classes(classes) => {
+                    let label = record[1].trim();
+                    let index = classes
+                        .iter()
+                        .position(|c| c == label)
+                        .with_context(|| format!("Unknown class for ID {}: '{}'", id, label))?;
+                    row.class = index as u32;
+                }
+                TargetKind::Numeric => {
+                    let target: f64 = record[1]
+                        .trim()
+                        .parse()
+                        .ok()
+                        .filter(|t: &f64| t.is_finite())
+                        .with_context(|| format!("Invalid target for ID {}: {}", id, &record[1]))?;
+                    row.target = target;
+                }
+                TargetKind::Binary => {
+                    let clase: i32 = record[1]
+                        .parse()
+                        .with_context(|| format!("Invalid clase_binaria: {}", &record[1]))?;
 
-            all_ids.insert(id);
+                    row.positive = clase == 1;
+                }
+            }
 
-            if clase == 1 {
-                positive_ids.insert(id);
+            if !gain_columns.is_empty() {
+                row.gains = gain_columns.read(&id, &record)?;
             }
+
+            if let Some(column) = split_column {
+                let value = record.get(column).unwrap_or("");
+                let split = Split::parse(value)
+                    .with_context(|| format!("Invalid split for ID {}: '{}'", id, value))?;
+                row.private = split == Split::Private;
+            }
+
+            rows.push(row);
         }
 
+        // Rows are numbered in ID order
+        rows.sort_unstable_by(|a, b| a.id.cmp(&b.id));
+        if let Some(pair) = rows.windows(2).find(|pair| pair[0].id == pair[1].id) {
+            anyhow::bail!("Duplicated ID in master data: {}", pair[0].id);
+        }
+
+        let mut positives = BitSet::new(rows.len());
+        let mut private = BitSet::new(rows.len());
+        for (i, row) in rows.iter().enumerate() {
+            if row.positive {
+                positives.insert(i);
+            }
+            if row.private {
+                private.insert(i);
+            }
+        }
+        let classes = match kind {
+            TargetKind::Classes(_) => rows.iter().map(|row| row.class).collect(),
+            _ => Vec::new(),
+        };
+        let targets = match kind {
+            TargetKind::Numeric => rows.iter().map(|row| row.target).collect(),
+            _ => Vec::new(),
+        };
+        let row_gains = if gain_columns.is_empty() {
+            Vec::new()
+        } else {
+            rows.iter().map(|row| row.gains).collect()
+        };
+        // IDs are moved into the interned index last, once nothing else needs the rows
+        let index = IdIndex::new(rows.into_iter().map(|row| row.id).collect());
+
         Ok(Self {
-            all_ids,
-            positive_ids,
+            index,
+            positives,
+            public: if has_split {
+                private.complement()
+            } else {
+                BitSet::new(0)
+            },
+            private: if has_split { private } else { BitSet::new(0) },
+            has_split,
+            classes,
+            class_labels: match kind {
+                TargetKind::Classes(classes) => classes.to_vec(),
+                _ => Vec::new(),
+            },
+            targets,
+            row_gains,
         })
     }
 
-    pub fn validate_ids(&self, predicted_ids: &HashSet<i32>) -> Vec<i32> {
-        predicted_ids
-            .iter()
-            .filter(|id| !self.all_ids.contains(id))
-            .copied()
-            .collect()
+    /// IDs de `predicted_ids` que no existen en el dataset.
+    pub fn validate_ids<'a>(&self, predicted_ids: impl IntoIterator<Item = &'a Id>) -> Vec<Id> {
+        self.index.unknown(predicted_ids)
     }
 
-    pub fn all_ids(&self) -> &HashSet<i32> {
-        &self.all_ids
+    /// ID de la fila; las filas siguen el orden de los IDs.
+    pub fn id(&self, row: usize) -> Id {
+        self.index.id(row)
     }
 
-    pub fn positive_ids(&self) -> &HashSet<i32> {
-        &self.positive_ids
+    pub fn index(&self) -> &IdIndex {
+        &self.index
+    }
+
+    /// Número de fila de `id`, si existe en el dataset.
+    pub fn row_of(&self, id: &Id) -> Option<usize> {
+        self.index.row_of(id)
+    }
+
+    /// Filas con clase positiva.
+    pub fn positives(&self) -> &BitSet {
+        &self.positives
+    }
+
+    pub fn is_positive(&self, row: usize) -> bool {
+        self.positives.contains(row)
     }
 
     pub fn total_count(&self) -> usize {
-        self.all_ids.len()
+        self.index.len()
     }
 
     pub fn positive_count(&self) -> usize {
-        self.positive_ids.len()
+        self.positives.count()
+    }
+
+    /// Clase real de la fila en competencias multiclase.
+    pub fn class_of(&self, row: usize) -> Option<usize> {
+        self.classes.get(row).map(|c| *c as usize)
+    }
+
+    /// Indica si el dataset maestro trae importes por fila.
+    pub fn has_row_gains(&self) -> bool {
+        !self.row_gains.is_empty()
+    }
+
+    /// Aporte de la fila a `cell`: su importe propio si lo tiene, o su peso
+    /// (1 por defecto) para multiplicar el valor de `gain_matrix`.
+    pub fn row_amount(&self, row: usize, cell: Cell) -> CellAmount {
+        let gains = self.row_gains.get(row).copied().unwrap_or_default();
+        match gains.cells[cell.index()] {
+            Some(fixed) => CellAmount { fixed, weight: 0.0 },
+            None => CellAmount {
+                fixed: 0.0,
+                weight: gains.weight.unwrap_or(1.0),
+            },
+        }
+    }
+
+    /// Valor objetivo de la fila en competencias de regresión.
+    pub fn target_of(&self, row: usize) -> Option<f64> {
+        self.targets.get(row).copied()
+    }
+
+    /// Etiquetas de clase (vacío en competencias binarias).
+    pub fn class_labels(&self) -> &[String] {
+        &self.class_labels
+    }
+
+    /// Indica si el dataset maestro define una partición pública/privada.
+    pub fn has_split(&self) -> bool {
+        self.has_split
+    }
+
+    /// Filas de la partición dada, o `None` cuando cuentan todas (sin
+    /// partición definida, todas las filas pertenecen a ambas).
+    pub fn split_rows(&self, split: Option<Split>) -> Option<&BitSet> {
+        match (self.has_split, split) {
+            (true, Some(Split::Public)) => Some(&self.public),
+            (true, Some(Split::Private)) => Some(&self.private),
+            _ => None,
+        }
+    }
+
+    /// Indica si la fila cuenta para la partición dada.
+    pub fn in_split(&self, row: usize, split: Split) -> bool {
+        self.split_rows(Some(split))
+            .is_none_or(|rows| rows.contains(row))
+    }
+
+    pub fn split_count(&self, split: Split) -> usize {
+        self.split_rows(Some(split))
+            .map_or(self.total_count(), BitSet::count)
     }
 }
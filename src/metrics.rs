@@ -0,0 +1,670 @@
+use crate::config::{BotConfig, ClassGainMatrix, GainMatrix, MetricConfig};
+use crate::dense::BitSet;
+use crate::master_data::{Cell, CellAmounts, MasterData, Split};
+use crate::models::{GainResult, Predictions};
+
+/// Everything a metric may look at for one submission over one subset of the master data.
+pub struct Evaluation {
+    pub tp: i32,
+    pub tn: i32,
+    pub fp: i32,
+    pub fn_: i32,
+    /// `(score, is_positive)` for every row in scope, only for `id,probability` files.
+    /// Rows missing from the file get a score of 0.
+    pub scored: Option<Vec<(f64, bool)>>,
+    /// `confusion[actual][predicted]` in multiclass competitions, where the
+    /// binary counts above stay at zero.
+    pub confusion: Option<Vec<Vec<i32>>>,
+    /// `(predicted, actual)` for every row in scope in regression competitions.
+    pub regression: Option<Vec<(f64, f64)>>,
+    /// Per-row amounts summed by cell, when the master data carries them.
+    pub amounts: Option<CellAmounts>,
+}
+
+impl Evaluation {
+    pub fn total(&self) -> i32 {
+        match &self.confusion {
+            Some(confusion) => confusion.iter().flatten().sum(),
+            None => self.tp + self.tn + self.fp + self.fn_,
+        }
+    }
+}
+
+pub trait Metric: Send + Sync {
+    /// Stable identifier, used as the key in the database.
+    fn name(&self) -> String;
+
+    fn higher_is_better(&self) -> bool {
+        true
+    }
+
+    /// Whether the metric can only be computed from `id,probability` submissions.
+    fn requires_scores(&self) -> bool {
+        false
+    }
+
+    /// `None` when the metric does not apply to this submission.
+    fn evaluate(&self, evaluation: &Evaluation) -> Option<f64>;
+}
+
+pub struct GainMatrixMetric {
+    gain_matrix: GainMatrix,
+    class_gain_matrix: Option<ClassGainMatrix>,
+}
+
+impl Metric for GainMatrixMetric {
+    fn name(&self) -> String {
+        "gain".to_string()
+    }
+
+    fn evaluate(&self, e: &Evaluation) -> Option<f64> {
+        match (&e.confusion, &self.class_gain_matrix) {
+            (Some(confusion), Some(matrix)) => Some(matrix.evaluate(confusion)),
+            (Some(_), None) => None,
+            (None, _) => Some(match &e.amounts {
+                Some(amounts) => self.gain_matrix.evaluate_amounts(amounts),
+                None => self.gain_matrix.evaluate(e.tp, e.tn, e.fp, e.fn_),
+            }),
+        }
+    }
+}
+
+pub struct F1;
+
+impl Metric for F1 {
+    fn name(&self) -> String {
+        "f1".to_string()
+    }
+
+    /// Macro-averaged over the classes in multiclass competitions.
+    fn evaluate(&self, e: &Evaluation) -> Option<f64> {
+        let f1 = |tp: i32, fp: i32, fn_: i32| {
+            let denominator = 2 * tp + fp + fn_;
+            if denominator == 0 {
+                0.0
+            } else {
+                2.0 * tp as f64 / denominator as f64
+            }
+        };
+
+        match &e.confusion {
+            Some(confusion) => {
+                let n = confusion.len();
+                if n == 0 {
+                    return None;
+                }
+                let total: f64 = (0..n)
+                    .map(|c| {
+                        let tp = confusion[c][c];
+                        let fn_: i32 = confusion[c].iter().sum::<i32>() - tp;
+                        let fp: i32 = confusion.iter().map(|row| row[c]).sum::<i32>() - tp;
+                        f1(tp, fp, fn_)
+                    })
+                    .sum();
+                Some(total / n as f64)
+            }
+            None => Some(f1(e.tp, e.fp, e.fn_)),
+        }
+    }
+}
+
+pub struct Accuracy;
+
+impl Metric for Accuracy {
+    fn name(&self) -> String {
+        "accuracy".to_string()
+    }
+
+    fn evaluate(&self, e: &Evaluation) -> Option<f64> {
+        if e.total() == 0 {
+            return None;
+        }
+        let correct = match &e.confusion {
+            Some(confusion) => (0..confusion.len()).map(|c| confusion[c][c]).sum(),
+            None => e.tp + e.tn,
+        };
+        Some(correct as f64 / e.total() as f64)
+    }
+}
+
+pub struct PrecisionAtK {
+    k: usize,
+}
+
+impl Metric for PrecisionAtK {
+    fn name(&self) -> String {
+        format!("precision@{}", self.k)
+    }
+
+    fn requires_scores(&self) -> bool {
+        true
+    }
+
+    fn evaluate(&self, e: &Evaluation) -> Option<f64> {
+        let scored = e.scored.as_ref()?;
+        let k = self.k.min(scored.len());
+        if k == 0 {
+            return None;
+        }
+
+        let mut sorted = scored.clone();
+        sorted.sort_by(|a, b| b.0.total_cmp(&a.0));
+        let hits = sorted[..k].iter().filter(|(_, positive)| *positive).count();
+        Some(hits as f64 / k as f64)
+    }
+}
+
+pub struct RocAuc;
+
+impl Metric for RocAuc {
+    fn name(&self) -> String {
+        "roc_auc".to_string()
+    }
+
+    fn requires_scores(&self) -> bool {
+        true
+    }
+
+    /// Mann-Whitney U statistic, with tied scores sharing their average rank.
+    fn evaluate(&self, e: &Evaluation) -> Option<f64> {
+        let scored = e.scored.as_ref()?;
+        let positives = scored.iter().filter(|(_, positive)| *positive).count();
+        let negatives = scored.len() - positives;
+        if positives == 0 || negatives == 0 {
+            return None;
+        }
+
+        let mut sorted = scored.clone();
+        sorted.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        let mut positive_rank_sum = 0.0;
+        let mut i = 0;
+        while i < sorted.len() {
+            let mut j = i;
+            while j < sorted.len() && sorted[j].0 == sorted[i].0 {
+                j += 1;
+            }
+            // Ranks are 1-based: rows i..j share the average of i+1..=j
+            let average_rank = (i + 1 + j) as f64 / 2.0;
+            let tied_positives = sorted[i..j].iter().filter(|(_, positive)| *positive).count();
+            positive_rank_sum += average_rank * tied_positives as f64;
+            i = j;
+        }
+
+        let p = positives as f64;
+        let u = positive_rank_sum - p * (p + 1.0) / 2.0;
+        Some(u / (p * negatives as f64))
+    }
+}
+
+pub struct LogLoss;
+
+impl Metric for LogLoss {
+    fn name(&self) -> String {
+        "log_loss".to_string()
+    }
+
+    fn higher_is_better(&self) -> bool {
+        false
+    }
+
+    fn requires_scores(&self) -> bool {
+        true
+    }
+
+    fn evaluate(&self, e: &Evaluation) -> Option<f64> {
+        const EPS: f64 = 1e-15;
+
+        let scored = e.scored.as_ref()?;
+        if scored.is_empty() {
+            return None;
+        }
+
+        let total: f64 = scored
+            .iter()
+            .map(|(score, positive)| {
+                let p = score.clamp(EPS, 1.0 - EPS);
+                if *positive {
+                    -p.ln()
+                } else {
+                    -(1.0 - p).ln()
+                }
+            })
+            .sum();
+        Some(total / scored.len() as f64)
+    }
+}
+
+pub struct Rmse;
+
+impl Metric for Rmse {
+    fn name(&self) -> String {
+        "rmse".to_string()
+    }
+
+    fn higher_is_better(&self) -> bool {
+        false
+    }
+
+    fn evaluate(&self, e: &Evaluation) -> Option<f64> {
+        let pairs = e.regression.as_ref().filter(|p| !p.is_empty())?;
+        let mse = pairs.iter().map(|(p, a)| (p - a).powi(2)).sum::<f64>() / pairs.len() as f64;
+        Some(mse.sqrt())
+    }
+}
+
+pub struct Mae;
+
+impl Metric for Mae {
+    fn name(&self) -> String {
+        "mae".to_string()
+    }
+
+    fn higher_is_better(&self) -> bool {
+        false
+    }
+
+    fn evaluate(&self, e: &Evaluation) -> Option<f64> {
+        let pairs = e.regression.as_ref().filter(|p| !p.is_empty())?;
+        Some(pairs.iter().map(|(p, a)| (p - a).abs()).sum::<f64>() / pairs.len() as f64)
+    }
+}
+
+pub struct Mape;
+
+impl Metric for Mape {
+    fn name(&self) -> String {
+        "mape".to_string()
+    }
+
+    fn higher_is_better(&self) -> bool {
+        false
+    }
+
+    /// Rows with a zero target are left out, since their percentage error is undefined.
+    fn evaluate(&self, e: &Evaluation) -> Option<f64> {
+        let errors: Vec<f64> = e
+            .regression
+            .as_ref()?
+            .iter()
+            .filter(|(_, a)| *a != 0.0)
+            .map(|(p, a)| ((p - a) / a).abs())
+            .collect();
+        if errors.is_empty() {
+            return None;
+        }
+        Some(100.0 * errors.iter().sum::<f64>() / errors.len() as f64)
+    }
+}
+
+pub struct MonetaryLoss {
+    over_cost: f64,
+    under_cost: f64,
+}
+
+impl Metric for MonetaryLoss {
+    fn name(&self) -> String {
+        "monetary_loss".to_string()
+    }
+
+    fn higher_is_better(&self) -> bool {
+        false
+    }
+
+    fn evaluate(&self, e: &Evaluation) -> Option<f64> {
+        let pairs = e.regression.as_ref()?;
+        Some(
+            pairs
+                .iter()
+                .map(|(p, a)| {
+                    if p > a {
+                        (p - a) * self.over_cost
+                    } else {
+                        (a - p) * self.under_cost
+                    }
+                })
+                .sum(),
+        )
+    }
+}
+
+pub fn build(
+    config: &MetricConfig,
+    gain_matrix: &GainMatrix,
+    class_gain_matrix: Option<&ClassGainMatrix>,
+) -> Box<dyn Metric> {
+    match config {
+        MetricConfig::Gain => Box::new(GainMatrixMetric {
+            gain_matrix: gain_matrix.clone(),
+            class_gain_matrix: class_gain_matrix.cloned(),
+        }),
+        MetricConfig::F1 => Box::new(F1),
+        MetricConfig::PrecisionAtK { k } => Box::new(PrecisionAtK { k: *k }),
+        MetricConfig::RocAuc => Box::new(RocAuc),
+        MetricConfig::LogLoss => Box::new(LogLoss),
+        MetricConfig::Accuracy => Box::new(Accuracy),
+        MetricConfig::Rmse => Box::new(Rmse),
+        MetricConfig::Mae => Box::new(Mae),
+        MetricConfig::Mape => Box::new(Mape),
+        MetricConfig::MonetaryLoss {
+            over_cost,
+            under_cost,
+        } => Box::new(MonetaryLoss {
+            over_cost: *over_cost,
+            under_cost: *under_cost,
+        }),
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MetricValue {
+    pub name: String,
+    pub value: Option<f64>,
+}
+
+/// Primary metric (ranking, thresholds) plus the secondary ones reported to teachers.
+pub struct MetricSet {
+    primary: Box<dyn Metric>,
+    secondary: Vec<Box<dyn Metric>>,
+}
+
+impl MetricSet {
+    pub fn new(primary: Box<dyn Metric>, secondary: Vec<Box<dyn Metric>>) -> Self {
+        Self { primary, secondary }
+    }
+
+    pub fn from_config(config: &BotConfig) -> Self {
+        let class_gain_matrix = config.class_gain_matrix.as_ref();
+        Self::new(
+            build(&config.metrics.primary, &config.gain_matrix, class_gain_matrix),
+            config
+                .metrics
+                .secondary
+                .iter()
+                .filter(|m| **m != config.metrics.primary)
+                .map(|m| build(m, &config.gain_matrix, class_gain_matrix))
+                .collect(),
+        )
+    }
+
+    pub fn primary(&self) -> &dyn Metric {
+        self.primary.as_ref()
+    }
+
+    pub fn all(&self) -> impl Iterator<Item = &dyn Metric> {
+        std::iter::once(self.primary.as_ref()).chain(self.secondary.iter().map(|m| m.as_ref()))
+    }
+
+    /// Scores `predictions` over the whole dataset, or only over `split`.
+    /// `GainResult::gain` holds the value of the primary metric.
+    pub fn evaluate(
+        &self,
+        predictions: &Predictions,
+        cutoff: f64,
+        master_data: &MasterData,
+        split: Option<Split>,
+    ) -> (GainResult, Vec<MetricValue>) {
+        let dense = DensePredictions::new(predictions, cutoff, master_data);
+        self.evaluate_dense(&dense, master_data, split)
+    }
+
+    /// Same as [`MetricSet::evaluate`] for predictions already laid out over
+    /// the master data rows, to score several splits without rebuilding them.
+    pub fn evaluate_dense(
+        &self,
+        predictions: &DensePredictions,
+        master_data: &MasterData,
+        split: Option<Split>,
+    ) -> (GainResult, Vec<MetricValue>) {
+        let needs_scores = self.all().any(|metric| metric.requires_scores());
+        let evaluation = build_evaluation(predictions, master_data, Rows::Split(split), needs_scores);
+
+        let values: Vec<MetricValue> = self
+            .all()
+            .map(|metric| MetricValue {
+                name: metric.name(),
+                value: metric.evaluate(&evaluation),
+            })
+            .collect();
+
+        let result = GainResult {
+            // Only degenerate subsets (e.g. no positives for ROC-AUC) leave the primary metric undefined
+            gain: values[0].value.unwrap_or(0.0),
+            tp: evaluation.tp,
+            tn: evaluation.tn,
+            fp: evaluation.fp,
+            fn_: evaluation.fn_,
+            confusion: evaluation.confusion,
+        };
+
+        (result, values)
+    }
+}
+
+/// Predictions laid out over the master data rows (see [`crate::dense`]),
+/// built once per submission and cut-off and shared by every split and
+/// bootstrap resample.
+pub struct DensePredictions {
+    /// Rows predicted positive
+    positives: BitSet,
+    /// Score of every row (0 when missing), only for `id,probability` files
+    scores: Option<Vec<f64>>,
+    /// Predicted class of every row, only in multiclass competitions
+    classes: Option<Vec<u32>>,
+    /// Predicted value of every row (NaN when missing), only in regression competitions
+    values: Option<Vec<f64>>,
+}
+
+impl DensePredictions {
+    /// IDs unknown to the master data are ignored.
+    pub fn new(predictions: &Predictions, cutoff: f64, master_data: &MasterData) -> Self {
+        let index = master_data.index();
+        let n = index.len();
+        let mut dense = Self {
+            positives: BitSet::new(n),
+            scores: None,
+            classes: None,
+            values: None,
+        };
+
+        match predictions {
+            Predictions::Ids(ids) => dense.positives = index.bitset_of(ids),
+            Predictions::Scores(scores) => {
+                let mut dense_scores = vec![0.0; n];
+                for (id, score) in scores {
+                    if let Some(row) = index.row_of(id) {
+                        dense_scores[row] = *score;
+                        if *score >= cutoff {
+                            dense.positives.insert(row);
+                        }
+                    }
+                }
+                dense.scores = Some(dense_scores);
+            }
+            Predictions::Classes {
+                predicted,
+                default_class,
+            } => {
+                let mut classes = vec![*default_class as u32; n];
+                for (id, class) in predicted {
+                    if let Some(row) = index.row_of(id) {
+                        classes[row] = *class as u32;
+                        if class != default_class {
+                            dense.positives.insert(row);
+                        }
+                    }
+                }
+                dense.classes = Some(classes);
+            }
+            Predictions::Values(values) => {
+                let mut dense_values = vec![f64::NAN; n];
+                for (id, value) in values {
+                    if let Some(row) = index.row_of(id) {
+                        dense_values[row] = *value;
+                    }
+                }
+                dense.values = Some(dense_values);
+            }
+        }
+
+        dense
+    }
+
+    /// Plain binary predictions: the rows in `positives` are predicted positive.
+    pub fn from_positives(positives: BitSet) -> Self {
+        Self {
+            positives,
+            scores: None,
+            classes: None,
+            values: None,
+        }
+    }
+
+    /// Rows predicted positive.
+    pub fn positives(&self) -> &BitSet {
+        &self.positives
+    }
+
+    /// Binary predictions can be counted with bitsets alone unless a metric
+    /// needs the score of every row.
+    fn counts_only(&self, needs_scores: bool) -> bool {
+        self.classes.is_none() && self.values.is_none() && (self.scores.is_none() || !needs_scores)
+    }
+}
+
+/// Rows an evaluation covers: a split of the master data, or an explicit
+/// list of rows that may repeat (bootstrap resamples).
+enum Rows<'a> {
+    Split(Option<Split>),
+    List(&'a [usize]),
+}
+
+/// Scores `metric` on an explicit list of master-data rows, which may repeat
+/// (bootstrap resamples).
+pub fn evaluate_rows(
+    metric: &dyn Metric,
+    predictions: &DensePredictions,
+    master_data: &MasterData,
+    rows: &[usize],
+) -> Option<f64> {
+    metric.evaluate(&build_evaluation(
+        predictions,
+        master_data,
+        Rows::List(rows),
+        metric.requires_scores(),
+    ))
+}
+
+/// `scored` is only filled when `needs_scores`, since it is the costliest part
+/// of an evaluation.
+fn build_evaluation(
+    predictions: &DensePredictions,
+    master_data: &MasterData,
+    rows: Rows,
+    needs_scores: bool,
+) -> Evaluation {
+    let n_classes = master_data.class_labels().len();
+
+    let mut evaluation = Evaluation {
+        tp: 0,
+        tn: 0,
+        fp: 0,
+        fn_: 0,
+        scored: predictions
+            .scores
+            .as_ref()
+            .filter(|_| needs_scores)
+            .map(|_| Vec::new()),
+        confusion: predictions
+            .classes
+            .as_ref()
+            .map(|_| vec![vec![0; n_classes]; n_classes]),
+        regression: predictions.values.as_ref().map(|_| Vec::new()),
+        amounts: master_data.has_row_gains().then(CellAmounts::default),
+    };
+
+    // Plain binary counts over a split only need bitset intersections
+    if let Rows::Split(split) = rows {
+        if predictions.counts_only(needs_scores) && evaluation.amounts.is_none() {
+            let (tp, fp, fn_, tn) = count_cells(
+                &predictions.positives,
+                master_data.positives(),
+                master_data.split_rows(split),
+                master_data.total_count(),
+            );
+            evaluation.tp = tp;
+            evaluation.fp = fp;
+            evaluation.fn_ = fn_;
+            evaluation.tn = tn;
+            return evaluation;
+        }
+    }
+
+    let mut add_row = |row: usize| {
+        if let (Some(confusion), Some(classes)) =
+            (evaluation.confusion.as_mut(), predictions.classes.as_ref())
+        {
+            if let Some(actual) = master_data.class_of(row) {
+                confusion[actual][classes[row] as usize] += 1;
+            }
+            return;
+        }
+
+        if let (Some(pairs), Some(values)) = (evaluation.regression.as_mut(), predictions.values.as_ref()) {
+            if let Some(actual) = master_data.target_of(row) {
+                if !values[row].is_nan() {
+                    pairs.push((values[row], actual));
+                }
+            }
+            return;
+        }
+
+        let is_positive = master_data.is_positive(row);
+        let cell = Cell::of(is_positive, predictions.positives.contains(row));
+        match cell {
+            Cell::Tp => evaluation.tp += 1,
+            Cell::Fn => evaluation.fn_ += 1,
+            Cell::Fp => evaluation.fp += 1,
+            Cell::Tn => evaluation.tn += 1,
+        }
+        if let Some(amounts) = evaluation.amounts.as_mut() {
+            amounts.add(cell, master_data.row_amount(row, cell));
+        }
+
+        if let (Some(scored), Some(scores)) = (evaluation.scored.as_mut(), predictions.scores.as_ref()) {
+            scored.push((scores[row], is_positive));
+        }
+    };
+
+    match rows {
+        Rows::Split(split) => match master_data.split_rows(split) {
+            Some(split_rows) => split_rows.iter().for_each(&mut add_row),
+            None => (0..master_data.total_count()).for_each(&mut add_row),
+        },
+        Rows::List(list) => list.iter().for_each(|row| add_row(*row)),
+    }
+
+    evaluation
+}
+
+/// `(tp, fp, fn, tn)` from word-wise intersections of predicted and actual
+/// positives, restricted to `scope` when given.
+fn count_cells(
+    predicted: &BitSet,
+    actual: &BitSet,
+    scope: Option<&BitSet>,
+    total: usize,
+) -> (i32, i32, i32, i32) {
+    let (mut tp, mut fp, mut fn_) = (0usize, 0usize, 0usize);
+    for (i, (p, y)) in predicted.words().iter().zip(actual.words()).enumerate() {
+        // Bits past the last row are zero in both sets, so a full mask is safe
+        let mask = scope.map_or(u64::MAX, |s| s.words()[i]);
+        tp += (p & y & mask).count_ones() as usize;
+        fp += (p & !y & mask).count_ones() as usize;
+        fn_ += (!p & y & mask).count_ones() as usize;
+    }
+    let in_scope = scope.map_or(total, BitSet::count);
+    let tn = in_scope - tp - fp - fn_;
+
+    (tp as i32, fp as i32, fn_ as i32, tn as i32)
+}
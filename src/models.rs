@@ -1,4 +1,8 @@
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::config::IdType;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Message {
@@ -28,6 +32,7 @@ pub struct Submission {
     pub submission_name: String,
     pub timestamp: String,
     pub file_checksum: String,
+    /// File of submissions stored before blobs, empty for newer ones (see `storage::path_of`)
     pub file_path: String,
     pub expected_gain: f64,
     pub actual_gain: f64,
@@ -38,15 +43,129 @@ pub struct Submission {
     pub positives_predicted: i32,
     pub threshold_category: String,
     pub after_deadline: bool,
+    pub public: GainResult,
+    pub private: GainResult,
+    pub cutoff: Option<f64>,
+    pub best_gain: Option<f64>,
+    pub best_cutoff: Option<f64>,
+    /// Matriz de confusión NxN (`[real][predicha]`) en competencias multiclase
+    pub confusion_matrix: Option<Vec<Vec<i32>>>,
+    /// Track de la competencia (`main` salvo que se elija otro)
+    pub track: String,
+    /// Retiro del envío por su autor; un envío retirado no compite ni cuenta en cuotas
+    pub withdrawal: Option<Withdrawal>,
+    /// Descripción libre del modelo, dada con `--desc` o al final de `submit`
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Withdrawal {
+    pub at: String,
+    pub reason: String,
+}
+
+/// Identificador de una fila del dataset maestro o de un envío.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Id {
+    Int(i64),
+    Text(Box<str>),
 }
 
+impl Id {
+    /// Parsea un ID según el tipo configurado; `None` si no es válido.
+    pub fn parse(raw: &str, id_type: IdType) -> Option<Self> {
+        let raw = raw.trim();
+        match id_type {
+            IdType::Integer => raw.parse().ok().map(Id::Int),
+            IdType::String => (!raw.is_empty()).then(|| Id::Text(raw.into())),
+        }
+    }
+}
+
+impl From<i64> for Id {
+    fn from(id: i64) -> Self {
+        Id::Int(id)
+    }
+}
+
+impl fmt::Display for Id {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Id::Int(id) => write!(f, "{}", id),
+            Id::Text(id) => f.write_str(id),
+        }
+    }
+}
+
+/// Contenido de un archivo de envío.
 #[derive(Debug, Clone)]
+pub enum Predictions {
+    /// Lista de IDs predichos como positivos
+    Ids(HashSet<Id>),
+    /// Filas `id,probability`; el bot aplica el corte
+    Scores(Vec<(Id, f64)>),
+    /// Filas `id,clase` con el índice de clase predicho; los IDs ausentes
+    /// se asumen de `default_class`
+    Classes {
+        predicted: HashMap<Id, usize>,
+        default_class: usize,
+    },
+    /// Filas `id,value` de competencias de regresión
+    Values(HashMap<Id, f64>),
+}
+
+impl Predictions {
+    /// Todos los IDs presentes en el archivo, sean positivos o no.
+    pub fn ids(&self) -> Box<dyn Iterator<Item = &Id> + '_> {
+        match self {
+            Predictions::Ids(ids) => Box::new(ids.iter()),
+            Predictions::Scores(scores) => Box::new(scores.iter().map(|(id, _)| id)),
+            Predictions::Classes { predicted, .. } => Box::new(predicted.keys()),
+            Predictions::Values(values) => Box::new(values.keys()),
+        }
+    }
+
+    /// IDs predichos como positivos con el corte dado. En multiclase, todos
+    /// los IDs con una clase distinta de la clase por defecto; en regresión, ninguno.
+    pub fn positives(&self, cutoff: f64) -> HashSet<Id> {
+        match self {
+            Predictions::Ids(ids) => ids.clone(),
+            Predictions::Scores(scores) => scores
+                .iter()
+                .filter(|(_, score)| *score >= cutoff)
+                .map(|(id, _)| id.clone())
+                .collect(),
+            Predictions::Classes {
+                predicted,
+                default_class,
+            } => predicted
+                .iter()
+                .filter(|(_, class)| *class != default_class)
+                .map(|(id, _)| id.clone())
+                .collect(),
+            Predictions::Values(_) => HashSet::new(),
+        }
+    }
+}
+
+/// Un archivo de envío leído, con los IDs que aparecen más de una vez.
+#[derive(Debug, Clone)]
+pub struct PredictionFile {
+    /// Solo cuenta la primera fila de cada ID
+    pub predictions: Predictions,
+    /// IDs repetidos, una vez cada uno, en el orden del archivo
+    pub duplicates: Vec<Id>,
+}
+
+#[derive(Debug, Clone, Default)]
 pub struct GainResult {
     pub gain: f64,
     pub tp: i32,
     pub tn: i32,
     pub fp: i32,
     pub fn_: i32,
+    /// Matriz de confusión NxN, solo en competencias multiclase
+    pub confusion: Option<Vec<Vec<i32>>>,
 }
 
 #[derive(Debug, Deserialize)]
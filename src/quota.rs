@@ -0,0 +1,150 @@
+//! Submission quotas per student: a daily one that resets at `reset_time` in
+//! the competition timezone and one for the whole competition. Extra
+//! submissions granted by a teacher raise both.
+
+use anyhow::Result;
+use chrono::{DateTime, Duration, NaiveDate, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+
+use crate::config::BotConfig;
+use crate::database::Database;
+
+/// The quota a student ran out of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exceeded {
+    Daily,
+    Total,
+}
+
+#[derive(Debug, Clone)]
+pub struct QuotaUsage {
+    pub used_today: u32,
+    pub used_total: u32,
+    /// Daily quota plus today's grants, `None` without a daily quota
+    pub daily_limit: Option<u32>,
+    /// Total quota plus every grant, `None` without a total quota
+    pub total_limit: Option<u32>,
+    pub next_reset: DateTime<Tz>,
+}
+
+impl QuotaUsage {
+    /// The quota another submission would exceed, if any.
+    pub fn exceeded(&self) -> Option<Exceeded> {
+        if self.daily_limit.is_some_and(|limit| self.used_today >= limit) {
+            Some(Exceeded::Daily)
+        } else if self.total_limit.is_some_and(|limit| self.used_total >= limit) {
+            Some(Exceeded::Total)
+        } else {
+            None
+        }
+    }
+
+    pub fn is_limited(&self) -> bool {
+        self.daily_limit.is_some() || self.total_limit.is_some()
+    }
+
+    /// Reply to an over-quota `submit`.
+    pub fn rejection(&self) -> String {
+        match self.exceeded() {
+            Some(Exceeded::Daily) => format!(
+                "🚫 **Alcanzaste tu cuota diaria de envíos** ({} de {}). Se renueva el {}.\n\
+                El intento quedó registrado pero no se puntuó.",
+                self.used_today,
+                self.daily_limit.unwrap_or_default(),
+                format_reset(&self.next_reset)
+            ),
+            Some(Exceeded::Total) => format!(
+                "🚫 **Alcanzaste tu cuota total de envíos** ({} de {}).\n\
+                El intento quedó registrado pero no se puntuó.",
+                self.used_total,
+                self.total_limit.unwrap_or_default()
+            ),
+            None => String::new(),
+        }
+    }
+
+    /// Markdown lines with the used and available submissions.
+    pub fn render(&self) -> String {
+        let limit = |limit: Option<u32>| limit.map_or("sin límite".to_string(), |l| l.to_string());
+        let mut response = format!(
+            "📦 **Hoy:** {} de {}",
+            self.used_today,
+            limit(self.daily_limit)
+        );
+        if self.daily_limit.is_some() {
+            response.push_str(&format!(" (se renueva el {})", format_reset(&self.next_reset)));
+        }
+        response.push_str(&format!(
+            "\n📦 **Total:** {} de {}\n",
+            self.used_total,
+            limit(self.total_limit)
+        ));
+        response
+    }
+}
+
+fn format_reset(reset: &DateTime<Tz>) -> String {
+    reset.format("%Y-%m-%d %H:%M %Z").to_string()
+}
+
+/// Quota day that `at` counts against: the local date, shifted back so the
+/// day starts at `reset` instead of midnight.
+pub fn quota_day(at: DateTime<Utc>, tz: Tz, reset: NaiveTime) -> NaiveDate {
+    let local = at.with_timezone(&tz).naive_local();
+    (local - (reset - NaiveTime::MIN)).date()
+}
+
+/// Start of the quota day after `day`.
+fn next_reset(day: NaiveDate, tz: Tz, reset: NaiveTime) -> DateTime<Tz> {
+    let local = (day + Duration::days(1)).and_time(reset);
+    // A reset inside a DST gap happens when the clocks resume
+    tz.from_local_datetime(&local)
+        .earliest()
+        .or_else(|| tz.from_local_datetime(&(local + Duration::hours(1))).earliest())
+        .unwrap_or_else(|| tz.from_utc_datetime(&local))
+}
+
+/// Quota usage of the Zulip user `user_id` at `now`.
+pub fn usage(db: &Database, config: &BotConfig, user_id: i64, now: DateTime<Utc>) -> Result<QuotaUsage> {
+    let quota = &config.submissions.quota;
+    let tz = config.competition.timezone()?;
+    let reset = quota.reset_time()?;
+    let today = quota_day(now, tz, reset);
+
+    let timestamps = db.get_submission_timestamps(user_id)?;
+    let used_today = timestamps
+        .iter()
+        .filter_map(|ts| DateTime::parse_from_rfc3339(ts).ok())
+        .filter(|ts| quota_day(ts.with_timezone(&Utc), tz, reset) == today)
+        .count() as u32;
+
+    let grants = db.get_quota_grants(user_id)?;
+    let today_str = today.to_string();
+    let granted_today: u32 = grants.iter().filter(|(day, _)| *day == today_str).map(|(_, n)| n).sum();
+    let granted: u32 = grants.iter().map(|(_, n)| n).sum();
+
+    Ok(QuotaUsage {
+        used_today,
+        used_total: timestamps.len() as u32,
+        daily_limit: quota.daily.map(|daily| daily + granted_today),
+        total_limit: quota.total.map(|total| total + granted),
+        next_reset: next_reset(today, tz, reset),
+    })
+}
+
+/// Grants `extra` submissions to the Zulip user `user_id`, named `user_name`,
+/// for the current quota day and the total.
+pub fn grant(
+    db: &Database,
+    config: &BotConfig,
+    user_id: i64,
+    user_name: &str,
+    extra: u32,
+    granted_by: &str,
+    now: DateTime<Utc>,
+) -> Result<QuotaUsage> {
+    let tz = config.competition.timezone()?;
+    let day = quota_day(now, tz, config.submissions.quota.reset_time()?);
+    db.save_quota_grant(user_id, user_name, extra, &day.to_string(), granted_by, &now.to_rfc3339())?;
+    usage(db, config, user_id, now)
+}
@@ -0,0 +1,118 @@
+//! Reference scores derived from the master data alone, to put the score of a
+//! submission in context: what a perfect model, the trivial submissions and a
+//! random one would get with the primary metric.
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::config::{BotConfig, TaskType};
+use crate::dense::BitSet;
+use crate::master_data::{MasterData, Split};
+use crate::metrics::{DensePredictions, MetricSet};
+
+/// Rates tried for the random baseline, in steps of 5%.
+const RANDOM_RATE_STEPS: usize = 20;
+
+/// Random submission predicting each row positive with a fixed probability.
+#[derive(Debug, Clone, Copy)]
+pub struct RandomBaseline {
+    pub rate: f64,
+    pub score: f64,
+}
+
+/// Reference scores over one scope (whole dataset, public or private split).
+/// A value is `None` when the primary metric is undefined for it, e.g. F1 of
+/// a submission without positives.
+#[derive(Debug, Clone)]
+pub struct ReferenceScores {
+    /// Every positive predicted and nothing else
+    pub oracle: Option<f64>,
+    pub nothing: Option<f64>,
+    pub everything: Option<f64>,
+    /// Best constant-rate random submission
+    pub random: Option<RandomBaseline>,
+}
+
+impl ReferenceScores {
+    /// Score as a percentage of the oracle, when the oracle is positive.
+    pub fn pct_of_oracle(&self, score: f64) -> Option<f64> {
+        self.oracle
+            .filter(|oracle| *oracle > 0.0)
+            .map(|oracle| score / oracle * 100.0)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct References {
+    pub all: ReferenceScores,
+    pub public: ReferenceScores,
+    pub private: ReferenceScores,
+}
+
+impl References {
+    /// Computed once at startup (and on reload). Only binary competitions with
+    /// a higher-is-better primary metric have a meaningful "% of the oracle".
+    pub fn compute(config: &BotConfig, master_data: &MasterData, metrics: &MetricSet) -> Option<Self> {
+        if config.competition.task != TaskType::Binary || !metrics.primary().higher_is_better() {
+            return None;
+        }
+
+        let n = master_data.total_count();
+        let oracle = DensePredictions::from_positives(master_data.positives().clone());
+        let nothing = DensePredictions::from_positives(BitSet::new(n));
+        let everything = DensePredictions::from_positives(BitSet::new(n).complement());
+
+        // One uniform draw per row: the positives at a rate are a subset of
+        // those at any higher rate, so the baselines only differ by the rate
+        let mut rng = StdRng::seed_from_u64(config.bootstrap.seed);
+        let draws: Vec<f64> = (0..n).map(|_| rng.gen()).collect();
+        let random: Vec<(f64, DensePredictions)> = (1..RANDOM_RATE_STEPS)
+            .map(|step| {
+                let rate = step as f64 / RANDOM_RATE_STEPS as f64;
+                let mut positives = BitSet::new(n);
+                for (row, draw) in draws.iter().enumerate() {
+                    if *draw < rate {
+                        positives.insert(row);
+                    }
+                }
+                (rate, DensePredictions::from_positives(positives))
+            })
+            .collect();
+
+        let score = |predictions: &DensePredictions, split: Option<Split>| {
+            let (_, values) = metrics.evaluate_dense(predictions, master_data, split);
+            values[0].value
+        };
+        let scope = |split: Option<Split>| {
+            let nothing = score(&nothing, split);
+            let everything = score(&everything, split);
+            // The trivial submissions are the rates 0 and 1
+            let random = [(0.0, nothing), (1.0, everything)]
+                .into_iter()
+                .chain(random.iter().map(|(rate, p)| (*rate, score(p, split))))
+                .filter_map(|(rate, score)| Some(RandomBaseline { rate, score: score? }))
+                .max_by(|a, b| a.score.total_cmp(&b.score));
+
+            ReferenceScores {
+                oracle: score(&oracle, split),
+                nothing,
+                everything,
+                random,
+            }
+        };
+
+        Some(Self {
+            all: scope(None),
+            public: scope(Some(Split::Public)),
+            private: scope(Some(Split::Private)),
+        })
+    }
+
+    pub fn scope(&self, split: Option<Split>) -> &ReferenceScores {
+        match split {
+            None => &self.all,
+            Some(Split::Public) => &self.public,
+            Some(Split::Private) => &self.private,
+        }
+    }
+}
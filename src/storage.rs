@@ -0,0 +1,175 @@
+//! Content-addressed storage of submission files. Each CSV is stored once
+//! under `<submissions.path>/blobs/<ab>/<sha256>.csv`, named by the SHA-256
+//! of its content, and the `blobs` table records the checksum of every
+//! stored file. The path follows from the checksum, so it is not persisted.
+//! Identical uploads share a blob, and students with the same name no longer
+//! share a folder.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use chrono::Utc;
+use sha2::{Digest, Sha256};
+
+use crate::config::BotConfig;
+use crate::database::{Blob, Database};
+use crate::models::Submission;
+
+/// Hex SHA-256 of `content`, the key of its blob.
+pub fn checksum(content: &[u8]) -> String {
+    hex::encode(Sha256::digest(content))
+}
+
+/// Folder holding the blobs of `config`.
+pub fn root(config: &BotConfig) -> PathBuf {
+    PathBuf::from(&config.submissions.path).join("blobs")
+}
+
+/// Where the blob with `checksum` lives. The first two characters pick a
+/// subfolder so no folder grows too large.
+pub fn blob_path(root: &Path, checksum: &str) -> PathBuf {
+    root.join(&checksum[..2]).join(format!("{}.csv", checksum))
+}
+
+/// File of `submission`: its blob, or for submissions stored before blobs
+/// existed, the path recorded with it.
+pub fn path_of(config: &BotConfig, submission: &Submission) -> PathBuf {
+    if submission.file_path.is_empty() {
+        blob_path(&root(config), &submission.file_checksum)
+    } else {
+        PathBuf::from(&submission.file_path)
+    }
+}
+
+/// Stores `content` and records its blob. A blob that is already on disk is
+/// kept, unless its content no longer matches its name.
+pub fn store(db: &Database, config: &BotConfig, content: &[u8]) -> Result<Blob> {
+    let checksum = checksum(content);
+    let path = blob_path(&root(config), &checksum);
+
+    let intact = fs::read(&path).is_ok_and(|stored| self::checksum(&stored) == checksum);
+    if !intact {
+        let dir = path.parent().context("Blob path without a folder")?;
+        fs::create_dir_all(dir)?;
+        // A partial write never takes the name of the blob
+        let partial = path.with_extension("csv.partial");
+        fs::write(&partial, content)?;
+        fs::rename(&partial, &path)?;
+    }
+
+    let blob = Blob {
+        checksum,
+        size: content.len() as u64,
+        stored_at: Utc::now().to_rfc3339(),
+    };
+    db.save_blob(&blob)?;
+    Ok(blob)
+}
+
+/// A stored file that failed verification.
+#[derive(Debug, Clone)]
+pub struct Problem {
+    pub checksum: String,
+    pub path: String,
+    /// Submissions that point at the file
+    pub submission_ids: Vec<i64>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct StorageReport {
+    /// Files re-hashed, blobs and files of older submissions
+    pub checked: usize,
+    pub missing: Vec<Problem>,
+    /// Files whose content no longer hashes to their checksum
+    pub corrupted: Vec<Problem>,
+}
+
+impl StorageReport {
+    pub fn is_ok(&self) -> bool {
+        self.missing.is_empty() && self.corrupted.is_empty()
+    }
+
+    /// Markdown report for the bot reply.
+    pub fn render(&self) -> String {
+        if self.is_ok() {
+            return format!(
+                "✅ **Almacenamiento verificado:** los {} archivos están completos e intactos",
+                self.checked
+            );
+        }
+
+        let mut response = format!(
+            "🧪 **Almacenamiento:** {} archivos revisados, {} faltantes, {} corruptos\n",
+            self.checked,
+            self.missing.len(),
+            self.corrupted.len()
+        );
+        for (title, problems) in [("❓ **Faltantes:**", &self.missing), ("💥 **Corruptos:**", &self.corrupted)] {
+            if problems.is_empty() {
+                continue;
+            }
+            response.push_str(&format!("\n{}\n", title));
+            for problem in problems {
+                let ids: Vec<String> = problem.submission_ids.iter().map(|id| id.to_string()).collect();
+                response.push_str(&format!(
+                    "• `{}...` `{}` (envíos {})\n",
+                    &problem.checksum[..problem.checksum.len().min(16)],
+                    problem.path,
+                    ids.join(", ")
+                ));
+            }
+        }
+        response
+    }
+}
+
+/// Re-hashes every blob, and the files of submissions stored before blobs
+/// existed, against the checksum recorded for them.
+pub fn verify(db: &Database, config: &BotConfig) -> Result<StorageReport> {
+    let submissions = db.get_all_submissions()?;
+    let root = root(config);
+
+    // One entry per stored file, with the submissions that use it
+    let mut files: Vec<Problem> = db
+        .get_blobs()?
+        .into_iter()
+        .map(|blob| Problem {
+            path: blob_path(&root, &blob.checksum).to_string_lossy().to_string(),
+            checksum: blob.checksum,
+            submission_ids: Vec::new(),
+        })
+        .collect();
+    let mut by_path: HashMap<String, usize> = files
+        .iter()
+        .enumerate()
+        .map(|(i, file)| (file.path.clone(), i))
+        .collect();
+    for sub in &submissions {
+        let path = path_of(config, sub).to_string_lossy().to_string();
+        let index = *by_path.entry(path.clone()).or_insert_with(|| {
+            files.push(Problem {
+                checksum: sub.file_checksum.clone(),
+                path,
+                submission_ids: Vec::new(),
+            });
+            files.len() - 1
+        });
+        files[index].submission_ids.push(sub.id.unwrap_or_default());
+    }
+
+    let mut report = StorageReport {
+        checked: files.len(),
+        ..Default::default()
+    };
+    for mut file in files {
+        file.submission_ids.sort();
+        match fs::read(&file.path) {
+            Err(_) => report.missing.push(file),
+            Ok(content) if checksum(&content) != file.checksum => report.corrupted.push(file),
+            Ok(_) => {}
+        }
+    }
+    Ok(report)
+}
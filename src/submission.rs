@@ -1,36 +1,843 @@
-use anyhow::{Context, Result};
+use anyhow::Result;
 use chrono::{DateTime, Utc};
-use csv::ReaderBuilder;
 use rand::seq::SliceRandom;
 use regex::Regex;
-use sha2::{Digest, Sha256};
 use std::collections::{HashSet, HashMap};
 use std::fs;
-use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
 use tracing::{info, warn};
 
-use crate::config::BotConfig;
-use crate::database::Database;
-use crate::master_data::MasterData;
-use crate::models::{GainResult, Message, Submission};
+use crate::args;
+use crate::attachment::{self, Compression, Expanded};
+use crate::bootstrap::{self, Interval};
+use crate::calibration;
+use crate::csv_dialect::{self, CsvError, Row, Rows};
+use crate::config::{BotConfig, ClassGainMatrix, GainThreshold, IdType, TaskType, ThresholdKind};
+use crate::database::{Database, LeaderboardEntry, RejectedAttempt};
+use crate::ensemble::{self, Rule};
+use crate::master_data::{MasterData, Split};
+use crate::gain_curve::{CurvePoint, GainCurve};
+use crate::metrics::{self, DensePredictions, MetricSet, MetricValue};
+use crate::models::{GainResult, Id, Message, PredictionFile, Predictions, Submission, Withdrawal};
+use crate::quota;
+use crate::storage;
+use crate::reference::{ReferenceScores, References};
+use crate::track::{self, Track};
+use crate::validation;
 use crate::zulip::ZulipClient;
 
 pub async fn process_submit(
     message: &Message,
-    config: &BotConfig,
+    tracks: &[Track],
     db: &Database,
-    master_data: &MasterData,
     is_teacher: bool,
 ) -> String {
     let user_email = &message.sender_email;
+    // The competition dates are shared by every track
+    let config = &tracks[0].config;
 
     info!(
         "Processing submit from {} (teacher: {})",
         user_email, is_teacher
     );
 
-    // Check deadline - be more flexible with date parsing
-    let deadline = match DateTime::parse_from_rfc3339(&config.competition.deadline) {
+    let after_deadline = deadline_passed(config);
+
+    let links = attachment_links(&message.content);
+    if links.len() > 1 {
+        return process_batch_submit(message, links, tracks, db, is_teacher, after_deadline).await;
+    }
+
+    // Parse command
+    let args = match args::parse_submit(&message.content) {
+        Ok(args) => args,
+        Err(e) => return e,
+    };
+    let request = match SubmitRequest::new(args, tracks) {
+        Ok(request) => request,
+        Err(e) => return e,
+    };
+    let config = &request.track.config;
+
+    info!(
+        "Submission name: {}, Expected gain: {}",
+        request.name, request.expected_gain
+    );
+
+    match name_taken(db, message.sender_id, &request.name, None) {
+        Ok(true) => {
+            return format!(
+                "❌ Ya tienes un envío llamado `{}`. Elige otro nombre o usa `rename <id> <nombre>`",
+                request.name
+            )
+        }
+        Ok(false) => {}
+        Err(e) => return format!("❌ Error obteniendo envíos: {}", e),
+    }
+
+    // Over-quota attempts are recorded before downloading anything, and never scored
+    let quota = match check_quota(message, &request, db, is_teacher) {
+        Ok(quota) => quota,
+        Err(e) => return e,
+    };
+
+    let link = match links.into_iter().next() {
+        Some(link) => link,
+        None => {
+            return "❌ Debes adjuntar un archivo CSV. Usa el formato: `submit <nombre> <ganancia_esperada>` y adjunta el archivo CSV.".to_string();
+        }
+    };
+    let accepted = match submit_file(message, &request, &link, db, after_deadline).await {
+        Ok(accepted) => accepted,
+        Err(e) => return e,
+    };
+    let Accepted {
+        id: submission_id,
+        submission,
+        scoring,
+        validation,
+    } = &accepted;
+    let track = request.track;
+    let master_data = &track.master_data;
+    let expected_gain = request.expected_gain;
+    let cutoff = scoring.cutoff;
+    let gain_result = &scoring.result;
+    let public_result = &scoring.public;
+    let private_result = &scoring.private;
+    let threshold_category = &scoring.threshold_category;
+    let positives_predicted = scoring.positives_predicted;
+    let best_point = scoring.best.as_ref();
+    let metric_values = &scoring.metric_values;
+
+    // Build response
+    let threshold_config = config
+        .gain_thresholds
+        .iter()
+        .find(|t| &t.category == threshold_category);
+
+    let mut response = match threshold_config {
+        Some(threshold) => format!("🎯 **{}**\n\n", threshold.message),
+        None => String::new(),
+    };
+    response.push_str(&format!("🆔 **ID Envío:** {}\n", submission_id));
+    if tracks.len() > 1 {
+        response.push_str(&format!("🛤️ **Track:** {}\n", track.name));
+    }
+    if let Some(description) = &submission.description {
+        response.push_str(&format!("📝 **Descripción:** {}\n", description));
+    }
+    response.push_str(&format!("📊 **Ganancia esperada:** {:.4}\n", expected_gain));
+    if let Some(cutoff) = cutoff {
+        response.push_str(&format!(
+            "✂️ **Corte:** {} ({} positivos)\n",
+            cutoff, positives_predicted
+        ));
+    }
+    response.push_str(&validation.render());
+
+    if master_data.has_split() {
+        response.push_str(&format!(
+            "🌐 **Ganancia pública:** {:.4}\n",
+            public_result.gain
+        ));
+        if results_revealed(config) {
+            response.push_str(&format!(
+                "🔒 **Ganancia privada:** {:.4}\n",
+                private_result.gain
+            ));
+        }
+    }
+
+    // The best achievable gain is computed on the whole dataset, so it waits for the reveal
+    if let Some(best) = best_point {
+        if is_teacher || results_revealed(config) {
+            response.push_str(&format!(
+                "🏅 **Mejor ganancia posible:** {:.4} (corte {}, {} positivos)\n",
+                best.gain,
+                format_threshold(best.threshold),
+                best.positives
+            ));
+        }
+    }
+
+    // Teachers see actual gain
+    if is_teacher {
+        response.push_str(&format!("✨ **Ganancia real:** {:.4}\n", gain_result.gain));
+        for metric in metric_values.iter().skip(1) {
+            if let Some(value) = metric.value {
+                response.push_str(&format!("📐 **{}:** {:.4}\n", metric.name, value));
+            }
+        }
+        if validation.positive_share.is_none() {
+            response.push_str(&format!(
+                "📈 **Positivos predichos:** {}\n",
+                positives_predicted
+            ));
+        }
+        match &gain_result.confusion {
+            Some(confusion) => {
+                response.push_str("🔢 **Matriz confusión:**\n\n");
+                response.push_str(&format_confusion_matrix(confusion, master_data.class_labels()));
+            }
+            None => response.push_str(&format!(
+                "🔢 **Matriz confusión:** TP={}, TN={}, FP={}, FN={}\n",
+                gain_result.tp, gain_result.tn, gain_result.fp, gain_result.fn_
+            )),
+        }
+    }
+
+    // After deadline notification
+    if after_deadline {
+        response.push_str("\n⚠️ **ENVÍO FUERA DE PLAZO** - Registrado pero no compite\n");
+    }
+
+    // Remaining quota, counting this submission
+    if quota.is_some_and(|usage| usage.is_limited()) {
+        if let Ok(usage) = quota::usage(db, config, message.sender_id, Utc::now()) {
+            response.push_str(&format!("\n{}", usage.render()));
+        }
+    }
+
+    // Add random GIF
+    if let Some(threshold) = threshold_config {
+        let mut rng = rand::thread_rng();
+        if let Some(gif) = threshold.gifs.choose(&mut rng) {
+            response.push_str(&format!("\n{}", gif));
+        }
+    }
+
+    response
+}
+
+/// A file to score and what the student said about it.
+struct SubmitRequest<'a> {
+    name: String,
+    expected_gain: f64,
+    /// Declared cut-off for `id,probability` files
+    cutoff: Option<f64>,
+    track: &'a Track,
+    description: Option<String>,
+}
+
+impl<'a> SubmitRequest<'a> {
+    /// `track=<track>` picks the track, the main one without it; the other
+    /// words describe the model, as does `--desc`.
+    fn new(args: args::SubmitArgs, tracks: &'a [Track]) -> Result<Self, String> {
+        let track = match &args.track {
+            Some(name) => track::find(tracks, name).ok_or_else(|| {
+                let names: Vec<&str> = tracks.iter().map(|t| t.name.as_str()).collect();
+                format!(
+                    "❌ No existe el track '{}'. Tracks disponibles: {}",
+                    name,
+                    names.join(", ")
+                )
+            })?,
+            None => &tracks[0],
+        };
+        let description = [args.rest.join(" "), args.description.unwrap_or_default()]
+            .into_iter()
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        Ok(Self {
+            name: args.name,
+            expected_gain: args.expected_gain,
+            cutoff: args.cutoff,
+            track,
+            description: (!description.is_empty()).then_some(description),
+        })
+    }
+}
+
+/// A scored and stored submission, with what the reply needs.
+struct Accepted {
+    id: i64,
+    submission: Submission,
+    scoring: Scoring,
+    validation: validation::ValidationReport,
+}
+
+/// Quota usage before this submission, `None` for teachers. Over quota, the
+/// attempt is recorded and the error is the reply.
+fn check_quota(
+    message: &Message,
+    request: &SubmitRequest,
+    db: &Database,
+    is_teacher: bool,
+) -> Result<Option<quota::QuotaUsage>, String> {
+    if is_teacher {
+        return Ok(None);
+    }
+    let usage = quota::usage(db, &request.track.config, message.sender_id, Utc::now())
+        .map_err(|e| format!("❌ Error calculando tu cuota de envíos: {}", e))?;
+    if usage.exceeded().is_some() {
+        warn!("Submission from {} over quota", message.sender_email);
+        let attempt = RejectedAttempt {
+            user_id: message.sender_id,
+            user_email: message.sender_email.clone(),
+            user_full_name: message.sender_full_name.clone(),
+            submission_name: request.name.clone(),
+            timestamp: Utc::now().to_rfc3339(),
+            track: request.track.name.clone(),
+            reason: "quota".to_string(),
+        };
+        if let Err(e) = db.save_rejected_attempt(&attempt) {
+            warn!("Could not record rejected attempt: {}", e);
+        }
+        return Err(usage.rejection());
+    }
+    Ok(Some(usage))
+}
+
+/// Downloads, validates, scores and stores one attachment. The error is the
+/// reply for the student.
+async fn submit_file(
+    message: &Message,
+    request: &SubmitRequest<'_>,
+    link: &AttachmentLink,
+    db: &Database,
+    after_deadline: bool,
+) -> Result<Accepted, String> {
+    let user_email = &message.sender_email;
+    let track = request.track;
+    let config = &track.config;
+    let master_data = &track.master_data;
+    let metrics = &track.metrics;
+    let submission_name = &request.name;
+
+    let filename = &link.filename;
+    if Compression::of(filename).is_none() {
+        return Err("❌ El archivo debe ser un CSV (también se aceptan `.csv.gz` y `.zip` con un solo CSV)".to_string());
+    }
+    let file_content = download_attachment(link, config)
+        .await
+        .map_err(|e| format!("❌ Error descargando archivo: {}", e))?;
+
+    // Compressed files are stored and checksummed decompressed, so duplicates
+    // are found whatever the format they were sent in
+    let Expanded {
+        filename,
+        content: file_content,
+    } = attachment::expand(filename, file_content, config.submissions.max_decompressed_bytes())
+        .map_err(|e| format!("❌ Error descomprimiendo archivo: {:#}", e))?;
+
+    // Read and validate CSV
+    let file = parse_predictions(&file_content, config, Some(config.submissions.max_rows)).map_err(|e| match e.downcast_ref::<CsvError>() {
+        Some(csv_error) => csv_error.report(),
+        None => format!("❌ Error leyendo CSV: {}", e),
+    })?;
+    let predictions = &file.predictions;
+
+    if metrics.primary().requires_scores() && matches!(predictions, Predictions::Ids(_)) {
+        return Err(format!(
+            "❌ La métrica de esta competencia ({}) requiere un CSV `id,probability`",
+            metrics.primary().name()
+        ));
+    }
+
+    if request.cutoff.is_some() && !matches!(predictions, Predictions::Scores(_)) {
+        return Err(
+            "❌ Indicaste un corte, pero el archivo no tiene probabilidades: el corte solo aplica a archivos `id,probability`"
+                .to_string(),
+        );
+    }
+
+    // Probability files are cut by the bot
+    let cutoff = match predictions {
+        Predictions::Scores(_) => Some(request.cutoff.unwrap_or(config.submissions.default_cutoff)),
+        _ => None,
+    };
+
+    // Duplicate and unknown IDs, share of positives
+    let positives = (config.competition.task == TaskType::Binary).then(|| {
+        master_data
+            .index()
+            .bitset_of(predictions.positives(cutoff.unwrap_or_default()).iter())
+            .count()
+    });
+    let validation = validation::validate(&file, master_data, positives, &config.submissions.validation);
+    if validation.has_errors() {
+        warn!("Submission from {} failed validation", user_email);
+        return Err(format!(
+            "❌ **El archivo no pasó la validación:**\n\n{}",
+            validation.render()
+        ));
+    }
+
+    // Regression needs exactly one prediction per ID
+    if matches!(predictions, Predictions::Values(_)) {
+        let predicted = master_data.index().bitset_of(predictions.ids()).count();
+        let missing = master_data.total_count() - predicted;
+        if missing > 0 {
+            return Err(format!(
+                "❌ Faltan predicciones: {} IDs del dataset no aparecen en el archivo",
+                missing
+            ));
+        }
+    }
+
+    // Calculate gain
+    info!("Calculating gain for {}", submission_name);
+    let mut scoring = score_predictions(predictions, cutoff, config, master_data, metrics);
+    let peers = match db.get_all_submissions() {
+        Ok(submissions) => {
+            competing_public_scores(submissions.iter().filter(|sub| sub.track == track.name))
+        }
+        Err(e) => return Err(format!("❌ Error obteniendo envíos: {}", e)),
+    };
+    scoring.categorize(
+        config,
+        metrics,
+        &Standing {
+            references: track.references.as_ref(),
+            peers: &peers,
+        },
+    );
+
+    info!(
+        "Gain calculated - Expected: {:.4}, Actual: {:.4}",
+        request.expected_gain, scoring.result.gain
+    );
+
+    // Only accepted files are stored; identical files share a blob, named by their checksum
+    let blob = storage::store(db, config, &file_content).map_err(|e| format!("❌ Error guardando archivo: {}", e))?;
+    info!("File {} stored, checksum: {}...", filename, &blob.checksum[..16]);
+
+    // Create submission record
+    let mut submission = Submission {
+        id: None,
+        user_id: message.sender_id,
+        user_email: user_email.clone(),
+        user_full_name: message.sender_full_name.clone(),
+        submission_name: submission_name.clone(),
+        timestamp: Utc::now().to_rfc3339(),
+        file_checksum: blob.checksum,
+        // The blob path follows from the checksum, see `storage::path_of`
+        file_path: String::new(),
+        expected_gain: request.expected_gain,
+        actual_gain: 0.0,
+        tp: 0,
+        tn: 0,
+        fp: 0,
+        fn_: 0,
+        positives_predicted: 0,
+        threshold_category: String::new(),
+        after_deadline,
+        public: GainResult::default(),
+        private: GainResult::default(),
+        cutoff: None,
+        best_gain: None,
+        best_cutoff: None,
+        confusion_matrix: None,
+        track: track.name.clone(),
+        withdrawal: None,
+        description: request.description.clone(),
+    };
+    scoring.apply(&mut submission);
+
+    // Save to database
+    let id = db
+        .save_submission(&submission)
+        .map_err(|e| format!("❌ Error guardando envío: {}", e))?;
+    info!("Submission saved with ID: {}", id);
+    scoring.save_details(db, id);
+    // Students do not see intervals, so they are not awaited
+    if let Some(dense) = scoring.dense.take() {
+        spawn_intervals(db, track, dense, id);
+    }
+    submission.id = Some(id);
+
+    Ok(Accepted {
+        id,
+        submission,
+        scoring,
+        validation,
+    })
+}
+
+/// One line of the batch `submit` reply.
+struct BatchRow {
+    filename: String,
+    name: Option<String>,
+    expected_gain: Option<f64>,
+    id: Option<i64>,
+    public_gain: Option<f64>,
+    category: Option<String>,
+    status: &'static str,
+}
+
+impl BatchRow {
+    fn rejected(link: &AttachmentLink, request: Option<&SubmitRequest>, status: &'static str) -> Self {
+        Self {
+            filename: link.filename.clone(),
+            name: request.map(|r| r.name.clone()),
+            expected_gain: request.map(|r| r.expected_gain),
+            id: None,
+            public_gain: None,
+            category: None,
+            status,
+        }
+    }
+
+    fn render(&self, has_split: bool) -> String {
+        let mut cells = vec![
+            self.filename.clone(),
+            self.name.clone().unwrap_or_default(),
+            self.id.map(|id| id.to_string()).unwrap_or_default(),
+            self.expected_gain.map(|g| format!("{:.2}", g)).unwrap_or_default(),
+        ];
+        if has_split {
+            cells.push(self.public_gain.map(|g| format!("{:.2}", g)).unwrap_or_default());
+        }
+        cells.push(self.category.clone().unwrap_or_default());
+        cells.push(self.status.to_string());
+        format!("| {} |\n", cells.join(" | "))
+    }
+}
+
+/// `submit` with several attachments: each one is named, checked against the
+/// quota and scored on its own, and the reply is a single table.
+async fn process_batch_submit(
+    message: &Message,
+    links: Vec<AttachmentLink>,
+    tracks: &[Track],
+    db: &Database,
+    is_teacher: bool,
+    after_deadline: bool,
+) -> String {
+    info!("Batch submit of {} files", links.len());
+    let lines: Vec<&str> = message.content.lines().collect();
+
+    let mut rows = Vec::new();
+    let mut details = Vec::new();
+    let mut names: Vec<String> = Vec::new();
+    let mut quota_limited = false;
+
+    for link in &links {
+        let shared_line = links.iter().filter(|other| other.line == link.line).count() > 1;
+        let line = lines.get(link.line).copied().unwrap_or_default();
+        let request = args::parse_attachment_line(line, &link.filename, shared_line)
+            .and_then(|args| SubmitRequest::new(args, tracks));
+        let request = match request {
+            Ok(request) => request,
+            Err(e) => {
+                rows.push(BatchRow::rejected(link, None, "❌"));
+                details.push(format!("**{}:** {}", link.filename, e));
+                continue;
+            }
+        };
+
+        // Names must be unique within the batch too
+        let taken = match name_taken(db, message.sender_id, &request.name, None) {
+            Ok(taken) => taken || names.iter().any(|name| name.eq_ignore_ascii_case(&request.name)),
+            Err(e) => {
+                rows.push(BatchRow::rejected(link, Some(&request), "❌"));
+                details.push(format!("**{}:** ❌ Error obteniendo envíos: {}", link.filename, e));
+                continue;
+            }
+        };
+        if taken {
+            rows.push(BatchRow::rejected(link, Some(&request), "❌"));
+            details.push(format!(
+                "**{}:** ❌ Ya tienes un envío llamado `{}`",
+                link.filename, request.name
+            ));
+            continue;
+        }
+        names.push(request.name.clone());
+
+        // Every file counts against the quota, including the earlier ones of this batch
+        match check_quota(message, &request, db, is_teacher) {
+            Ok(quota) => quota_limited |= quota.is_some_and(|usage| usage.is_limited()),
+            Err(e) => {
+                rows.push(BatchRow::rejected(link, Some(&request), "🚫"));
+                details.push(format!("**{}:** {}", link.filename, e));
+                continue;
+            }
+        }
+
+        match submit_file(message, &request, link, db, after_deadline).await {
+            Ok(accepted) => {
+                let has_warnings = !accepted.validation.findings.is_empty();
+                if has_warnings {
+                    details.push(format!("**{}:**\n{}", link.filename, accepted.validation.render()));
+                }
+                let name = if tracks.len() > 1 {
+                    format!("{} ({})", request.name, request.track.name)
+                } else {
+                    request.name.clone()
+                };
+                rows.push(BatchRow {
+                    filename: link.filename.clone(),
+                    name: Some(name),
+                    expected_gain: Some(request.expected_gain),
+                    id: Some(accepted.id),
+                    public_gain: request
+                        .track
+                        .master_data
+                        .has_split()
+                        .then_some(accepted.scoring.public.gain),
+                    category: Some(accepted.scoring.threshold_category),
+                    status: if has_warnings { "⚠️" } else { "✅" },
+                });
+            }
+            Err(e) => {
+                rows.push(BatchRow::rejected(link, Some(&request), "❌"));
+                details.push(format!("**{}:** {}", link.filename, e));
+            }
+        }
+    }
+
+    let scored = rows.iter().filter(|row| row.id.is_some()).count();
+    let has_split = rows.iter().any(|row| row.public_gain.is_some());
+    let mut response = format!(
+        "📦 **Envío múltiple:** {} de {} archivos puntuados\n\n",
+        scored,
+        links.len()
+    );
+    if has_split {
+        response.push_str("| Archivo | Nombre | 🆔 | 💰 Esperada | 🌐 Pública | 🎯 Categoría | Estado |\n");
+        response.push_str("|---|---|---|---|---|---|---|\n");
+    } else {
+        response.push_str("| Archivo | Nombre | 🆔 | 💰 Esperada | 🎯 Categoría | Estado |\n");
+        response.push_str("|---|---|---|---|---|---|\n");
+    }
+    for row in &rows {
+        response.push_str(&row.render(has_split));
+    }
+    if !details.is_empty() {
+        response.push_str(&format!("\n{}\n", details.join("\n\n")));
+    }
+
+    if after_deadline && scored > 0 {
+        response.push_str("\n⚠️ **ENVÍOS FUERA DE PLAZO** - Registrados pero no compiten\n");
+    }
+    // Remaining quota, counting this batch
+    if quota_limited {
+        if let Ok(usage) = quota::usage(db, &tracks[0].config, message.sender_id, Utc::now()) {
+            response.push_str(&format!("\n{}", usage.render()));
+        }
+    }
+    response
+}
+
+/// Everything computed from a submission file: results per split, metrics,
+/// threshold category, best cut-off and bootstrap intervals.
+struct Scoring {
+    result: GainResult,
+    public: GainResult,
+    private: GainResult,
+    metric_values: Vec<MetricValue>,
+    public_metric_values: Vec<MetricValue>,
+    private_metric_values: Vec<MetricValue>,
+    threshold_category: String,
+    positives_predicted: i32,
+    cutoff: Option<f64>,
+    best: Option<CurvePoint>,
+    /// Kept for the bootstrap intervals, computed after the reply; taken by
+    /// the task that computes them
+    dense: Option<DensePredictions>,
+}
+
+fn score_predictions(
+    predictions: &Predictions,
+    cutoff: Option<f64>,
+    config: &BotConfig,
+    master_data: &MasterData,
+    metrics: &MetricSet,
+) -> Scoring {
+    // The curve gives the best achievable gain for probability files
+    let best = match predictions {
+        Predictions::Scores(scores) => {
+            Some(GainCurve::compute(scores, master_data, &config.gain_matrix).best().clone())
+        }
+        _ => None,
+    };
+
+    let dense = DensePredictions::new(predictions, cutoff.unwrap_or_default(), master_data);
+    let (result, metric_values) = metrics.evaluate_dense(&dense, master_data, None);
+    let (public, public_metric_values) =
+        metrics.evaluate_dense(&dense, master_data, Some(Split::Public));
+    let (private, private_metric_values) =
+        metrics.evaluate_dense(&dense, master_data, Some(Split::Private));
+
+    Scoring {
+        // Set by `Scoring::categorize`, which needs the other submissions
+        threshold_category: String::new(),
+        positives_predicted: dense.positives().count() as i32,
+        dense: Some(dense),
+        result,
+        public,
+        private,
+        metric_values,
+        public_metric_values,
+        private_metric_values,
+        cutoff,
+        best,
+    }
+}
+
+impl Scoring {
+    /// Threshold category from the public score: students only ever get
+    /// feedback derived from the public split.
+    fn categorize(&mut self, config: &BotConfig, metrics: &MetricSet, standing: &Standing) {
+        self.threshold_category = get_threshold_category(
+            self.public.gain,
+            config,
+            metrics.primary().higher_is_better(),
+            standing,
+        );
+    }
+
+    /// Copies the scores into `submission`, leaving its identity untouched.
+    fn apply(&self, submission: &mut Submission) {
+        submission.actual_gain = self.result.gain;
+        submission.tp = self.result.tp;
+        submission.tn = self.result.tn;
+        submission.fp = self.result.fp;
+        submission.fn_ = self.result.fn_;
+        submission.positives_predicted = self.positives_predicted;
+        submission.threshold_category = self.threshold_category.clone();
+        submission.public = self.public.clone();
+        submission.private = self.private.clone();
+        submission.cutoff = self.cutoff;
+        submission.best_gain = self.best.as_ref().map(|p| p.gain);
+        submission.best_cutoff = self.best.as_ref().map(|p| p.threshold);
+        submission.confusion_matrix = self.result.confusion.clone();
+    }
+
+    /// Stores per-scope metrics of a saved submission.
+    fn save_details(&self, db: &Database, submission_id: i64) {
+        for (scope, values) in [
+            ("all", &self.metric_values),
+            ("public", &self.public_metric_values),
+            ("private", &self.private_metric_values),
+        ] {
+            if let Err(e) = db.save_submission_metrics(submission_id, scope, values) {
+                warn!("Error saving metrics for submission {}: {}", submission_id, e);
+            }
+        }
+    }
+}
+
+/// Computes and stores the bootstrap intervals of a saved submission on the
+/// blocking thread pool: with the default iterations they take far longer
+/// than scoring, and the bot keeps answering meanwhile.
+fn spawn_intervals(db: &Database, track: &Track, dense: DensePredictions, submission_id: i64) {
+    if track.config.bootstrap.iterations == 0 {
+        return;
+    }
+    let db = db.clone();
+    let config = track.config.clone();
+    let master_data = Arc::clone(&track.master_data);
+    tokio::task::spawn_blocking(move || {
+        let metrics = MetricSet::from_config(&config);
+        let intervals = compute_intervals(&dense, &master_data, &metrics, &config);
+        save_intervals(&db, submission_id, &intervals);
+    });
+}
+
+fn save_intervals(db: &Database, submission_id: i64, intervals: &[(&'static str, Interval)]) {
+    for (scope, interval) in intervals {
+        if let Err(e) = db.save_submission_interval(submission_id, scope, interval) {
+            warn!("Error saving interval for submission {}: {}", submission_id, e);
+        }
+    }
+}
+
+/// Bootstrap interval of the primary metric for each scope. Without a split
+/// every scope covers the same rows, so it is computed once.
+fn compute_intervals(
+    predictions: &DensePredictions,
+    master_data: &MasterData,
+    metrics: &MetricSet,
+    config: &BotConfig,
+) -> Vec<(&'static str, Interval)> {
+    let interval = |split: Option<Split>| {
+        let rows = bootstrap::rows_in_scope(master_data, split);
+        bootstrap::confidence_interval(
+            metrics.primary(),
+            predictions,
+            master_data,
+            &rows,
+            &config.bootstrap,
+        )
+    };
+
+    let scopes = [
+        ("all", None),
+        ("public", Some(Split::Public)),
+        ("private", Some(Split::Private)),
+    ];
+    if master_data.has_split() {
+        scopes
+            .into_iter()
+            .filter_map(|(scope, split)| interval(split).map(|i| (scope, i)))
+            .collect()
+    } else {
+        match interval(None) {
+            Some(i) => scopes.into_iter().map(|(scope, _)| (scope, i)).collect(),
+            None => Vec::new(),
+        }
+    }
+}
+
+fn interval_label(config: &BotConfig) -> String {
+    format!("IC {}%", config.bootstrap.confidence * 100.0)
+}
+
+fn format_interval(interval: Option<&Interval>) -> String {
+    interval
+        .map(|i| format!("[{:.2}, {:.2}]", i.low, i.high))
+        .unwrap_or_else(|| "N/A".to_string())
+}
+
+/// `score` como porcentaje del oráculo del scope, o N/A sin referencias
+fn format_pct_of_oracle(references: Option<&References>, split: Option<Split>, score: f64) -> String {
+    references
+        .and_then(|r| r.scope(split).pct_of_oracle(score))
+        .map(|pct| format!("{:.1}%", pct))
+        .unwrap_or_else(|| "N/A".to_string())
+}
+
+/// Track de un envío guardado, o un mensaje de error si ya no está configurado.
+fn track_of<'a>(tracks: &'a [Track], submission: &Submission) -> Result<&'a Track, String> {
+    track::find(tracks, &submission.track).ok_or_else(|| {
+        format!(
+            "❌ El track '{}' del envío {} ya no existe en la configuración",
+            submission.track,
+            submission.id.unwrap_or(0)
+        )
+    })
+}
+
+/// Oráculo del track de un envío, para las columnas de % del oráculo.
+fn references_of<'a>(tracks: &'a [Track], submission: &Submission) -> Option<&'a References> {
+    track::find(tracks, &submission.track).and_then(|t| t.references.as_ref())
+}
+
+/// Split que ordena el leaderboard: público antes de la revelación, privado después
+fn leaderboard_split(config: &BotConfig) -> Split {
+    if results_revealed(config) {
+        Split::Private
+    } else {
+        Split::Public
+    }
+}
+
+fn split_label(split: Split) -> &'static str {
+    match split {
+        Split::Public => "Público",
+        Split::Private => "Privado",
+    }
+}
+
+/// Fecha límite de la competencia; sin una fecha válida, nunca vence.
+fn competition_deadline(config: &BotConfig) -> DateTime<Utc> {
+    // Be flexible with date parsing
+    match DateTime::parse_from_rfc3339(&config.competition.deadline) {
         Ok(dt) => dt.with_timezone(&Utc),
         Err(_) => {
             // Try parsing without timezone
@@ -55,306 +862,1276 @@ pub async fn process_submit(
                 }
             }
         }
-    };
-    let after_deadline = Utc::now() > deadline;
-
-    // Parse command
-    let parts: Vec<&str> = message.content.trim().split_whitespace().collect();
-    if parts.len() < 3 {
-        return "❌ Formato incorrecto. Uso: `submit <nombre_envio> <ganancia_esperada>` y adjunta el archivo CSV".to_string();
     }
+}
 
-    let submission_name = parts[1].to_string();
-    let expected_gain: f64 = match parts[2].parse() {
-        Ok(g) => g,
-        Err(_) => return "❌ La ganancia esperada debe ser un número".to_string(),
-    };
-
-    info!(
-        "Submission name: {}, Expected gain: {}",
-        submission_name, expected_gain
-    );
+fn deadline_passed(config: &BotConfig) -> bool {
+    Utc::now() > competition_deadline(config)
+}
 
-    // Extract file from message
-    let (filename, file_content) = match extract_file_from_message(&message.content, config).await {
-        Ok(Some((f, c))) => (f, c),
-        Ok(None) => {
-            return "❌ Debes adjuntar un archivo CSV. Usa el formato: `submit <nombre> <ganancia_esperada>` y adjunta el archivo CSV.".to_string();
-        }
-        Err(e) => {
-            return format!("❌ Error descargando archivo: {}", e);
+/// Verifica si ya se pueden revelar los resultados completos
+fn results_revealed(config: &BotConfig) -> bool {
+    let reveal_date = &config.competition.results_reveal_date;
+    // Intentar parsear la fecha de revelación
+    let reveal_dt = match DateTime::parse_from_rfc3339(reveal_date) {
+        Ok(dt) => dt.with_timezone(&Utc),
+        Err(_) => {
+            // Intentar parsear sin timezone
+            match chrono::NaiveDateTime::parse_from_str(reveal_date, "%Y-%m-%dT%H:%M:%S") {
+                Ok(naive_dt) => DateTime::<Utc>::from_naive_utc_and_offset(naive_dt, Utc),
+                Err(_) => return false, // Si no se puede parsear, no revelar
+            }
         }
     };
+    Utc::now() >= reveal_dt
+}
 
-    if !filename.to_lowercase().ends_with(".csv") {
-        return "❌ El archivo debe ser un CSV".to_string();
+pub fn process_quota(user_id: i64, db: &Database, config: &BotConfig) -> String {
+    match quota::usage(db, config, user_id, Utc::now()) {
+        Ok(usage) => format!("📦 **Tu cuota de envíos:**\n\n{}", usage.render()),
+        Err(e) => format!("❌ Error calculando tu cuota de envíos: {}", e),
     }
+}
 
-    // Save file
-    let file_path = match save_submission_file(
-        &message.sender_full_name,
-        &submission_name,
-        &filename,
-        &file_content,
-        is_teacher,
-        config,
-    ) {
+/// Otorga `extra` envíos a un estudiante, hoy y sobre la cuota total.
+pub fn process_grant(
+    user_id: i64,
+    user_name: &str,
+    extra: u32,
+    teacher_email: &str,
+    db: &Database,
+    config: &BotConfig,
+) -> String {
+    match quota::grant(db, config, user_id, user_name, extra, teacher_email, Utc::now()) {
+        Ok(usage) => format!(
+            "✅ **{} envíos extra para {}**\n\n{}",
+            extra,
+            user_name,
+            usage.render()
+        ),
+        Err(e) => format!("❌ Error otorgando envíos: {}", e),
+    }
+}
+
+/// Elige los envíos de un estudiante que cuentan para el ranking final; sin
+/// IDs muestra la elección actual.
+pub fn process_select(user_id: i64, ids: &[i64], db: &Database, tracks: &[Track]) -> String {
+    // The deadline and the limit are shared by every track
+    let config = &tracks[0].config;
+
+    if ids.is_empty() {
+        return match db.get_selected_submissions(user_id) {
+            Ok(selected) if selected.is_empty() => "📌 No elegiste envíos: en cada track cuenta tu último envío en plazo. \
+                Usa `select <id> [<id>]` para elegir."
+                .to_string(),
+            Ok(selected) => format!(
+                "📌 **Envíos elegidos para el ranking final:** {}",
+                format_ids(&selected)
+            ),
+            Err(e) => format!("❌ Error obteniendo tu selección: {}", e),
+        };
+    }
+
+    if deadline_passed(config) {
+        return "❌ La fecha límite ya pasó: la selección de envíos está cerrada".to_string();
+    }
+
+    let mut ids = ids.to_vec();
+    ids.sort_unstable();
+    ids.dedup();
+
+    let mut by_track: Vec<(&Track, Vec<i64>)> = Vec::new();
+    for &id in &ids {
+        let submission = match db.get_submission(id) {
+            Ok(Some(s)) if s.user_id == user_id => s,
+            Ok(_) => return format!("❌ No tienes un envío con ID {}", id),
+            Err(e) => return format!("❌ Error obteniendo envío: {}", e),
+        };
+        if submission.after_deadline {
+            return format!("❌ El envío {} fue fuera de plazo y no compite", id);
+        }
+        if submission.withdrawal.is_some() {
+            return format!("❌ El envío {} está retirado y no compite", id);
+        }
+        let track = match track_of(tracks, &submission) {
+            Ok(t) => t,
+            Err(e) => return e,
+        };
+        match by_track.iter_mut().find(|(t, _)| t.name == track.name) {
+            Some((_, track_ids)) => track_ids.push(id),
+            None => by_track.push((track, vec![id])),
+        }
+    }
+
+    let max = config.competition.max_selected;
+    if let Some((track, track_ids)) = by_track.iter().find(|(_, track_ids)| track_ids.len() > max) {
+        return format!(
+            "❌ Puedes elegir hasta {} envíos por track, elegiste {} en `{}`",
+            max,
+            track_ids.len(),
+            track.name
+        );
+    }
+
+    let now = Utc::now().to_rfc3339();
+    for (track, track_ids) in &by_track {
+        if let Err(e) = db.select_final_submissions(user_id, &track.name, track_ids, &now) {
+            return format!("❌ Error guardando la selección: {}", e);
+        }
+    }
+
+    let mut response = String::from("✅ **Envíos elegidos para el ranking final**\n\n");
+    for (track, track_ids) in &by_track {
+        if tracks.len() > 1 {
+            response.push_str(&format!("🛤️ **{}:** {}\n", track.name, format_ids(track_ids)));
+        } else {
+            response.push_str(&format!("📌 {}\n", format_ids(track_ids)));
+        }
+    }
+    response.push_str("\nEl leaderboard usa el mejor de ellos; tus otros envíos del track no cuentan.");
+    response
+}
+
+/// Retira un envío propio antes de la fecha límite. El envío queda guardado
+/// para los profesores, pero deja de competir y de contar en la cuota.
+pub fn process_withdraw(user_id: i64, submission_id: i64, reason: &str, db: &Database, tracks: &[Track]) -> String {
+    let config = &tracks[0].config;
+    if deadline_passed(config) {
+        return "❌ La fecha límite ya pasó: no se pueden retirar envíos".to_string();
+    }
+    let reason = reason.trim();
+    if reason.is_empty() {
+        return "❌ Indica el motivo. Uso: `withdraw <id_envío> <motivo>`".to_string();
+    }
+
+    let submission = match db.get_submission(submission_id) {
+        Ok(Some(s)) if s.user_id == user_id => s,
+        Ok(_) => return format!("❌ No tienes un envío con ID {}", submission_id),
+        Err(e) => return format!("❌ Error obteniendo envío: {}", e),
+    };
+    if submission.withdrawal.is_some() {
+        return format!("❌ El envío {} ya estaba retirado", submission_id);
+    }
+
+    let withdrawal = Withdrawal {
+        at: Utc::now().to_rfc3339(),
+        reason: reason.to_string(),
+    };
+    if let Err(e) = db.withdraw_submission(submission_id, &withdrawal) {
+        return format!("❌ Error retirando envío: {}", e);
+    }
+    info!("Submission {} withdrawn by user {}", submission_id, user_id);
+
+    format!(
+        "🗑️ **Envío {} (`{}`) retirado**\n\
+        • **Motivo:** {}\n\n\
+        Ya no cuenta para el leaderboard ni para tu cuota de envíos.",
+        submission_id, submission.submission_name, withdrawal.reason
+    )
+}
+
+/// Whether the Zulip user `user_id` already has a submission called `name`,
+/// ignoring case and withdrawn submissions, apart from `except`.
+fn name_taken(db: &Database, user_id: i64, name: &str, except: Option<i64>) -> Result<bool> {
+    let name = name.to_lowercase();
+    Ok(db
+        .get_submission_names(user_id)?
+        .iter()
+        .any(|(id, taken)| Some(*id) != except && taken.to_lowercase() == name))
+}
+
+/// Cambia el nombre de un envío propio, que debe seguir siendo único.
+pub fn process_rename(user_id: i64, submission_id: i64, name: &str, db: &Database) -> String {
+    let name = match args::parse_name(name) {
+        Ok(name) => name,
+        Err(e) => return e,
+    };
+    let submission = match db.get_submission(submission_id) {
+        Ok(Some(s)) if s.user_id == user_id => s,
+        Ok(_) => return format!("❌ No tienes un envío con ID {}", submission_id),
+        Err(e) => return format!("❌ Error obteniendo envío: {}", e),
+    };
+    match name_taken(db, user_id, &name, Some(submission_id)) {
+        Ok(true) => return format!("❌ Ya tienes un envío llamado `{}`", name),
+        Ok(false) => {}
+        Err(e) => return format!("❌ Error obteniendo envíos: {}", e),
+    }
+
+    if let Err(e) = db.rename_submission(submission_id, &name) {
+        return format!("❌ Error renombrando envío: {}", e);
+    }
+    format!(
+        "✏️ Envío {} renombrado: `{}` → `{}`",
+        submission_id, submission.submission_name, name
+    )
+}
+
+fn format_ids(ids: &[i64]) -> String {
+    ids.iter().map(|id| format!("`{}`", id)).collect::<Vec<_>>().join(", ")
+}
+
+pub fn process_list_submits(user_name: &str, db: &Database, tracks: &[Track]) -> String {
+    let submissions = match db.get_user_submissions(user_name) {
+        Ok(s) => s,
+        Err(e) => return format!("❌ Error obteniendo envíos: {}", e),
+    };
+
+    if submissions.is_empty() {
+        return "📋 No tienes envíos registrados".to_string();
+    }
+
+    // The reveal date is shared by every track
+    let config = &tracks[0].config;
+    let show_results = results_revealed(config);
+    let selected = db.get_selected_submissions(submissions[0].user_id).unwrap_or_default();
+
+    let mut response = "📋 **Tus Envíos:**\n\n".to_string();
+
+    if tracks.len() == 1 {
+        response.push_str(&submits_table(&submissions, &selected, show_results, tracks[0].master_data.has_split()));
+    } else {
+        for track in tracks {
+            let track_submissions: Vec<Submission> = submissions
+                .iter()
+                .filter(|sub| sub.track == track.name)
+                .cloned()
+                .collect();
+            if track_submissions.is_empty() {
+                continue;
+            }
+            response.push_str(&format!("🛤️ **Track {}**\n\n", track.name));
+            response.push_str(&submits_table(&track_submissions, &selected, show_results, track.master_data.has_split()));
+            response.push('\n');
+        }
+    }
+
+    if !selected.is_empty() {
+        response.push_str("\n📌 *Elegidos para el ranking final*\n");
+    }
+
+    // Informar cuándo se revelarán los resultados si aún no se han revelado
+    if !show_results {
+        let reveal_date = &config.competition.results_reveal_date; 
+        let reveal_str: String = reveal_date.chars().take(16).collect();
+        response.push_str(&format!(
+            "\n📊 *Los resultados completos se revelarán el {}*",
+            reveal_str
+        ));
+    }
+    response
+}
+
+/// Tabla de envíos de un track; las columnas dependen de la revelación y de la partición.
+fn submits_table(submissions: &[Submission], selected: &[i64], show_results: bool, has_split: bool) -> String {
+    let mut response = String::new();
+
+    if show_results && has_split {
+        // Con partición, después de la revelación se muestran ambas ganancias
+        response.push_str("| ID | Nombre | 📅 Fecha | 💰 Esperada | 🌐 Pública | 🔒 Privada | 🎯 Categoría | ⏰ |\n");
+        response.push_str("|---|---|---|---|---|---|---|---|\n");
+
+        for sub in submissions {
+            let deadline_mark = if sub.after_deadline { "⚠️" } else { "✅" };
+            let ts_str: String = sub.timestamp.chars().take(16).collect();
+            response.push_str(&format!(
+                "|{}|{}|{}|{:.2}|{:.2}|{:.2}|{}|{}|\n",
+                format_selected_id(sub, selected),
+                sub.submission_name,
+                ts_str,
+                sub.expected_gain,
+                sub.public.gain,
+                sub.private.gain,
+                sub.threshold_category,
+                deadline_mark
+            ));
+        }
+    } else if show_results {
+        // Mostrar información completa después de la fecha de revelación
+        response.push_str("| ID | Nombre | 📅 Fecha | 💰 Esperada | ✨ Real | 🎯 Categoría | ⏰ |\n");
+        response.push_str("|---|---|---|---|---|---|---|\n");
+
+        for sub in submissions {
+            let deadline_mark = if sub.after_deadline { "⚠️" } else { "✅" };
+            let ts_str: String = sub.timestamp.chars().take(16).collect();
+            response.push_str(&format!(
+                "|{}|{}|{}|{:.2}|{:.2}|{}|{}|\n",
+                format_selected_id(sub, selected),
+                sub.submission_name,
+                ts_str,
+                sub.expected_gain,
+                sub.actual_gain,
+                sub.threshold_category,
+                deadline_mark
+            ));
+        }
+    } else if has_split {
+        // Antes de la revelación solo se muestra la ganancia pública
+        response.push_str("| ID | Nombre | 📅 Fecha | 💰 Esperada | 🌐 Pública | 🎯 Categoría | ⏰ |\n");
+        response.push_str("|---|---|---|---|---|---|---|\n");
+
+        for sub in submissions {
+            let deadline_mark = if sub.after_deadline { "⚠️" } else { "✅" };
+            let ts_str: String = sub.timestamp.chars().take(16).collect();
+            response.push_str(&format!(
+                "|{}|{}|{}|{:.2}|{:.2}|{}|{}|\n",
+                format_selected_id(sub, selected),
+                sub.submission_name,
+                ts_str,
+                sub.expected_gain,
+                sub.public.gain,
+                sub.threshold_category,
+                deadline_mark
+            ));
+        }
+    } else {
+        // Mostrar información limitada antes de la fecha de revelación
+        response.push_str("| ID | Nombre | 📅 Fecha | 💰 Esperada | 🎯 Categoría | ⏰ |\n");
+        response.push_str("|---|---|---|---|---|---|\n");
+
+        for sub in submissions {
+            let deadline_mark = if sub.after_deadline { "⚠️" } else { "✅" };
+            let ts_str: String = sub.timestamp.chars().take(16).collect();
+            response.push_str(&format!(
+                "|{}|{}|{}|{:.2}|{}|{}|\n",
+                format_selected_id(sub, selected),
+                sub.submission_name,
+                ts_str,
+                sub.expected_gain,
+                sub.threshold_category,
+                deadline_mark
+            ));
+        }
+    }
+    response
+}
+
+/// ID del envío, marcado con 📌 si fue elegido para el ranking final.
+fn format_selected_id(sub: &Submission, selected: &[i64]) -> String {
+    let id = sub.id.unwrap_or(0);
+    if selected.contains(&id) {
+        format!("📌 {}", id)
+    } else {
+        id.to_string()
+    }
+}
+
+pub fn process_duplicates(db: &Database) -> String {
+    let duplicates = match db.get_duplicates() {
+        Ok(d) => d,
+        Err(e) => return format!("❌ Error obteniendo duplicados: {}", e),
+    };
+
+    if duplicates.is_empty() {
+        return "✅ No se encontraron envíos duplicados".to_string();
+    }
+
+    let mut response = "🔍 **Envíos Duplicados:**\n\n".to_string();
+    for (checksum, _count, users, names) in duplicates {
+        response.push_str(&format!("**Checksum:** `{}...`\n", &checksum[..16]));
+        response.push_str(&format!("**Usuarios:** {}\n", users));
+        response.push_str(&format!("**Envíos:** {}\n\n", names));
+    }
+
+    response
+}
+
+pub fn process_verify_storage(db: &Database, config: &BotConfig) -> String {
+    match storage::verify(db, config) {
+        Ok(report) => report.render(),
+        Err(e) => format!("❌ Error verificando el almacenamiento: {}", e),
+    }
+}
+
+pub fn process_leaderboard_full(db: &Database, track: &Track, order_by: &str) -> String {
+    let config = &track.config;
+    let metrics = &track.metrics;
+    let references = track.references.as_ref();
+    // Before the reveal the ranking uses the public split, afterwards the private one
+    let split = leaderboard_split(config);
+
+    let results = match db.get_leaderboard(&track.name, order_by, split, metrics.primary().higher_is_better()) {
+        Ok(r) => r,
+        Err(e) => return format!("❌ Error obteniendo leaderboard: {}", e),
+    };
+
+    if results.is_empty() {
+        return "📊 No hay submissions en el leaderboard".to_string();
+    }
+
+    let order_label = match order_by {
+        "datetime" => "Ordenado por Fecha".to_string(),
+        _ => format!("Ordenado por {}", metrics.primary().name()),
+    };
+
+    let mut response = format!(
+        "🏆 **Leaderboard Completo - {} ({}, {})** \n\n",
+        config.competition.name,
+        order_label,
+        split_label(split)
+    );
+    if !config.competition.tracks.is_empty() {
+        response.push_str(&format!("🛤️ **Track:** {}\n\n", track.name));
+    }
+    response.push_str(&format!(
+        "| Pos | Nombre | TS | 💰 Elegido | 🎯 % Oráculo | 📏 {} | 💰 Esperada | 📊 Envíos | 📈 Máximo |\n",
+        interval_label(config)
+    ));
+    response.push_str("|---|---|---|---|---|---|---|---|---|\n");
+
+    for (i, entry) in results.iter().enumerate() {
+        if !config.teachers.contains(&entry.user_email) {
+            let max_str = entry
+                .max_gain
+                .map(|a| format!("{:.2}", a))
+                .unwrap_or_else(|| "N/A".to_string());
+            let ts_str: String = entry.timestamp.chars().take(16).collect();
+            response.push_str(&format!(
+                "| {} | {} | {} | {} | {} | {} | {:.2} | {} | {} |\n",
+                i + 1,
+                entry.user_full_name,
+                ts_str,
+                format_chosen(entry),
+                format_pct_of_oracle(references, Some(split), entry.final_gain),
+                format_interval(entry.interval.as_ref()),
+                entry.final_expected_gain,
+                entry.total_submissions,
+                max_str
+            ));
+        }
+    }
+
+    response
+}
+
+/// Puntaje que cuenta en el leaderboard, con 📌 si el estudiante lo eligió.
+fn format_chosen(entry: &LeaderboardEntry) -> String {
+    if entry.selected {
+        format!("{:.2} 📌", entry.final_gain)
+    } else {
+        format!("{:.2}", entry.final_gain)
+    }
+}
+
+pub fn process_metrics(submission_id: i64, db: &Database, tracks: &[Track]) -> String {
+    let submission = match db.get_submission(submission_id) {
+        Ok(Some(s)) => s,
+        Ok(None) => return format!("❌ No existe el envío {}", submission_id),
+        Err(e) => return format!("❌ Error obteniendo envío: {}", e),
+    };
+    let track = match track_of(tracks, &submission) {
+        Ok(t) => t,
+        Err(e) => return e,
+    };
+    let config = &track.config;
+    let references = track.references.as_ref();
+
+    let metrics = match db.get_submission_metrics(submission_id) {
+        Ok(m) => m,
+        Err(e) => return format!("❌ Error obteniendo métricas: {}", e),
+    };
+
+    if metrics.is_empty() {
+        return format!("📐 No hay métricas registradas para el envío {}", submission_id);
+    }
+
+    // Group by metric, keeping the order in which they were stored
+    let mut rows: Vec<(String, HashMap<String, Option<f64>>)> = Vec::new();
+    for (metric, scope, value) in metrics {
+        match rows.iter_mut().find(|(name, _)| *name == metric) {
+            Some((_, scopes)) => {
+                scopes.insert(scope, value);
+            }
+            None => rows.push((metric, HashMap::from([(scope, value)]))),
+        }
+    }
+
+    let format_value = |scopes: &HashMap<String, Option<f64>>, scope: &str| {
+        scopes
+            .get(scope)
+            .copied()
+            .flatten()
+            .map(|v| format!("{:.4}", v))
+            .unwrap_or_else(|| "N/A".to_string())
+    };
+
+    let mut response = format!("📐 **Métricas del envío {}:**\n\n", submission_id);
+    response.push_str("| Métrica | ✨ Total | 🌐 Pública | 🔒 Privada |\n");
+    response.push_str("|---|---|---|---|\n");
+    for (metric, scopes) in &rows {
+        response.push_str(&format!(
+            "| {} | {} | {} | {} |\n",
+            metric,
+            format_value(scopes, "all"),
+            format_value(scopes, "public"),
+            format_value(scopes, "private")
+        ));
+    }
+
+    if let Ok(intervals) = db.get_submission_intervals(submission_id) {
+        if !intervals.is_empty() {
+            let interval = |scope: &str| {
+                format_interval(intervals.iter().find(|(s, _)| s == scope).map(|(_, i)| i))
+            };
+            response.push_str(&format!(
+                "| 📏 {} ({}) | {} | {} | {} |\n",
+                interval_label(config),
+                rows[0].0,
+                interval("all"),
+                interval("public"),
+                interval("private")
+            ));
+        }
+    }
+
+    if references.is_some() {
+        response.push_str(&format!(
+            "| 🎯 % del oráculo ({}) | {} | {} | {} |\n",
+            rows[0].0,
+            format_pct_of_oracle(references, None, submission.actual_gain),
+            format_pct_of_oracle(references, Some(Split::Public), submission.public.gain),
+            format_pct_of_oracle(references, Some(Split::Private), submission.private.gain)
+        ));
+    }
+
+    if let Some(confusion) = &submission.confusion_matrix {
+        response.push_str("\n🔢 **Matriz de confusión (filas: real, columnas: predicha):**\n\n");
+        response.push_str(&format_confusion_matrix(confusion, track.master_data.class_labels()));
+    }
+
+    if let Ok(history) = db.get_score_history(submission_id) {
+        if !history.is_empty() {
+            response.push_str("\n🕰️ **Puntajes anteriores (reemplazados por `rescore`):**\n\n");
+            for (replaced_at, gain) in history {
+                let ts_str: String = replaced_at.chars().take(16).collect();
+                response.push_str(&format!("• {} → {:.4}\n", ts_str, gain));
+            }
+        }
+    }
+
+    response
+}
+
+fn format_confusion_matrix(confusion: &[Vec<i32>], labels: &[String]) -> String {
+    let label = |i: usize| labels.get(i).cloned().unwrap_or_else(|| i.to_string());
+
+    let mut table = "| real \\ pred |".to_string();
+    for j in 0..confusion.len() {
+        table.push_str(&format!(" {} |", label(j)));
+    }
+    table.push_str(&format!("\n|{}\n", "---|".repeat(confusion.len() + 1)));
+
+    for (i, row) in confusion.iter().enumerate() {
+        table.push_str(&format!("| **{}** |", label(i)));
+        for count in row {
+            table.push_str(&format!(" {} |", count));
+        }
+        table.push('\n');
+    }
+
+    table
+}
+
+pub fn process_gain_curve(submission_id: i64, db: &Database, tracks: &[Track]) -> String {
+    const MAX_ROWS: usize = 25;
+
+    let submission = match db.get_submission(submission_id) {
+        Ok(Some(s)) => s,
+        Ok(None) => return format!("❌ No existe el envío {}", submission_id),
+        Err(e) => return format!("❌ Error obteniendo envío: {}", e),
+    };
+    let track = match track_of(tracks, &submission) {
+        Ok(t) => t,
+        Err(e) => return e,
+    };
+    let config = &track.config;
+    let master_data = &track.master_data;
+
+    let predictions = match fs::read(storage::path_of(config, &submission))
+        .map_err(anyhow::Error::from)
+        .and_then(|content| parse_predictions(&content, config, None))
+        .map(|file| file.predictions)
+    {
         Ok(p) => p,
-        Err(e) => return format!("❌ Error guardando archivo: {}", e),
+        Err(e) => return format!("❌ Error leyendo {}: {}", storage::path_of(config, &submission).display(), e),
+    };
+
+    let scores = match predictions {
+        Predictions::Scores(scores) => scores,
+        _ => {
+            return format!(
+                "❌ El envío {} no tiene probabilidades (formato `id,probability`)",
+                submission_id
+            )
+        }
+    };
+
+    let curve = GainCurve::compute(&scores, master_data, &config.gain_matrix);
+    let best = curve.best();
+
+    let mut response = format!(
+        "📈 **Curva de ganancia - Envío {} ({}, {})**\n\n",
+        submission_id, submission.submission_name, submission.user_full_name
+    );
+    response.push_str(&format!(
+        "**Corte declarado:** {} | **Mejor corte:** {} ({} positivos, ganancia {:.2})\n\n",
+        submission
+            .cutoff
+            .map(format_threshold)
+            .unwrap_or_else(|| "N/A".to_string()),
+        format_threshold(best.threshold),
+        best.positives,
+        best.gain
+    ));
+    response.push_str("| Corte | Positivos | ✨ Ganancia | 🌐 Pública | 🔒 Privada |\n");
+    response.push_str("|---|---|---|---|---|\n");
+
+    for point in curve.sample(MAX_ROWS) {
+        let best_mark = if std::ptr::eq(point, best) { " 🏅" } else { "" };
+        response.push_str(&format!(
+            "| {} | {} | {:.2}{} | {:.2} | {:.2} |\n",
+            format_threshold(point.threshold),
+            point.positives,
+            point.gain,
+            best_mark,
+            point.public_gain,
+            point.private_gain
+        ));
+    }
+
+    response
+}
+
+/// Recalcula todos los envíos guardados con el dataset maestro y la
+/// configuración actuales, archivando los puntajes anteriores, y muestra
+/// cómo cambian las posiciones del leaderboard.
+pub fn process_rescore(db: &Database, track: &Track) -> String {
+    let config = &track.config;
+    let master_data = &track.master_data;
+    let metrics = &track.metrics;
+    let references = track.references.as_ref();
+    let split = leaderboard_split(config);
+    let higher_is_better = metrics.primary().higher_is_better();
+
+    let before = match db.get_leaderboard(&track.name, "gain", split, higher_is_better) {
+        Ok(r) => r,
+        Err(e) => return format!("❌ Error obteniendo leaderboard: {}", e),
+    };
+    let submissions: Vec<Submission> = match db.get_all_submissions() {
+        Ok(s) => s.into_iter().filter(|sub| sub.track == track.name).collect(),
+        Err(e) => return format!("❌ Error obteniendo envíos: {}", e),
     };
 
-    // Calculate checksum
-    let checksum = calculate_checksum(&file_content);
-    info!(
-        "File saved: {}, checksum: {}...",
-        file_path,
-        &checksum[..16]
-    );
+    let replaced_at = Utc::now().to_rfc3339();
+    let mut rescored = 0;
+    let mut changed = 0;
+    let mut failures = Vec::new();
+    let mut scored = Vec::new();
+
+    for old in &submissions {
+        let id = old.id.unwrap_or(0);
+        let predictions = match fs::read(storage::path_of(config, old))
+            .map_err(anyhow::Error::from)
+            .and_then(|content| parse_predictions(&content, config, None))
+            .map(|file| file.predictions)
+        {
+            Ok(p) => p,
+            Err(e) => {
+                warn!("Could not rescore submission {}: {}", id, e);
+                failures.push(format!("{} ({}): {}", id, storage::path_of(config, old).display(), e));
+                continue;
+            }
+        };
+
+        // Keep the cut-off the student declared
+        let cutoff = match predictions {
+            Predictions::Scores(_) => Some(old.cutoff.unwrap_or(config.submissions.default_cutoff)),
+            _ => None,
+        };
+        scored.push((old, score_predictions(&predictions, cutoff, config, master_data, metrics)));
+    }
+
+    // Relative threshold categories compare against the new public scores;
+    // submissions that could not be rescored keep their old ones
+    let mut public_scores: HashMap<i64, f64> = submissions
+        .iter()
+        .filter(|sub| !sub.after_deadline && sub.withdrawal.is_none())
+        .map(|sub| (sub.id.unwrap_or(0), sub.public.gain))
+        .collect();
+    for (old, scoring) in &scored {
+        if let Some(score) = public_scores.get_mut(&old.id.unwrap_or(0)) {
+            *score = scoring.public.gain;
+        }
+    }
+
+    for (old, mut scoring) in scored {
+        let id = old.id.unwrap_or(0);
+        let peers: Vec<f64> = public_scores
+            .iter()
+            .filter(|(peer, _)| **peer != id)
+            .map(|(_, score)| *score)
+            .collect();
+        scoring.categorize(config, metrics, &Standing { references, peers: &peers });
+        let mut new = old.clone();
+        scoring.apply(&mut new);
+
+        if let Err(e) = db.update_submission_scores(old, &new, &replaced_at) {
+            failures.push(format!("{}: {}", id, e));
+            continue;
+        }
+        scoring.save_details(db, id);
+        // Rescoring runs as a batch job, from the CLI or off the runtime from chat,
+        // so its intervals are computed in place
+        if let Some(dense) = &scoring.dense {
+            save_intervals(db, id, &compute_intervals(dense, master_data, metrics, config));
+        }
+
+        rescored += 1;
+        if new.actual_gain != old.actual_gain
+            || new.public.gain != old.public.gain
+            || new.private.gain != old.private.gain
+        {
+            changed += 1;
+        }
+    }
+
+    info!(
+        "Rescore finished: {} rescored, {} changed, {} failed",
+        rescored,
+        changed,
+        failures.len()
+    );
+
+    let after = match db.get_leaderboard(&track.name, "gain", split, higher_is_better) {
+        Ok(r) => r,
+        Err(e) => return format!("❌ Error obteniendo leaderboard: {}", e),
+    };
+
+    let mut response = format!(
+        "🔄 **Rescore - {} ({})**\n\n\
+         **Envíos recalculados:** {} de {}\n\
+         **Con puntaje distinto:** {}\n",
+        config.competition.name,
+        track.name,
+        rescored,
+        submissions.len(),
+        changed
+    );
+    if !failures.is_empty() {
+        response.push_str(&format!("\n⚠️ **No se pudieron recalcular ({}):**\n", failures.len()));
+        for failure in &failures {
+            response.push_str(&format!("• {}\n", failure));
+        }
+    }
+
+    // Ranks only count students, as in the leaderboard
+    let ranked = |entries: &[LeaderboardEntry]| -> Vec<LeaderboardEntry> {
+        entries
+            .iter()
+            .filter(|e| !config.teachers.contains(&e.user_email))
+            .cloned()
+            .collect()
+    };
+    let before = ranked(&before);
+    let after = ranked(&after);
+    if after.is_empty() {
+        return response;
+    }
+
+    response.push_str(&format!(
+        "\n📊 **Posiciones ({}):**\n\n",
+        split_label(split)
+    ));
+    response.push_str("| Nombre | Pos. antes | Pos. después | Cambio | 💰 Antes | 💰 Después |\n");
+    response.push_str("|---|---|---|---|---|---|\n");
+    for (new_rank, entry) in after.iter().enumerate() {
+        let previous = before
+            .iter()
+            .enumerate()
+            .find(|(_, e)| e.user_email == entry.user_email);
+        let (old_rank, old_gain, movement) = match previous {
+            Some((old_rank, old)) => (
+                (old_rank + 1).to_string(),
+                format!("{:.2}", old.final_gain),
+                match old_rank.cmp(&new_rank) {
+                    std::cmp::Ordering::Greater => format!("⬆️ {}", old_rank - new_rank),
+                    std::cmp::Ordering::Less => format!("⬇️ {}", new_rank - old_rank),
+                    std::cmp::Ordering::Equal => "=".to_string(),
+                },
+            ),
+            None => ("-".to_string(), "-".to_string(), "🆕".to_string()),
+        };
+        response.push_str(&format!(
+            "| {} | {} | {} | {} | {} | {:.2} |\n",
+            entry.user_full_name,
+            old_rank,
+            new_rank + 1,
+            movement,
+            old_gain,
+            entry.final_gain
+        ));
+    }
+
+    response
+}
+
+/// Prueba de bootstrap pareado entre dos envíos, sobre el split del leaderboard.
+pub fn process_significance(
+    first_id: i64,
+    second_id: i64,
+    db: &Database,
+    tracks: &[Track],
+) -> String {
+    let mut submissions = Vec::new();
+    for id in [first_id, second_id] {
+        match db.get_submission(id) {
+            Ok(Some(s)) => submissions.push(s),
+            Ok(None) => return format!("❌ No existe el envío {}", id),
+            Err(e) => return format!("❌ Error obteniendo envío: {}", e),
+        }
+    }
+    if submissions[0].track != submissions[1].track {
+        return format!(
+            "❌ Los envíos {} y {} son de tracks distintos ({} y {}) y no se pueden comparar",
+            first_id, second_id, submissions[0].track, submissions[1].track
+        );
+    }
+    let track = match track_of(tracks, &submissions[0]) {
+        Ok(t) => t,
+        Err(e) => return e,
+    };
+    let config = &track.config;
+    let master_data = &track.master_data;
+    let metrics = &track.metrics;
+
+    let mut loaded = Vec::new();
+    for submission in submissions {
+        let predictions = match fs::read(storage::path_of(config, &submission))
+            .map_err(anyhow::Error::from)
+            .and_then(|content| parse_predictions(&content, config, None))
+            .map(|file| file.predictions)
+        {
+            Ok(p) => p,
+            Err(e) => return format!("❌ Error leyendo {}: {}", storage::path_of(config, &submission).display(), e),
+        };
+        let dense = DensePredictions::new(&predictions, submission.cutoff.unwrap_or_default(), master_data);
+        loaded.push((submission, dense));
+    }
+
+    let split = leaderboard_split(config);
+    let rows = bootstrap::rows_in_scope(master_data, Some(split));
+    let metric = metrics.primary();
+
+    let comparison = match bootstrap::paired_test(
+        metric,
+        &loaded[0].1,
+        &loaded[1].1,
+        master_data,
+        &rows,
+        &config.bootstrap,
+    ) {
+        Some(c) => c,
+        None => {
+            return format!(
+                "❌ No se pudo comparar: {} no está definida para estos envíos o el bootstrap está desactivado",
+                metric.name()
+            )
+        }
+    };
+
+    let mut response = format!(
+        "⚖️ **Significancia: envío {} vs envío {} ({}, {})**\n\n",
+        first_id,
+        second_id,
+        metric.name(),
+        split_label(split)
+    );
+    response.push_str("| ID | Nombre | Usuario | Valor |\n");
+    response.push_str("|---|---|---|---|\n");
+    for (submission, predictions) in &loaded {
+        let value = metrics::evaluate_rows(metric, predictions, master_data, &rows);
+        response.push_str(&format!(
+            "| {} | {} | {} | {} |\n",
+            submission.id.unwrap_or(0),
+            submission.submission_name,
+            submission.user_full_name,
+            value
+                .map(|v| format!("{:.4}", v))
+                .unwrap_or_else(|| "N/A".to_string())
+        ));
+    }
+
+    let alpha = 1.0 - config.bootstrap.confidence;
+    response.push_str(&format!(
+        "\n**Diferencia ({} - {}):** {:.4}\n",
+        first_id, second_id, comparison.difference
+    ));
+    response.push_str(&format!(
+        "**p-valor (bootstrap pareado, {} remuestreos):** {:.4}\n",
+        comparison.iterations, comparison.p_value
+    ));
+    response.push_str(&format!(
+        "**{} mejor que {} en:** {:.1}% de los remuestreos\n\n",
+        first_id,
+        second_id,
+        comparison.win_rate * 100.0
+    ));
+    if comparison.p_value < alpha {
+        response.push_str(&format!("✅ La diferencia es significativa (α = {})\n", alpha));
+    } else {
+        response.push_str(&format!(
+            "🤝 La diferencia no es significativa (α = {}): es un empate estadístico\n",
+            alpha
+        ));
+    }
+
+    response
+}
 
-    // Read and validate CSV
-    let predicted_ids = match read_csv_ids(&file_content) {
-        Ok(ids) => ids,
-        Err(e) => return format!("❌ Error leyendo CSV: {}", e),
+/// Combina envíos binarios guardados con una regla de votación y puntúa el
+/// resultado sin guardarlo como envío.
+pub fn process_ensemble(ids: &[i64], rule: Rule, db: &Database, tracks: &[Track]) -> String {
+    let mut unique_ids: Vec<i64> = Vec::new();
+    for id in ids {
+        if !unique_ids.contains(id) {
+            unique_ids.push(*id);
+        }
+    }
+    if unique_ids.len() < 2 {
+        return "❌ Un ensamble necesita al menos dos envíos distintos".to_string();
+    }
+    let min_votes = match rule.min_votes(unique_ids.len()) {
+        Ok(v) => v,
+        Err(e) => return format!("❌ Regla inválida: {}", e),
     };
 
-    // Validate IDs
-    let invalid_ids = master_data.validate_ids(&predicted_ids);
-    if !invalid_ids.is_empty() {
-        warn!("Invalid IDs in submission from {}", user_email);
+    let mut submissions = Vec::new();
+    for id in &unique_ids {
+        match db.get_submission(*id) {
+            Ok(Some(s)) => submissions.push(s),
+            Ok(None) => return format!("❌ No existe el envío {}", id),
+            Err(e) => return format!("❌ Error obteniendo envío: {}", e),
+        }
+    }
+    if let Some(other) = submissions.iter().find(|s| s.track != submissions[0].track) {
         return format!(
-            "❌ IDs inválidos encontrados: {} IDs no existen en el dataset",
-            invalid_ids.len()
+            "❌ Los envíos {} y {} son de tracks distintos ({} y {}) y no se pueden combinar",
+            submissions[0].id.unwrap_or(0),
+            other.id.unwrap_or(0),
+            submissions[0].track,
+            other.track
         );
     }
+    let track = match track_of(tracks, &submissions[0]) {
+        Ok(t) => t,
+        Err(e) => return e,
+    };
+    let config = &track.config;
+    let master_data = &track.master_data;
+    let metrics = &track.metrics;
+    if config.competition.task != TaskType::Binary {
+        return "❌ Los ensambles solo están disponibles en competencias binarias".to_string();
+    }
 
-    // Calculate gain
-    info!("Calculating gain for {}", submission_name);
-    let gain_result = calculate_gain(&predicted_ids, master_data, &config.gain_matrix);
-    let threshold_category = get_threshold_category(gain_result.gain, config);
-    let positives_predicted = predicted_ids.len() as i32;
-
-    info!(
-        "Gain calculated - Expected: {:.4}, Actual: {:.4}",
-        expected_gain, gain_result.gain
-    );
+    let mut members = Vec::new();
+    for submission in &submissions {
+        let predictions = match fs::read(storage::path_of(config, submission))
+            .map_err(anyhow::Error::from)
+            .and_then(|content| parse_predictions(&content, config, None))
+            .map(|file| file.predictions)
+        {
+            Ok(p) => p,
+            Err(e) => return format!("❌ Error leyendo {}: {}", storage::path_of(config, submission).display(), e),
+        };
+        members.push(DensePredictions::new(&predictions, submission.cutoff.unwrap_or_default(), master_data));
+    }
+    let positives: Vec<_> = members.iter().map(|m| m.positives()).collect();
+    let ensemble = DensePredictions::from_positives(ensemble::combine(&positives, min_votes));
 
-    // Create submission record
-    let submission = Submission {
-        id: None,
-        user_id: message.sender_id,
-        user_email: user_email.clone(),
-        user_full_name: message.sender_full_name.clone(),
-        submission_name: submission_name.clone(),
-        timestamp: Utc::now().to_rfc3339(),
-        file_checksum: checksum,
-        file_path,
-        expected_gain,
-        actual_gain: gain_result.gain,
-        tp: gain_result.tp,
-        tn: gain_result.tn,
-        fp: gain_result.fp,
-        fn_: gain_result.fn_,
-        positives_predicted,
-        threshold_category: threshold_category.clone(),
-        after_deadline,
+    let score = |predictions: &DensePredictions| {
+        [None, Some(Split::Public), Some(Split::Private)]
+            .map(|split| metrics.evaluate_dense(predictions, master_data, split).0.gain)
     };
-
-    // Save to database
-    let submission_id = match db.save_submission(&submission) {
-        Ok(id) => id,
-        Err(e) => return format!("❌ Error guardando envío: {}", e),
+    let row = |label: String, name: &str, predictions: &DensePredictions| {
+        let [all, public, private] = score(predictions);
+        format!(
+            "| {} | {} | {} | {:.2} | {:.2} | {:.2} |\n",
+            label,
+            name,
+            predictions.positives().count(),
+            all,
+            public,
+            private
+        )
     };
 
-    info!("Submission saved with ID: {}", submission_id);
+    let mut response = format!(
+        "🧩 **Ensamble por {} ({} de {} votos) - {}**\n\n",
+        rule.label(),
+        min_votes,
+        submissions.len(),
+        metrics.primary().name()
+    );
+    if !config.competition.tracks.is_empty() {
+        response.push_str(&format!("🛤️ **Track:** {}\n\n", track.name));
+    }
+    response.push_str("| ID | Nombre | ✅ Positivos | ✨ Total | 🌐 Pública | 🔒 Privada |\n");
+    response.push_str("|---|---|---|---|---|---|\n");
+    for (submission, predictions) in submissions.iter().zip(&members) {
+        let name = format!("{} ({})", submission.submission_name, submission.user_full_name);
+        response.push_str(&row(submission.id.unwrap_or(0).to_string(), &name, predictions));
+    }
+    response.push_str(&row("🧩".to_string(), "**Ensamble**", &ensemble));
 
-    // Build response
-    let threshold_config = config
-        .gain_thresholds
+    let split = leaderboard_split(config);
+    let scope = |predictions: &DensePredictions| metrics.evaluate_dense(predictions, master_data, Some(split)).0.gain;
+    let ensemble_score = scope(&ensemble);
+    let higher_is_better = metrics.primary().higher_is_better();
+    let best_member = members
         .iter()
-        .find(|t| t.category == threshold_category)
-        .unwrap();
-
-    let mut response = format!("🎯 **{}**\n\n", threshold_config.message);
-    response.push_str(&format!("🆔 **ID Envío:** {}\n", submission_id));
-    response.push_str(&format!("📊 **Ganancia esperada:** {:.4}\n", expected_gain));
-
-    // Teachers see actual gain
-    if is_teacher {
-        response.push_str(&format!("✨ **Ganancia real:** {:.4}\n", gain_result.gain));
-        response.push_str(&format!(
-            "📈 **Positivos predichos:** {}\n",
-            positives_predicted
-        ));
+        .map(scope)
+        .reduce(|a, b| if (a >= b) == higher_is_better { a } else { b })
+        .unwrap_or(ensemble_score);
+    response.push_str(&format!(
+        "\n**Ensamble vs. mejor miembro ({}):** {:.2} vs. {:.2} ({:+.2})\n",
+        split_label(split),
+        ensemble_score,
+        best_member,
+        ensemble_score - best_member
+    ));
+    if track.references.is_some() {
         response.push_str(&format!(
-            "🔢 **Matriz confusión:** TP={}, TN={}, FP={}, FN={}\n",
-            gain_result.tp, gain_result.tn, gain_result.fp, gain_result.fn_
+            "**Ensamble como % del oráculo:** {}\n",
+            format_pct_of_oracle(track.references.as_ref(), Some(split), ensemble_score)
         ));
     }
+    response.push_str("\n*El ensamble no se guarda como envío.*\n");
 
-    // After deadline notification
-    if after_deadline {
-        response.push_str("\n⚠️ **ENVÍO FUERA DE PLAZO** - Registrado pero no compite\n");
-    }
+    response
+}
 
-    // Add random GIF
-    if !threshold_config.gifs.is_empty() {
-        let mut rng = rand::thread_rng();
-        if let Some(gif) = threshold_config.gifs.choose(&mut rng) {
-            response.push_str(&format!("\n{}", gif));
+/// Oráculo y baselines calculados del dataset maestro, por scope.
+pub fn process_references(track: &Track) -> String {
+    let config = &track.config;
+    let metrics = &track.metrics;
+    let references = match &track.references {
+        Some(r) => r,
+        None => {
+            return format!(
+                "📏 No hay puntajes de referencia para este track ({:?}, {})",
+                config.competition.task,
+                metrics.primary().name()
+            )
         }
+    };
+
+    let value = |score: Option<f64>| {
+        score
+            .map(|v| format!("{:.2}", v))
+            .unwrap_or_else(|| "N/A".to_string())
+    };
+    let random = |scope: &ReferenceScores| {
+        scope
+            .random
+            .map(|r| format!("{:.2} ({:.0}%)", r.score, r.rate * 100.0))
+            .unwrap_or_else(|| "N/A".to_string())
+    };
+
+    let mut response = format!(
+        "📏 **Puntajes de referencia - {} ({})**\n\n",
+        config.competition.name,
+        metrics.primary().name()
+    );
+    if !config.competition.tracks.is_empty() {
+        response.push_str(&format!("🛤️ **Track:** {}\n\n", track.name));
     }
+    response.push_str("| Referencia | ✨ Total | 🌐 Pública | 🔒 Privada |\n");
+    response.push_str("|---|---|---|---|\n");
+    let scopes = [&references.all, &references.public, &references.private];
+    for (label, scores) in [
+        ("🔮 Oráculo", scopes.map(|s| s.oracle)),
+        ("🚫 Predecir nada", scopes.map(|s| s.nothing)),
+        ("✅ Predecir todo", scopes.map(|s| s.everything)),
+    ] {
+        response.push_str(&format!(
+            "| {} | {} | {} | {} |\n",
+            label,
+            value(scores[0]),
+            value(scores[1]),
+            value(scores[2])
+        ));
+    }
+    response.push_str(&format!(
+        "| 🎲 Mejor azar (tasa de positivos) | {} | {} | {} |\n",
+        random(scopes[0]),
+        random(scopes[1]),
+        random(scopes[2])
+    ));
 
     response
 }
 
-/// Verifica si ya se pueden revelar los resultados completos
-fn results_revealed(config: &BotConfig) -> bool {
-    let reveal_date = &config.competition.results_reveal_date;
-    // Intentar parsear la fecha de revelación
-    let reveal_dt = match DateTime::parse_from_rfc3339(reveal_date) {
-        Ok(dt) => dt.with_timezone(&Utc),
-        Err(_) => {
-            // Intentar parsear sin timezone
-            match chrono::NaiveDateTime::parse_from_str(reveal_date, "%Y-%m-%dT%H:%M:%S") {
-                Ok(naive_dt) => DateTime::<Utc>::from_naive_utc_and_offset(naive_dt, Utc),
-                Err(_) => return false, // Si no se puede parsear, no revelar
-            }
-        }
+/// Writes every submission as CSV, with its score as a percentage of the
+/// oracle of its track in each scope. Returns the number of rows written.
+pub fn export_submissions(
+    db: &Database,
+    tracks: &[Track],
+    writer: impl std::io::Write,
+) -> Result<usize> {
+    let submissions = db.get_all_submissions()?;
+    let pct = |sub: &Submission, split: Option<Split>, score: f64| {
+        references_of(tracks, sub)
+            .and_then(|r| r.scope(split).pct_of_oracle(score))
+            .map(|pct| format!("{:.4}", pct))
+            .unwrap_or_default()
     };
-    Utc::now() >= reveal_dt
+
+    let mut csv = csv::Writer::from_writer(writer);
+    csv.write_record([
+        "id",
+        "user_email",
+        "user_full_name",
+        "submission_name",
+        "track",
+        "timestamp",
+        "expected_gain",
+        "actual_gain",
+        "public_gain",
+        "private_gain",
+        "pct_oracle",
+        "pct_oracle_public",
+        "pct_oracle_private",
+        "threshold_category",
+        "after_deadline",
+        "withdrawn_at",
+        "withdraw_reason",
+        "description",
+    ])?;
+    for sub in &submissions {
+        csv.write_record([
+            sub.id.unwrap_or(0).to_string(),
+            sub.user_email.clone(),
+            sub.user_full_name.clone(),
+            sub.submission_name.clone(),
+            sub.track.clone(),
+            sub.timestamp.clone(),
+            sub.expected_gain.to_string(),
+            sub.actual_gain.to_string(),
+            sub.public.gain.to_string(),
+            sub.private.gain.to_string(),
+            pct(sub, None, sub.actual_gain),
+            pct(sub, Some(Split::Public), sub.public.gain),
+            pct(sub, Some(Split::Private), sub.private.gain),
+            sub.threshold_category.clone(),
+            sub.after_deadline.to_string(),
+            sub.withdrawal.as_ref().map(|w| w.at.clone()).unwrap_or_default(),
+            sub.withdrawal.as_ref().map(|w| w.reason.clone()).unwrap_or_default(),
+            sub.description.clone().unwrap_or_default(),
+        ])?;
+    }
+    csv.flush()?;
+
+    Ok(submissions.len())
 }
 
-pub fn process_list_submits(user_name: &str, db: &Database, config: &BotConfig) -> String {
-    let submissions = match db.get_user_submissions(user_name) {
-        Ok(s) => s,
+/// Expected vs actual gain per student, or per submission for one student.
+pub fn process_calibration(user_identifier: Option<&str>, db: &Database, config: &BotConfig) -> String {
+    let submissions = match user_identifier {
+        Some(user) => db.get_user_submissions_by_identifier(user),
+        None => db.get_all_submissions(),
+    };
+    // Withdrawn uploads are usually the wrong file, not an estimate
+    let submissions: Vec<Submission> = match submissions {
+        Ok(s) => s.into_iter().filter(|sub| sub.withdrawal.is_none()).collect(),
         Err(e) => return format!("❌ Error obteniendo envíos: {}", e),
     };
 
-    if submissions.is_empty() {
-        return "📋 No tienes envíos registrados".to_string();
+    let calibrations = calibration::by_student(&submissions, &config.teachers);
+    if calibrations.is_empty() {
+        return "📋 No hay envíos de estudiantes para calibrar".to_string();
     }
 
-    let show_results = results_revealed(config);
-
-    let mut response = "📋 **Tus Envíos:**\n\n".to_string();
-    
-    if show_results {
-        // Mostrar información completa después de la fecha de revelación
-        response.push_str("| ID | Nombre | 📅 Fecha | 💰 Esperada | ✨ Real | 🎯 Categoría | ⏰ |\n");
-        response.push_str("|---|---|---|---|---|---|---|\n");
-
-        for sub in submissions {
-            let deadline_mark = if sub.after_deadline { "⚠️" } else { "✅" };
-            let ts_str: String = sub.timestamp.chars().take(16).collect();
+    let mut response = "🎯 **Calibración: ganancia esperada vs. real**\n\n".to_string();
+    response.push_str("| Nombre | 📊 Envíos | 📏 MAE | ⚖️ Sesgo | 🔗 Correlación |\n");
+    response.push_str("|---|---|---|---|---|\n");
+    for c in &calibrations {
+        response.push_str(&format!(
+            "| {} | {} | {:.2} | {:+.2} | {} |\n",
+            c.user_full_name,
+            c.submissions,
+            c.mae,
+            c.bias,
+            c.correlation
+                .map(|r| format!("{:.2}", r))
+                .unwrap_or_else(|| "N/A".to_string())
+        ));
+    }
+    response.push_str("\nSesgo positivo: el estudiante sobreestima la ganancia de sus modelos.\n");
+
+    // With a single student, list every submission behind the numbers
+    if user_identifier.is_some() {
+        response.push_str("\n| ID | Nombre | 💰 Esperada | ✨ Real | Δ |\n");
+        response.push_str("|---|---|---|---|---|\n");
+        for sub in submissions
+            .iter()
+            .filter(|sub| !config.teachers.contains(&sub.user_email))
+        {
             response.push_str(&format!(
-                "|{}|{}|{}|{:.2}|{:.2}|{}|{}|\n",
+                "| {} | {} | {:.2} | {:.2} | {:+.2} |\n",
                 sub.id.unwrap_or(0),
                 sub.submission_name,
-                ts_str,
                 sub.expected_gain,
                 sub.actual_gain,
-                sub.threshold_category,
-                deadline_mark
-            ));
-        }
-    } else {
-        // Mostrar información limitada antes de la fecha de revelación
-        response.push_str("| ID | Nombre | 📅 Fecha | 💰 Esperada | 🎯 Categoría | ⏰ |\n");
-        response.push_str("|---|---|---|---|---|---|\n");
-
-        for sub in submissions {
-            let deadline_mark = if sub.after_deadline { "⚠️" } else { "✅" };
-            let ts_str: String = sub.timestamp.chars().take(16).collect();
-            response.push_str(&format!(
-                "|{}|{}|{}|{:.2}|{}|{}|\n",
-                sub.id.unwrap_or(0),
-                sub.submission_name,
-                ts_str,
-                sub.expected_gain,
-                sub.threshold_category,
-                deadline_mark
+                sub.expected_gain - sub.actual_gain
             ));
         }
     }
 
-    // Informar cuándo se revelarán los resultados si aún no se han revelado
-    if !show_results {
-        let reveal_date = &config.competition.results_reveal_date; 
-        let reveal_str: String = reveal_date.chars().take(16).collect();
-        response.push_str(&format!(
-            "\n📊 *Los resultados completos se revelarán el {}*",
-            reveal_str
-        ));
-    }
-    response
-}
-
-pub fn process_duplicates(db: &Database) -> String {
-    let duplicates = match db.get_duplicates() {
-        Ok(d) => d,
-        Err(e) => return format!("❌ Error obteniendo duplicados: {}", e),
-    };
-
-    if duplicates.is_empty() {
-        return "✅ No se encontraron envíos duplicados".to_string();
-    }
-
-    let mut response = "🔍 **Envíos Duplicados:**\n\n".to_string();
-    for (checksum, _count, users, names) in duplicates {
-        response.push_str(&format!("**Checksum:** `{}...`\n", &checksum[..16]));
-        response.push_str(&format!("**Usuarios:** {}\n", users));
-        response.push_str(&format!("**Envíos:** {}\n\n", names));
-    }
-
     response
 }
 
-pub fn process_leaderboard_full(db: &Database, config: &BotConfig, order_by: &str) -> String {
-    let results = match db.get_leaderboard(order_by) {
-        Ok(r) => r,
-        Err(e) => return format!("❌ Error obteniendo leaderboard: {}", e),
+/// Ranks students by how well they predict the gain of their own models.
+pub fn process_estimator_leaderboard(db: &Database, config: &BotConfig) -> String {
+    let submissions: Vec<Submission> = match db.get_all_submissions() {
+        Ok(s) => s.into_iter().filter(|sub| sub.withdrawal.is_none()).collect(),
+        Err(e) => return format!("❌ Error obteniendo envíos: {}", e),
     };
 
-    if results.is_empty() {
-        return "📊 No hay submissions en el leaderboard".to_string();
+    let calibrations = calibration::by_student(&submissions, &config.teachers);
+    let ranking = calibration::estimator_ranking(&calibrations);
+    if ranking.is_empty() {
+        return format!(
+            "📊 Ningún estudiante tiene todavía {} envíos para entrar al ranking de estimadores",
+            calibration::MIN_RANKED_SUBMISSIONS
+        );
     }
 
-    let order_label = match order_by {
-        "datetime" => "Ordenado por Fecha",
-        _ => "Ordenado por Ganancia",
-    };
-
     let mut response = format!(
-        "🏆 **Leaderboard Completo - {} ({})** \n\n",
+        "🔮 **Mejores Estimadores - {}**\n\n\
+        Ordenado por error absoluto medio entre ganancia esperada y real \
+        (mínimo {} envíos)\n\n",
         config.competition.name,
-        order_label
+        calibration::MIN_RANKED_SUBMISSIONS
     );
-    response.push_str("| Pos | Nombre | TS | 💰 Elegido | 💰 Esperada | 📊 Envíos | 📈 Máximo |\n");
-    response.push_str("|---|---|---|---|---|---|---|\n");
-
-    for (i, (name, email, ts, best_gain, expected_gain, total, max_gain)) in results.iter().enumerate() {
-        if !config.teachers.contains(email) {
-            let max_str = max_gain
-                .map(|a| format!("{:.2}", a))
-                .unwrap_or_else(|| "N/A".to_string());
-            let ts_str: String = ts.chars().take(16).collect();
-            response.push_str(&format!(
-                "| {} | {} | {} | {:.2} | {:.2} | {} | {} |\n",
-                i + 1,
-                name,
-                ts_str,
-                best_gain,
-                expected_gain,
-                total,
-                max_str
-            ));
-        }
+    response.push_str("| Pos | Nombre | 📏 MAE | ⚖️ Sesgo | 🔗 Correlación | 📊 Envíos |\n");
+    response.push_str("|---|---|---|---|---|---|\n");
+    for (i, c) in ranking.iter().enumerate() {
+        response.push_str(&format!(
+            "| {} | {} | {:.2} | {:+.2} | {} | {} |\n",
+            i + 1,
+            c.user_full_name,
+            c.mae,
+            c.bias,
+            c.correlation
+                .map(|r| format!("{:.2}", r))
+                .unwrap_or_else(|| "N/A".to_string()),
+            c.submissions
+        ));
     }
 
     response
 }
 
-pub fn process_user_submits(user_identifier: &str, db: &Database) -> String {
+pub fn process_user_submits(user_identifier: &str, db: &Database, tracks: &[Track]) -> String {
     let submissions = match db.get_user_submissions_by_identifier(user_identifier) {
         Ok(s) => s,
         Err(e) => return format!("❌ Error obteniendo envíos: {}", e),
@@ -365,24 +2142,56 @@ pub fn process_user_submits(user_identifier: &str, db: &Database) -> String {
     }
 
     let mut response = format!("📋 **Envíos de '{}':**\n\n", user_identifier);
-    response.push_str("| ID | Nombre | 📅 Fecha | 💰 Esperada | ✨ Real | 🎯 | ⏰ |\n");
-    response.push_str("|---|---|---|---|---|---|---|\n");
+    response.push_str("| ID | Nombre | 📅 Fecha | 💰 Esperada | ✨ Real | 🎯 % Oráculo | 🌐 Pública | 🔒 Privada | 🎯 | ⏰ |\n");
+    response.push_str("|---|---|---|---|---|---|---|---|---|---|\n");
 
-    for sub in submissions {
-        let deadline_mark = if sub.after_deadline { "⚠️" } else { "✅" };
+    for sub in &submissions {
+        let deadline_mark = status_mark(sub);
         let ts_str: String = sub.timestamp.chars().take(16).collect();
         response.push_str(&format!(
-            "|{}|{}|{}|{:.2}|{:.2}|{}|{}|\n",
+            "|{}|{}|{}|{:.2}|{:.2}|{}|{:.2}|{:.2}|{}|{}|\n",
             sub.id.unwrap_or(0),
             sub.submission_name,
             ts_str,
             sub.expected_gain,
             sub.actual_gain,
+            format_pct_of_oracle(references_of(tracks, sub), None, sub.actual_gain),
+            sub.public.gain,
+            sub.private.gain,
             sub.threshold_category,
             deadline_mark
         ));
     }
 
+    let withdrawn: Vec<&Submission> = submissions.iter().filter(|sub| sub.withdrawal.is_some()).collect();
+    if !withdrawn.is_empty() {
+        response.push_str(&format!("\n🗑️ **Retirados:** {}\n", withdrawn.len()));
+        for sub in withdrawn {
+            if let Some(withdrawal) = &sub.withdrawal {
+                let ts_str: String = withdrawal.at.chars().take(16).collect();
+                response.push_str(&format!(
+                    "• {} ({}): {}\n",
+                    sub.id.unwrap_or(0),
+                    ts_str,
+                    withdrawal.reason
+                ));
+            }
+        }
+    }
+
+    // Over-quota attempts, recorded but never scored
+    let attempts = db.get_rejected_attempts(user_identifier).unwrap_or_default();
+    if !attempts.is_empty() {
+        response.push_str(&format!("\n🚫 **Intentos rechazados:** {}\n", attempts.len()));
+        for attempt in &attempts {
+            let ts_str: String = attempt.timestamp.chars().take(16).collect();
+            response.push_str(&format!(
+                "• {} `{}` ({})\n",
+                ts_str, attempt.submission_name, attempt.reason
+            ));
+        }
+    }
+
     response
 }
 
@@ -446,7 +2255,7 @@ pub async fn process_no_submits(db: &Database, client: &ZulipClient, config: &Bo
     for (i, (user, last_active_ts)) in user_presence_list.iter().enumerate() {
         let (last_conn_str, time_diff_str) = if let Some(ts) = last_active_ts {
             let dt = DateTime::from_timestamp(*ts, 0)
-                .unwrap_or_else(|| Utc::now());
+                .unwrap_or_else(Utc::now);
             let duration = now.signed_duration_since(dt);
             let days = duration.num_days();
             let hours = duration.num_hours() % 24;
@@ -475,7 +2284,18 @@ pub async fn process_no_submits(db: &Database, client: &ZulipClient, config: &Bo
 }
 
 
-pub fn process_all_submits(db: &Database) -> String {
+/// ⏰ de las vistas de profesores: 🗑️ retirado, ⚠️ fuera de plazo, ✅ compite.
+fn status_mark(sub: &Submission) -> &'static str {
+    if sub.withdrawal.is_some() {
+        "🗑️"
+    } else if sub.after_deadline {
+        "⚠️"
+    } else {
+        "✅"
+    }
+}
+
+pub fn process_all_submits(db: &Database, tracks: &[Track]) -> String {
     let submissions = match db.get_all_submissions() {
         Ok(s) => s,
         Err(e) => return format!("❌ Error obteniendo envíos: {}", e),
@@ -490,7 +2310,7 @@ pub fn process_all_submits(db: &Database) -> String {
     for sub in &submissions {
         submissions_by_user
             .entry(sub.user_id)
-            .or_insert_with(Vec::new)
+            .or_default()
             .push(sub);
     }
 
@@ -509,7 +2329,7 @@ pub fn process_all_submits(db: &Database) -> String {
 
     // Paginate into chunks of 50 rows
     const ROWS_PER_PAGE: usize = 50;
-    let total_pages = (grouped_submissions.len() + ROWS_PER_PAGE - 1) / ROWS_PER_PAGE;
+    let total_pages = grouped_submissions.len().div_ceil(ROWS_PER_PAGE);
 
     let mut messages = Vec::new();
 
@@ -519,19 +2339,19 @@ pub fn process_all_submits(db: &Database) -> String {
             page_num + 1,
             total_pages
         );
-        response.push_str("| ID | Usuario | Nombre | 📅 Fecha | 💰 Esperada | ✨ Real | 🎯 | ⏰ |\n");
-        response.push_str("|---|---|---|---|---|---|---|---|\n");
+        response.push_str("| ID | Usuario | Nombre | 📅 Fecha | 💰 Esperada | ✨ Real | 🎯 % Oráculo | 🎯 | ⏰ |\n");
+        response.push_str("|---|---|---|---|---|---|---|---|---|\n");
 
         let mut current_user_id = None;
         
         for sub in chunk {
             // Add a visual separator when switching to a new user
             if current_user_id.is_some() && current_user_id != Some(sub.user_id) {
-                response.push_str("|---|---|---|---|---|---|---|---|\n");
+                response.push_str("|---|---|---|---|---|---|---|---|---|\n");
             }
             current_user_id = Some(sub.user_id);
 
-            let deadline_mark = if sub.after_deadline { "⚠️" } else { "✅" };
+            let deadline_mark = status_mark(sub);
             let ts_str: String = sub.timestamp.chars().take(16).collect();
             let user_display = if sub.user_full_name.is_empty() {
                 &sub.user_email
@@ -540,13 +2360,14 @@ pub fn process_all_submits(db: &Database) -> String {
             };
 
             response.push_str(&format!(
-                "|{}|{}|{}|{}|{:.2}|{:.2}|{}|{}|\n",
+                "|{}|{}|{}|{}|{:.2}|{:.2}|{}|{}|{}|\n",
                 sub.id.unwrap_or(0),
                 user_display,
                 sub.submission_name,
                 ts_str,
                 sub.expected_gain,
                 sub.actual_gain,
+                format_pct_of_oracle(references_of(tracks, sub), None, sub.actual_gain),
                 sub.threshold_category,
                 deadline_mark
             ));
@@ -571,143 +2392,310 @@ pub fn process_all_submits(db: &Database) -> String {
 
 // Helper functions
 
-async fn extract_file_from_message(
-    content: &str,
-    config: &BotConfig,
-) -> Result<Option<(String, Vec<u8>)>> {
-    let re = Regex::new(r"\[([^\]]+\.csv)\]\(([^)]+)\)")?;
+/// A Zulip upload linked in a message, e.g. `[envio.csv](/user_uploads/...)`.
+struct AttachmentLink {
+    filename: String,
+    url: String,
+    /// Line of the message the link is on, starting at 0
+    line: usize,
+}
 
-    if let Some(caps) = re.captures(content) {
-        let filename = caps.get(1).unwrap().as_str().to_string();
-        let url = caps.get(2).unwrap().as_str();
+/// Every CSV (or compressed CSV) linked in `content`, in order.
+fn attachment_links(content: &str) -> Vec<AttachmentLink> {
+    let re = Regex::new(r"(?i)\[([^\]]+\.(?:csv|csv\.gz|zip))\]\(([^)]+)\)").expect("valid regex");
+    re.captures_iter(content)
+        .map(|caps| AttachmentLink {
+            filename: caps[1].to_string(),
+            url: caps[2].to_string(),
+            line: content[..caps.get(0).map_or(0, |m| m.start())].matches('\n').count(),
+        })
+        .collect()
+}
 
-        let full_url = if url.starts_with("http") {
-            url.to_string()
-        } else {
-            format!("{}{}", config.zulip.site, url)
-        };
+async fn download_attachment(link: &AttachmentLink, config: &BotConfig) -> Result<Vec<u8>> {
+    let full_url = if link.url.starts_with("http") {
+        link.url.clone()
+    } else {
+        format!("{}{}", config.zulip.site, link.url)
+    };
 
-        let client = reqwest::Client::new();
-        let response = client
-            .get(&full_url)
-            .basic_auth(&config.zulip.email, Some(&config.zulip.api_key))
-            .send()
-            .await?;
+    let client = reqwest::Client::new();
+    let request = client
+        .get(&full_url)
+        .basic_auth(&config.zulip.email, Some(&config.zulip.api_key));
+
+    attachment::download(
+        request,
+        config.submissions.max_download_bytes(),
+        Duration::from_secs(config.submissions.download_timeout_secs),
+    )
+    .await
+}
 
-        let content = response.bytes().await?.to_vec();
-        Ok(Some((filename, content)))
+fn format_threshold(threshold: f64) -> String {
+    if threshold.is_infinite() {
+        "∞".to_string()
     } else {
-        Ok(None)
+        format!("{:.4}", threshold)
     }
 }
 
-fn save_submission_file(
-    user_name: &str,
-    submission_name: &str,
-    filename: &str,
-    content: &[u8],
-    is_teacher: bool,
-    config: &BotConfig,
-) -> Result<String> {
-    let base_path = PathBuf::from(&config.submissions.path);
+/// Parses a stored or uploaded submission file according to the competition
+/// task. `max_rows` caps uploads only, so lowering it later keeps the stored
+/// files readable.
+fn parse_predictions(content: &[u8], config: &BotConfig, max_rows: Option<usize>) -> Result<PredictionFile> {
+    let mut rows = csv_dialect::read_rows(content)?;
+    if let Some(max_rows) = max_rows {
+        rows = rows.limit(max_rows);
+    }
+    let id_type = config.master_data.id_type;
+    match (&config.competition.task, &config.class_gain_matrix) {
+        (TaskType::Multiclass, Some(matrix)) => class_predictions(rows, matrix, id_type),
+        (TaskType::Regression, _) => value_predictions(rows, id_type),
+        _ => predictions(rows, id_type),
+    }
+}
 
-    let safe_user_name: String = user_name
-        .chars()
-        .filter(|c| c.is_alphanumeric() || *c == ' ' || *c == '-' || *c == '_')
-        .collect();
+/// Header names accepted for the column after the ID, the first one is the
+/// documented name.
+const PROBABILITY_HEADERS: &[&str] = &["probability", "prob", "probabilidad", "score"];
+const CLASS_HEADERS: &[&str] = &["class", "clase", "prediction"];
+const VALUE_HEADERS: &[&str] = &["value", "valor", "prediction"];
 
-    let user_dir = if is_teacher {
-        base_path.join("teachers").join(safe_user_name)
-    } else {
-        base_path.join("students").join(safe_user_name)
-    };
+/// Whether the ID column of `row` holds an ID rather than a column name.
+fn has_id(row: &Row, id_type: IdType) -> bool {
+    !csv_dialect::is_id_header(&row.fields[0]) && Id::parse(&row.fields[0], id_type).is_some()
+}
+
+/// IDs seen so far in a file; a repeated ID is recorded once and its later
+/// rows are ignored.
+#[derive(Default)]
+struct SeenIds {
+    seen: HashSet<Id>,
+    duplicates: Vec<Id>,
+    reported: HashSet<Id>,
+}
+
+impl SeenIds {
+    /// Whether `id` is new.
+    fn first(&mut self, id: &Id) -> bool {
+        if self.seen.insert(id.clone()) {
+            return true;
+        }
+        if self.reported.insert(id.clone()) {
+            self.duplicates.push(id.clone());
+        }
+        false
+    }
+}
 
-    fs::create_dir_all(&user_dir)?;
+/// Reads either a bare list of positive IDs or `id,probability` rows (with an
+/// optional header), depending on the number of columns.
+pub fn read_predictions(content: &[u8], id_type: IdType) -> Result<PredictionFile> {
+    predictions(csv_dialect::read_rows(content)?, id_type)
+}
 
-    let timestamp = Utc::now().format("%Y%m%d_%H%M%S");
-    let safe_name: String = submission_name
-        .chars()
-        .filter(|c| c.is_alphanumeric() || *c == ' ' || *c == '-' || *c == '_')
-        .collect();
+fn predictions(mut rows: Rows, id_type: IdType) -> Result<PredictionFile> {
+    let first_len = match rows.peek()? {
+        Some(row) => row.len(),
+        None => {
+            return Ok(PredictionFile {
+                predictions: Predictions::Ids(HashSet::new()),
+                duplicates: Vec::new(),
+            })
+        }
+    };
+    if first_len == 1 {
+        return read_csv_ids(rows, id_type);
+    }
+    if first_len != 2 {
+        let first = rows.peek()?.expect("first row was peeked");
+        return Err(csv_dialect::wrong_columns(first, "`id` o `id,probability`").into());
+    }
 
-    let file_path = user_dir.join(format!("{}_{}_{}", timestamp, safe_name.trim(), filename));
+    let mut seen = SeenIds::default();
+    let mut scores = Vec::new();
 
-    fs::write(&file_path, content)?;
+    // Header row, e.g. `id,probability`
+    rows.skip_header(&[PROBABILITY_HEADERS], |row| has_id(row, id_type))?;
+    for row in rows {
+        let row = row?;
+        if row.len() != 2 {
+            return Err(csv_dialect::wrong_columns(&row, "`id,probability`").into());
+        }
+        let id = csv_dialect::parse_id(&row, 0, id_type)?;
+        let score = csv_dialect::parse_number(&row, 1, "probabilidad")?;
+        if seen.first(&id) {
+            scores.push((id, score));
+        }
+    }
 
-    Ok(file_path.to_string_lossy().to_string())
+    Ok(PredictionFile {
+        predictions: Predictions::Scores(scores),
+        duplicates: seen.duplicates,
+    })
 }
 
-fn calculate_checksum(content: &[u8]) -> String {
-    let mut hasher = Sha256::new();
-    hasher.update(content);
-    hex::encode(hasher.finalize())
+/// Reads `id,class` rows (with an optional header) for multiclass competitions.
+pub fn read_class_predictions(
+    content: &[u8],
+    matrix: &ClassGainMatrix,
+    id_type: IdType,
+) -> Result<PredictionFile> {
+    class_predictions(csv_dialect::read_rows(content)?, matrix, id_type)
 }
 
-fn read_csv_ids(content: &[u8]) -> Result<HashSet<i32>> {
-    let mut reader = ReaderBuilder::new().has_headers(false).from_reader(content);
+fn class_predictions(mut rows: Rows, matrix: &ClassGainMatrix, id_type: IdType) -> Result<PredictionFile> {
+    let mut seen = SeenIds::default();
+    let mut predicted = HashMap::new();
 
-    let mut ids = HashSet::new();
-
-    for result in reader.records() {
-        let record = result?;
-        if record.len() != 1 {
-            anyhow::bail!("CSV must have exactly 1 column");
+    // Header row, e.g. `id,class`
+    rows.skip_header(&[CLASS_HEADERS], |row| has_id(row, id_type))?;
+    for row in rows {
+        let row = row?;
+        if row.len() != 2 {
+            return Err(csv_dialect::wrong_columns(&row, "`id,class`").into());
         }
 
-        let id: i32 = record[0]
-            .parse()
-            .with_context(|| format!("Invalid ID: {}", &record[0]))?;
-        ids.insert(id);
+        let id = csv_dialect::parse_id(&row, 0, id_type)?;
+        let class = matrix.class_index(&row.fields[1]).ok_or_else(|| {
+            CsvError::new(
+                row.line,
+                row.fields[1].as_str(),
+                format!("clase desconocida para el ID {}", id),
+                format!("Usa una de estas clases: {}", matrix.classes.join(", ")),
+            )
+        })?;
+        if seen.first(&id) {
+            predicted.insert(id, class);
+        }
     }
 
-    Ok(ids)
+    Ok(PredictionFile {
+        predictions: Predictions::Classes {
+            predicted,
+            default_class: matrix.default_class_index(),
+        },
+        duplicates: seen.duplicates,
+    })
 }
 
-fn calculate_gain(
-    predicted_ids: &HashSet<i32>,
-    master_data: &MasterData,
-    gain_matrix: &crate::config::GainMatrix,
-) -> GainResult {
-    let mut tp = 0;
-    let mut tn = 0;
-    let mut fp = 0;
-    let mut fn_ = 0;
+/// Reads `id,value` rows (with an optional header) for regression competitions.
+pub fn read_value_predictions(content: &[u8], id_type: IdType) -> Result<PredictionFile> {
+    value_predictions(csv_dialect::read_rows(content)?, id_type)
+}
 
-    for id in master_data.all_ids() {
-        let is_positive = master_data.positive_ids().contains(id);
-        let predicted_positive = predicted_ids.contains(id);
+fn value_predictions(mut rows: Rows, id_type: IdType) -> Result<PredictionFile> {
+    let mut seen = SeenIds::default();
+    let mut values = HashMap::new();
 
-        match (is_positive, predicted_positive) {
-            (true, true) => tp += 1,
-            (true, false) => fn_ += 1,
-            (false, true) => fp += 1,
-            (false, false) => tn += 1,
+    // Header row, e.g. `id,value`
+    rows.skip_header(&[VALUE_HEADERS], |row| has_id(row, id_type))?;
+    for row in rows {
+        let row = row?;
+        if row.len() != 2 {
+            return Err(csv_dialect::wrong_columns(&row, "`id,value`").into());
+        }
+
+        let id = csv_dialect::parse_id(&row, 0, id_type)?;
+        let value = csv_dialect::parse_number(&row, 1, "predicción")?;
+        if seen.first(&id) {
+            values.insert(id, value);
         }
     }
 
-    let gain = (tp as f64) * gain_matrix.tp
-        + (tn as f64) * gain_matrix.tn
-        + (fp as f64) * gain_matrix.fp
-        + (fn_ as f64) * gain_matrix.fn_;
+    Ok(PredictionFile {
+        predictions: Predictions::Values(values),
+        duplicates: seen.duplicates,
+    })
+}
+
+fn read_csv_ids(mut rows: Rows, id_type: IdType) -> Result<PredictionFile> {
+    let mut seen = SeenIds::default();
 
-    GainResult {
-        gain,
-        tp,
-        tn,
-        fp,
-        fn_,
+    // Header row, e.g. `id`
+    rows.skip_header(&[], |row| has_id(row, id_type))?;
+    for row in rows {
+        let row = row?;
+        if row.len() != 1 {
+            return Err(csv_dialect::wrong_columns(&row, "`id`").into());
+        }
+        seen.first(&csv_dialect::parse_id(&row, 0, id_type)?);
     }
+
+    Ok(PredictionFile {
+        predictions: Predictions::Ids(seen.seen),
+        duplicates: seen.duplicates,
+    })
+}
+
+/// Public scores of the submissions that compete, i.e. sent before the
+/// deadline and not withdrawn.
+fn competing_public_scores<'a>(submissions: impl Iterator<Item = &'a Submission>) -> Vec<f64> {
+    submissions
+        .filter(|sub| !sub.after_deadline && sub.withdrawal.is_none())
+        .map(|sub| sub.public.gain)
+        .collect()
 }
 
-fn get_threshold_category(gain: f64, config: &BotConfig) -> String {
-    let mut thresholds = config.gain_thresholds.clone();
-    thresholds.sort_by(|a, b| b.min_gain.partial_cmp(&a.min_gain).unwrap());
+/// What a public score is compared against for relative threshold categories.
+pub(crate) struct Standing<'a> {
+    pub references: Option<&'a References>,
+    /// Public scores of the other submissions that compete
+    pub peers: &'a [f64],
+}
 
-    for threshold in thresholds.iter() {
-        if gain >= threshold.min_gain {
-            return threshold.category.clone();
+impl Standing<'_> {
+    fn reached(&self, kind: ThresholdKind, score: f64, higher_is_better: bool) -> bool {
+        let better = |a: f64, b: f64| if higher_is_better { a > b } else { a < b };
+        match kind {
+            // For lower-is-better metrics `min_gain` acts as a maximum
+            ThresholdKind::Gain(min) => score == min || better(score, min),
+            ThresholdKind::PctOfOracle(min) => self
+                .references
+                .and_then(|r| r.public.pct_of_oracle(score))
+                .is_some_and(|pct| pct >= min),
+            // Without peers there is no standing to reach, so the first
+            // submission gets the least demanding category
+            ThresholdKind::Percentile(_) | ThresholdKind::Rank(_) if self.peers.is_empty() => false,
+            ThresholdKind::Percentile(min) => {
+                let beaten = self.peers.iter().filter(|peer| better(score, **peer)).count();
+                beaten as f64 / self.peers.len() as f64 * 100.0 >= min
+            }
+            ThresholdKind::Rank(max) => {
+                let ahead = self.peers.iter().filter(|peer| better(**peer, score)).count();
+                ahead < max
+            }
         }
     }
+}
 
-    thresholds.last().unwrap().category.clone()
+/// Category of the most demanding threshold `score` reaches, or of the least
+/// demanding one when it reaches none. Thresholds are validated at config load.
+pub(crate) fn get_threshold_category(
+    score: f64,
+    config: &BotConfig,
+    higher_is_better: bool,
+    standing: &Standing,
+) -> String {
+    let mut thresholds: Vec<(ThresholdKind, &GainThreshold)> = config
+        .gain_thresholds
+        .iter()
+        .filter_map(|t| Some((t.kind()?, t)))
+        .collect();
+    // Most demanding first
+    let demand = |kind: &ThresholdKind| match *kind {
+        ThresholdKind::Gain(min) if higher_is_better => min,
+        ThresholdKind::Gain(max) => -max,
+        ThresholdKind::PctOfOracle(min) | ThresholdKind::Percentile(min) => min,
+        ThresholdKind::Rank(max) => -(max as f64),
+    };
+    thresholds.sort_by(|a, b| demand(&b.0).total_cmp(&demand(&a.0)));
+
+    thresholds
+        .iter()
+        .find(|(kind, _)| standing.reached(*kind, score, higher_is_better))
+        .or(thresholds.last())
+        .map(|(_, t)| t.category.clone())
+        .unwrap_or_default()
 }
@@ -146,4 +146,1951 @@ mod tests {
 
         assert!(test_time < deadline);
     }
+
+    /// Merges `overrides` into `base`: objects key by key, anything else replaced.
+    fn merge(base: &mut serde_json::Value, overrides: serde_json::Value) {
+        match (base, overrides) {
+            (serde_json::Value::Object(base), serde_json::Value::Object(overrides)) => {
+                for (key, value) in overrides {
+                    merge(base.entry(key).or_insert(serde_json::Value::Null), value);
+                }
+            }
+            (base, overrides) => *base = overrides,
+        }
+    }
+
+    /// Config of a competition over `master_data` (CSV content), written to
+    /// `dir` next to the database, with `overrides` merged over the defaults.
+    fn test_config(
+        dir: &std::path::Path,
+        master_data: &str,
+        overrides: serde_json::Value,
+    ) -> crate::config::BotConfig {
+        std::fs::write(dir.join("master_data.csv"), master_data).unwrap();
+        let mut config = serde_json::json!({
+            "zulip": { "email": "bot@example.com", "api_key": "key", "site": "https://example.com" },
+            "database": { "path": dir.join("test.db") },
+            "logs": { "path": "logs" },
+            "teachers": [],
+            "master_data": { "path": dir.join("master_data.csv") },
+            "submissions": { "path": dir },
+            "gain_matrix": { "tp": 1.0, "tn": 0.0, "fp": 0.0, "fn_": 0.0 },
+            "gain_thresholds": [{ "min_gain": 0.0, "category": "basic", "message": "ok" }],
+            "competition": {
+                "name": "test",
+                "description": "test",
+                "deadline": "2099-01-01T00:00:00",
+                "results_reveal_date": "2099-01-01T00:00:00"
+            },
+            "bootstrap": { "iterations": 0 }
+        });
+        merge(&mut config, overrides);
+        serde_json::from_value(config).unwrap()
+    }
+
+    /// The main track of `config` and its initialized database.
+    fn test_competition(
+        config: &crate::config::BotConfig,
+    ) -> (Vec<crate::track::Track>, crate::database::Database) {
+        let tracks = vec![crate::track::Track::load(config, "main").unwrap()];
+        let db = crate::database::Database::new(&config.database.path).unwrap();
+        db.init().unwrap();
+        (tracks, db)
+    }
+
+    /// Local Zulip site answering every request with `body`, as an attachment download.
+    async fn serve_attachments(body: &'static [u8]) -> String {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let site = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let mut request = [0u8; 4096];
+                let _ = socket.read(&mut request).await;
+                let head = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    body.len()
+                );
+                let _ = socket.write_all(head.as_bytes()).await;
+                let _ = socket.write_all(body).await;
+            }
+        });
+        site
+    }
+
+    #[test]
+    fn test_public_private_split_gain() {
+        use crate::config::{GainMatrix, IdType, MetricConfig};
+        use crate::master_data::{MasterData, Split};
+        use crate::metrics::{self, MetricSet};
+        use crate::models::{Id, Predictions};
+
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("master_data.csv");
+        std::fs::write(
+            &path,
+            "id,clase_binaria,split\n1,1,public\n2,0,public\n3,1,private\n4,0,private\n",
+        )
+        .unwrap();
+        let master_data = MasterData::load(path.to_str().unwrap(), IdType::Integer).unwrap();
+        assert!(master_data.has_split());
+        assert_eq!(master_data.split_count(Split::Public), 2);
+
+        let gain_matrix = GainMatrix {
+            tp: 10.0,
+            tn: 0.0,
+            fp: -1.0,
+            fn_: -5.0,
+        };
+        let metric_set = MetricSet::new(
+            metrics::build(&MetricConfig::Gain, &gain_matrix, None),
+            vec![],
+        );
+        let predicted = Predictions::Ids([1, 4].into_iter().map(Id::from).collect());
+
+        let (public, _) = metric_set.evaluate(&predicted, 0.0, &master_data, Some(Split::Public));
+        assert_eq!((public.tp, public.tn, public.fp, public.fn_), (1, 1, 0, 0));
+        assert_eq!(public.gain, 10.0);
+
+        let (private, _) = metric_set.evaluate(&predicted, 0.0, &master_data, Some(Split::Private));
+        assert_eq!(
+            (private.tp, private.tn, private.fp, private.fn_),
+            (0, 0, 1, 1)
+        );
+        assert_eq!(private.gain, -6.0);
+
+        let (full, _) = metric_set.evaluate(&predicted, 0.0, &master_data, None);
+        assert_eq!(full.gain, public.gain + private.gain);
+
+        // Only a column named `split` splits the rows
+        std::fs::write(&path, "id,clase_binaria,fuente\n1,1,web\n2,0,app\n").unwrap();
+        let master_data = MasterData::load(path.to_str().unwrap(), IdType::Integer).unwrap();
+        assert!(!master_data.has_split());
+        assert_eq!(master_data.total_count(), 2);
+    }
+
+    #[test]
+    fn test_probability_submission_gain_curve() {
+        use crate::config::{GainMatrix, IdType};
+        use crate::gain_curve::GainCurve;
+        use crate::master_data::MasterData;
+        use crate::models::{Id, Predictions};
+        use crate::submission::read_predictions;
+
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("master_data.csv");
+        std::fs::write(&path, "id,clase_binaria\n1,1\n2,0\n3,1\n4,0\n").unwrap();
+        let master_data = MasterData::load(path.to_str().unwrap(), IdType::Integer).unwrap();
+
+        let predictions = read_predictions(
+            b"id,probability\n1,0.9\n2,0.7\n3,0.6\n4,0.1\n",
+            IdType::Integer,
+        )
+        .unwrap()
+        .predictions;
+        let scores = match &predictions {
+            Predictions::Scores(scores) => scores.clone(),
+            _ => panic!("expected probability format"),
+        };
+        let expected: HashSet<Id> = [1, 2].into_iter().map(Id::from).collect();
+        assert_eq!(predictions.positives(0.65), expected);
+
+        let gain_matrix = GainMatrix {
+            tp: 10.0,
+            tn: 0.0,
+            fp: -1.0,
+            fn_: 0.0,
+        };
+        let curve = GainCurve::compute(&scores, &master_data, &gain_matrix);
+        let gains: Vec<f64> = curve.points.iter().map(|p| p.gain).collect();
+        assert_eq!(gains, vec![0.0, 10.0, 9.0, 19.0, 18.0]);
+
+        let best = curve.best();
+        assert_eq!(best.positives, 3);
+        assert_eq!(best.threshold, 0.6);
+
+        // A bare list of IDs keeps working
+        assert!(matches!(
+            read_predictions(b"1\n3\n", IdType::Integer)
+                .unwrap()
+                .predictions,
+            Predictions::Ids(_)
+        ));
+    }
+
+    #[test]
+    fn test_metrics() {
+        use crate::config::{GainMatrix, MetricConfig};
+        use crate::metrics::{self, Evaluation};
+
+        let gain_matrix = GainMatrix {
+            tp: 1.0,
+            tn: 0.0,
+            fp: 0.0,
+            fn_: 0.0,
+        };
+        let evaluation = Evaluation {
+            tp: 2,
+            tn: 1,
+            fp: 1,
+            fn_: 1,
+            scored: Some(vec![
+                (0.9, true),
+                (0.8, false),
+                (0.7, true),
+                (0.3, true),
+                (0.1, false),
+            ]),
+            confusion: None,
+            regression: None,
+            amounts: None,
+        };
+        let value = |config: MetricConfig| {
+            metrics::build(&config, &gain_matrix, None)
+                .evaluate(&evaluation)
+                .unwrap()
+        };
+
+        assert_eq!(value(MetricConfig::Gain), 2.0);
+        assert_eq!(value(MetricConfig::Accuracy), 0.6);
+        assert!((value(MetricConfig::F1) - 2.0 / 3.0).abs() < 1e-12);
+        assert_eq!(value(MetricConfig::PrecisionAtK { k: 2 }), 0.5);
+        // 4 of the 6 positive/negative pairs are ordered correctly
+        assert!((value(MetricConfig::RocAuc) - 4.0 / 6.0).abs() < 1e-12);
+        assert!(value(MetricConfig::LogLoss) > 0.0);
+        assert!(!metrics::build(&MetricConfig::LogLoss, &gain_matrix, None).higher_is_better());
+    }
+
+    #[test]
+    fn test_multiclass_confusion_and_gain() {
+        use crate::config::{ClassGainMatrix, GainMatrix, IdType, MetricConfig};
+        use crate::master_data::MasterData;
+        use crate::metrics::{self, MetricSet};
+        use crate::submission::read_class_predictions;
+
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("master_data.csv");
+        std::fs::write(&path, "id,clase\n1,a\n2,b\n3,c\n4,c\n").unwrap();
+        let classes: Vec<String> = vec!["a".into(), "b".into(), "c".into()];
+        let master_data =
+            MasterData::load_multiclass(path.to_str().unwrap(), &classes, IdType::Integer).unwrap();
+
+        let matrix = ClassGainMatrix {
+            classes,
+            gains: vec![
+                vec![1.0, 0.0, -1.0],
+                vec![0.0, 2.0, -1.0],
+                vec![-5.0, -5.0, 3.0],
+            ],
+            default_class: Some("c".into()),
+        };
+        // ID 4 is missing and falls back to the default class
+        let predictions =
+            read_class_predictions(b"id,class\n1,a\n2,c\n3,a\n", &matrix, IdType::Integer)
+                .unwrap()
+                .predictions;
+        assert!(read_class_predictions(b"1,z\n", &matrix, IdType::Integer).is_err());
+
+        let gain_matrix = GainMatrix {
+            tp: 0.0,
+            tn: 0.0,
+            fp: 0.0,
+            fn_: 0.0,
+        };
+        let metric_set = MetricSet::new(
+            metrics::build(&MetricConfig::Gain, &gain_matrix, Some(&matrix)),
+            vec![metrics::build(
+                &MetricConfig::Accuracy,
+                &gain_matrix,
+                Some(&matrix),
+            )],
+        );
+        let (result, values) = metric_set.evaluate(&predictions, 0.0, &master_data, None);
+
+        assert_eq!(
+            result.confusion,
+            Some(vec![vec![1, 0, 0], vec![0, 0, 1], vec![1, 0, 1]])
+        );
+        assert_eq!(result.gain, 1.0 - 1.0 - 5.0 + 3.0);
+        assert_eq!(values[1].value, Some(0.5));
+    }
+
+    #[test]
+    fn test_regression_metrics() {
+        use crate::config::{GainMatrix, IdType, MetricConfig};
+        use crate::master_data::MasterData;
+        use crate::metrics::{self, MetricSet};
+        use crate::submission::read_value_predictions;
+
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("master_data.csv");
+        std::fs::write(&path, "id,target\n1,10\n2,20\n").unwrap();
+        let master_data =
+            MasterData::load_regression(path.to_str().unwrap(), IdType::Integer).unwrap();
+
+        let predictions = read_value_predictions(b"id,value\n1,12\n2,17\n", IdType::Integer)
+            .unwrap()
+            .predictions;
+        // Repeated IDs keep their first row and are reported for validation
+        let repeated = read_value_predictions(b"1,5\n1,6\n1,7\n", IdType::Integer).unwrap();
+        assert_eq!(repeated.duplicates, vec![crate::models::Id::from(1)]);
+
+        let gain_matrix = GainMatrix {
+            tp: 0.0,
+            tn: 0.0,
+            fp: 0.0,
+            fn_: 0.0,
+        };
+        let build = |config: MetricConfig| metrics::build(&config, &gain_matrix, None);
+        let metric_set = MetricSet::new(
+            build(MetricConfig::Mae),
+            vec![
+                build(MetricConfig::Rmse),
+                build(MetricConfig::Mape),
+                build(MetricConfig::MonetaryLoss {
+                    over_cost: 1.0,
+                    under_cost: 10.0,
+                }),
+            ],
+        );
+        let (result, values) = metric_set.evaluate(&predictions, 0.0, &master_data, None);
+
+        assert_eq!(result.gain, 2.5);
+        assert!((values[1].value.unwrap() - 6.5f64.sqrt()).abs() < 1e-12);
+        assert!((values[2].value.unwrap() - 17.5).abs() < 1e-12);
+        assert_eq!(values[3].value, Some(2.0 + 30.0));
+        assert!(!metric_set.primary().higher_is_better());
+    }
+
+    #[test]
+    fn test_row_gains_override_matrix() {
+        use crate::config::{GainMatrix, IdType, MetricConfig};
+        use crate::gain_curve::GainCurve;
+        use crate::master_data::{MasterData, Split};
+        use crate::metrics::{self, MetricSet};
+        use crate::models::{Id, Predictions};
+
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("master_data.csv");
+        std::fs::write(
+            &path,
+            "id,target,split,gain_if_tp,cost_if_fp,weight\n\
+             1,1,public,100,,\n\
+             2,1,private,,,2\n\
+             3,0,public,,5,\n\
+             4,0,private,,,\n",
+        )
+        .unwrap();
+        let master_data = MasterData::load(path.to_str().unwrap(), IdType::Integer).unwrap();
+        assert!(master_data.has_row_gains());
+        assert!(master_data.has_split());
+
+        let gain_matrix = GainMatrix {
+            tp: 10.0,
+            tn: 0.0,
+            fp: -1.0,
+            fn_: -3.0,
+        };
+        let metric_set = MetricSet::new(
+            metrics::build(&MetricConfig::Gain, &gain_matrix, None),
+            vec![],
+        );
+        let predictions = Predictions::Ids([1, 3].into_iter().map(Id::from).collect());
+
+        // 100 (own TP amount) - 5 (own FP cost) - 3 * 2 (weighted FN) + 0
+        let (all, _) = metric_set.evaluate(&predictions, 0.5, &master_data, None);
+        assert_eq!(all.gain, 89.0);
+        let (public, _) = metric_set.evaluate(&predictions, 0.5, &master_data, Some(Split::Public));
+        assert_eq!(public.gain, 95.0);
+
+        let curve = GainCurve::compute(
+            &[(Id::from(1), 0.9), (Id::from(3), 0.8), (Id::from(2), 0.1)],
+            &master_data,
+            &gain_matrix,
+        );
+        assert_eq!(curve.points[2].gain, 89.0);
+        assert_eq!(curve.best().gain, 115.0);
+    }
+
+    #[test]
+    fn test_bootstrap_interval_and_paired_test() {
+        use crate::bootstrap::{confidence_interval, paired_test, rows_in_scope};
+        use crate::config::{BootstrapConfig, GainMatrix, IdType, MetricConfig};
+        use crate::master_data::MasterData;
+        use crate::metrics::{self, DensePredictions};
+        use crate::models::{Id, Predictions};
+
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("master_data.csv");
+        let mut csv = "id,clase_binaria\n".to_string();
+        for id in 0..200 {
+            csv.push_str(&format!("{},{}\n", id, (id % 4 == 0) as i32));
+        }
+        std::fs::write(&path, csv).unwrap();
+        let master_data = MasterData::load(path.to_str().unwrap(), IdType::Integer).unwrap();
+
+        let gain_matrix = GainMatrix {
+            tp: 10.0,
+            tn: 0.0,
+            fp: -1.0,
+            fn_: 0.0,
+        };
+        let metric = metrics::build(&MetricConfig::Gain, &gain_matrix, None);
+        let config = BootstrapConfig {
+            iterations: 300,
+            ..Default::default()
+        };
+        let rows = rows_in_scope(&master_data, None);
+
+        // All 50 positives plus 10 negatives: gain 490
+        let good = Predictions::Ids(
+            (0..200)
+                .filter(|id| id % 4 == 0 || *id < 40)
+                .map(Id::from)
+                .collect(),
+        );
+        let good = DensePredictions::new(&good, 0.0, &master_data);
+        // Every third row: a mix of hits and misses
+        let noisy = Predictions::Ids((0..200).filter(|id| id % 3 == 0).map(Id::from).collect());
+        let noisy = DensePredictions::new(&noisy, 0.0, &master_data);
+
+        let interval =
+            confidence_interval(metric.as_ref(), &good, &master_data, &rows, &config).unwrap();
+        assert!(interval.low < 490.0 && 490.0 < interval.high);
+
+        let same =
+            paired_test(metric.as_ref(), &good, &good, &master_data, &rows, &config).unwrap();
+        assert_eq!(same.difference, 0.0);
+        assert_eq!(same.p_value, 1.0);
+
+        let different =
+            paired_test(metric.as_ref(), &good, &noisy, &master_data, &rows, &config).unwrap();
+        assert!(different.difference > 0.0);
+        assert!(different.p_value < 0.05);
+        assert_eq!(different.win_rate, 1.0);
+
+        let disabled = BootstrapConfig {
+            iterations: 0,
+            ..Default::default()
+        };
+        assert!(
+            confidence_interval(metric.as_ref(), &good, &master_data, &rows, &disabled).is_none()
+        );
+    }
+
+    #[test]
+    fn test_rescore_archives_old_scores() {
+        use crate::database::Database;
+        use crate::models::{GainResult, Submission};
+        use crate::submission::process_rescore;
+        use crate::track::Track;
+
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        std::fs::write(dir.join("envio.csv"), "1\n2\n").unwrap();
+
+        let config = test_config(
+            dir,
+            "id,clase_binaria\n1,1\n2,0\n3,1\n4,0\n",
+            serde_json::json!({ "gain_matrix": { "tp": 10.0, "tn": 0.0, "fp": -2.0, "fn_": -1.0 } }),
+        );
+
+        let db = Database::new(&config.database.path).unwrap();
+        db.init().unwrap();
+        let stale = GainResult {
+            gain: 1.0,
+            ..Default::default()
+        };
+        let id = db
+            .save_submission(&Submission {
+                id: None,
+                user_id: 7,
+                user_email: "student@example.com".to_string(),
+                user_full_name: "Student".to_string(),
+                submission_name: "first".to_string(),
+                timestamp: "2025-01-01T00:00:00+00:00".to_string(),
+                file_checksum: "abc".to_string(),
+                file_path: dir.join("envio.csv").to_string_lossy().to_string(),
+                expected_gain: 5.0,
+                actual_gain: 1.0,
+                tp: 0,
+                tn: 0,
+                fp: 0,
+                fn_: 0,
+                positives_predicted: 2,
+                threshold_category: "basic".to_string(),
+                after_deadline: false,
+                public: stale.clone(),
+                private: stale,
+                cutoff: None,
+                best_gain: None,
+                best_cutoff: None,
+                confusion_matrix: None,
+                track: "main".to_string(),
+                withdrawal: None,
+                description: None,
+            })
+            .unwrap();
+
+        let track = Track::load(&config, "main").unwrap();
+        let report = process_rescore(&db, &track);
+        assert!(report.contains("**Con puntaje distinto:** 1"), "{}", report);
+
+        // TP=1 (10), FP=1 (-2), FN=1 (-1)
+        let rescored = db.get_submission(id).unwrap().unwrap();
+        assert_eq!(rescored.actual_gain, 7.0);
+        assert_eq!(
+            (rescored.tp, rescored.tn, rescored.fp, rescored.fn_),
+            (1, 1, 1, 1)
+        );
+        assert_eq!(rescored.public.gain, 7.0);
+        let history = db.get_score_history(id).unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].1, 1.0);
+    }
+
+    #[test]
+    fn test_dense_index_and_bitset_counts() {
+        use crate::config::{GainMatrix, IdType, MetricConfig};
+        use crate::dense::IdIndex;
+        use crate::master_data::{MasterData, Split};
+        use crate::metrics::{self, MetricSet};
+        use crate::models::{Id, Predictions};
+
+        // Contiguous IDs use the offset table, sparse ones binary search
+        for ids in [vec![3, 4, 6, 7], vec![-5, 10, 1_000_000, 2_000_000_000]] {
+            let ids: Vec<Id> = ids.into_iter().map(Id::from).collect();
+            let index = IdIndex::new(ids.clone());
+            for (row, id) in ids.iter().enumerate() {
+                assert_eq!(index.row_of(id), Some(row));
+            }
+            assert_eq!(index.row_of(&Id::from(5)), None);
+            assert_eq!(index.row_of(&Id::from(i64::MIN)), None);
+        }
+
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("master_data.csv");
+        let mut csv = "id,clase_binaria,split\n".to_string();
+        // Rows in reverse order and spanning several bitset words
+        for id in (0..150).rev() {
+            let split = if id % 3 == 0 { "private" } else { "public" };
+            csv.push_str(&format!("{},{},{}\n", id * 2, (id % 5 == 0) as i32, split));
+        }
+        std::fs::write(&path, &csv).unwrap();
+        let master_data = MasterData::load(path.to_str().unwrap(), IdType::Integer).unwrap();
+        assert_eq!(master_data.id(2), Id::from(4));
+        assert_eq!(master_data.positive_count(), 30);
+        assert_eq!(master_data.split_count(Split::Private), 50);
+
+        let gain_matrix = GainMatrix {
+            tp: 1.0,
+            tn: 0.0,
+            fp: 0.0,
+            fn_: 0.0,
+        };
+        // ROC-AUC needs every score, which forces the row-by-row path for probabilities
+        let metric_set = MetricSet::new(
+            metrics::build(&MetricConfig::Gain, &gain_matrix, None),
+            vec![metrics::build(&MetricConfig::RocAuc, &gain_matrix, None)],
+        );
+        let predicted: HashSet<Id> = (0..150)
+            .filter(|id| id % 2 == 0)
+            .map(|id| Id::from(id * 2))
+            .collect();
+        let ids = Predictions::Ids(predicted.clone());
+        let scores = Predictions::Scores(
+            (0..150)
+                .map(|id| (Id::from(id * 2), (id % 2 == 0) as i32 as f64))
+                .collect(),
+        );
+
+        for split in [None, Some(Split::Public), Some(Split::Private)] {
+            let (fast, _) = metric_set.evaluate(&ids, 0.5, &master_data, split);
+            let (slow, _) = metric_set.evaluate(&scores, 0.5, &master_data, split);
+            assert_eq!(
+                (fast.tp, fast.tn, fast.fp, fast.fn_),
+                (slow.tp, slow.tn, slow.fp, slow.fn_)
+            );
+        }
+        let (all, _) = metric_set.evaluate(&ids, 0.5, &master_data, None);
+        // Positives are multiples of 5, predicted are even: multiples of 10
+        assert_eq!(all.tp, 15);
+        assert_eq!(all.tp + all.tn + all.fp + all.fn_, 150);
+
+        std::fs::write(&path, "id,clase_binaria\n1,0\n1,1\n").unwrap();
+        assert!(MasterData::load(path.to_str().unwrap(), IdType::Integer).is_err());
+    }
+
+    #[test]
+    fn test_string_and_wide_ids() {
+        use crate::config::{GainMatrix, IdType, MetricConfig};
+        use crate::master_data::MasterData;
+        use crate::metrics::{self, MetricSet};
+        use crate::models::Id;
+        use crate::submission::{read_predictions, read_value_predictions};
+
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("master_data.csv");
+        std::fs::write(
+            &path,
+            "id,clase_binaria\n\
+             c3d1a0f2-9b1e-4c55-8f00-2a7e5d9c4b11,1\n\
+             a1b2c3d4-0000-4000-8000-000000000001,0\n\
+             CLI-0042,1\n",
+        )
+        .unwrap();
+        let master_data = MasterData::load(path.to_str().unwrap(), IdType::String).unwrap();
+        assert_eq!(master_data.id(0), Id::Text("CLI-0042".into()));
+        assert_eq!(master_data.row_of(&Id::Text("cli-0042".into())), None);
+        // Integer IDs are rejected by a string master and vice versa
+        assert!(MasterData::load(path.to_str().unwrap(), IdType::Integer).is_err());
+
+        let predictions = read_predictions(
+            b"id\nCLI-0042\nc3d1a0f2-9b1e-4c55-8f00-2a7e5d9c4b11\nunknown\n",
+            IdType::String,
+        )
+        .unwrap()
+        .predictions;
+        let unknown = master_data.validate_ids(predictions.ids());
+        assert_eq!(unknown, vec![Id::Text("unknown".into())]);
+
+        let gain_matrix = GainMatrix {
+            tp: 10.0,
+            tn: 1.0,
+            fp: -1.0,
+            fn_: -5.0,
+        };
+        let metric_set = MetricSet::new(
+            metrics::build(&MetricConfig::Gain, &gain_matrix, None),
+            vec![],
+        );
+        let (all, _) = metric_set.evaluate(&predictions, 0.5, &master_data, None);
+        assert_eq!((all.tp, all.tn, all.fp, all.fn_), (2, 1, 0, 0));
+
+        // IDs beyond the i32 range
+        std::fs::write(&path, "id,target\n5000000000,10\n-9000000000000,20\n").unwrap();
+        let master_data =
+            MasterData::load_regression(path.to_str().unwrap(), IdType::Integer).unwrap();
+        let predictions = read_value_predictions(
+            b"id,value\n5000000000,12\n-9000000000000,20\n",
+            IdType::Integer,
+        )
+        .unwrap()
+        .predictions;
+        assert!(master_data.validate_ids(predictions.ids()).is_empty());
+        assert_eq!(
+            master_data.target_of(master_data.row_of(&Id::from(5_000_000_000)).unwrap()),
+            Some(10.0)
+        );
+        let metric_set = MetricSet::new(
+            metrics::build(&MetricConfig::Mae, &gain_matrix, None),
+            vec![],
+        );
+        let (_, values) = metric_set.evaluate(&predictions, 0.5, &master_data, None);
+        assert_eq!(values[0].value, Some(1.0));
+    }
+
+    #[test]
+    fn test_calibration_by_student() {
+        use crate::calibration::{by_student, estimator_ranking};
+        use crate::models::{GainResult, Submission};
+
+        let submission = |user_id: i64, email: &str, expected: f64, actual: f64| Submission {
+            id: None,
+            user_id,
+            user_email: email.to_string(),
+            user_full_name: email.to_uppercase(),
+            submission_name: "envio".to_string(),
+            timestamp: "2025-01-01T00:00:00+00:00".to_string(),
+            file_checksum: String::new(),
+            file_path: String::new(),
+            expected_gain: expected,
+            actual_gain: actual,
+            tp: 0,
+            tn: 0,
+            fp: 0,
+            fn_: 0,
+            positives_predicted: 0,
+            threshold_category: String::new(),
+            after_deadline: false,
+            public: GainResult::default(),
+            private: GainResult::default(),
+            cutoff: None,
+            best_gain: None,
+            best_cutoff: None,
+            confusion_matrix: None,
+            track: "main".to_string(),
+            withdrawal: None,
+            description: None,
+        };
+        let submissions = vec![
+            // Always 10 too optimistic, but perfectly correlated
+            submission(1, "ana", 110.0, 100.0),
+            submission(1, "ana", 210.0, 200.0),
+            // Sent before her email changed, still the same student
+            Submission {
+                timestamp: "2024-12-01T00:00:00+00:00".to_string(),
+                ..submission(1, "ana.vieja", 60.0, 50.0)
+            },
+            // Closer on average, in both directions
+            submission(2, "bob", 102.0, 100.0),
+            submission(2, "bob", 48.0, 50.0),
+            submission(2, "bob", 75.0, 80.0),
+            // Too few submissions to be ranked
+            submission(3, "eve", 100.0, 100.0),
+            submission(4, "profe", 0.0, 100.0),
+        ];
+
+        let calibrations = by_student(&submissions, &["profe".to_string()]);
+        assert_eq!(calibrations.len(), 3);
+        let ana = &calibrations[0];
+        assert_eq!(
+            (ana.user_id, ana.user_email.as_str(), ana.submissions),
+            (1, "ana", 3)
+        );
+        assert_eq!((ana.mae, ana.bias), (10.0, 10.0));
+        assert!((ana.correlation.unwrap() - 1.0).abs() < 1e-12);
+        assert_eq!(calibrations[2].correlation, None);
+
+        let ranking: Vec<&str> = estimator_ranking(&calibrations)
+            .iter()
+            .map(|c| c.user_email.as_str())
+            .collect();
+        assert_eq!(ranking, vec!["bob", "ana"]);
+    }
+
+    #[test]
+    fn test_reference_scores() {
+        use crate::config::MetricConfig;
+        use crate::master_data::{MasterData, Split};
+        use crate::metrics::{self, MetricSet};
+        use crate::reference::References;
+
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        let mut csv = "id,clase_binaria,split\n".to_string();
+        for id in 0..100 {
+            let split = if id < 60 { "public" } else { "private" };
+            csv.push_str(&format!("{},{},{}\n", id, (id % 5 == 0) as i32, split));
+        }
+        let config = test_config(
+            dir,
+            &csv,
+            serde_json::json!({ "gain_matrix": { "tp": 10.0, "tn": 0.0, "fp": -1.0, "fn_": 0.0 } }),
+        );
+        let master_data = MasterData::load_for(&config).unwrap();
+        let references =
+            References::compute(&config, &master_data, &MetricSet::from_config(&config)).unwrap();
+
+        // 20 positives, 80 negatives; 12 and 48 of them in the public split
+        assert_eq!(references.all.oracle, Some(200.0));
+        assert_eq!(references.all.nothing, Some(0.0));
+        assert_eq!(references.all.everything, Some(120.0));
+        assert_eq!(references.scope(Some(Split::Public)).oracle, Some(120.0));
+        assert_eq!(references.public.everything, Some(72.0));
+        // Expected gain grows with the rate here, so the best random is close to "everything"
+        let random = references.all.random.unwrap();
+        assert!(
+            random.score <= 200.0 && random.score >= 100.0,
+            "{:?}",
+            random
+        );
+        assert_eq!(references.all.pct_of_oracle(150.0), Some(75.0));
+
+        // Lower-is-better primary metrics have no meaningful % of the oracle
+        let log_loss = MetricSet::new(
+            metrics::build(&MetricConfig::LogLoss, &config.gain_matrix, None),
+            vec![],
+        );
+        assert!(References::compute(&config, &master_data, &log_loss).is_none());
+    }
+
+    #[test]
+    fn test_relative_threshold_categories() {
+        use crate::config::{BotConfig, GainThreshold};
+        use crate::submission::{get_threshold_category, Standing};
+
+        let threshold = |category: &str, json: serde_json::Value| -> GainThreshold {
+            let mut value = json;
+            value["category"] = category.into();
+            value["message"] = "msg".into();
+            serde_json::from_value(value).unwrap()
+        };
+        let mut config: BotConfig = serde_json::from_value(serde_json::json!({
+            "zulip": { "email": "bot@example.com", "api_key": "key", "site": "https://example.com" },
+            "database": { "path": "test.db" },
+            "logs": { "path": "logs" },
+            "teachers": [],
+            "master_data": { "path": "master_data.csv" },
+            "submissions": { "path": "." },
+            "gain_matrix": { "tp": 10.0, "tn": 0.0, "fp": -1.0, "fn_": 0.0 },
+            "gain_thresholds": [],
+            "competition": {
+                "name": "test",
+                "description": "test",
+                "deadline": "2099-01-01T00:00:00",
+                "results_reveal_date": "2099-01-01T00:00:00"
+            }
+        }))
+        .unwrap();
+        assert!(config.validate().is_err(), "empty thresholds");
+
+        let peers = [10.0, 20.0, 30.0, 40.0];
+        let standing = Standing {
+            references: None,
+            peers: &peers,
+        };
+
+        config.gain_thresholds = vec![
+            threshold("top", serde_json::json!({ "min_percentile": 75.0 })),
+            threshold("mid", serde_json::json!({ "min_percentile": 50.0 })),
+            threshold("low", serde_json::json!({ "min_percentile": 0.0 })),
+        ];
+        config.validate().unwrap();
+        assert_eq!(
+            get_threshold_category(35.0, &config, true, &standing),
+            "top"
+        );
+        assert_eq!(
+            get_threshold_category(25.0, &config, true, &standing),
+            "mid"
+        );
+        assert_eq!(get_threshold_category(5.0, &config, true, &standing), "low");
+        // Lower is better: 5 beats every peer
+        assert_eq!(
+            get_threshold_category(5.0, &config, false, &standing),
+            "top"
+        );
+
+        config.gain_thresholds = vec![
+            threshold("podium", serde_json::json!({ "max_rank": 3 })),
+            threshold("rest", serde_json::json!({ "max_rank": 1000 })),
+        ];
+        config.validate().unwrap();
+        assert_eq!(
+            get_threshold_category(30.0, &config, true, &standing),
+            "podium"
+        );
+        assert_eq!(
+            get_threshold_category(15.0, &config, true, &standing),
+            "rest"
+        );
+
+        // Nobody to compare with: the least demanding category, not the top one
+        let alone = Standing {
+            references: None,
+            peers: &[],
+        };
+        assert_eq!(get_threshold_category(30.0, &config, true, &alone), "rest");
+        config.gain_thresholds = vec![
+            threshold("top", serde_json::json!({ "min_percentile": 75.0 })),
+            threshold("low", serde_json::json!({ "min_percentile": 0.0 })),
+        ];
+        assert_eq!(get_threshold_category(35.0, &config, true, &alone), "low");
+
+        // NaN and mixed criteria are rejected instead of panicking later
+        let mut nan = threshold("nan", serde_json::json!({ "min_gain": 0.0 }));
+        nan.min_gain = Some(f64::NAN);
+        config.gain_thresholds = vec![nan];
+        assert!(config.validate().is_err());
+        config.gain_thresholds = vec![
+            threshold("a", serde_json::json!({ "min_gain": 1.0 })),
+            threshold("b", serde_json::json!({ "max_rank": 1 })),
+        ];
+        assert!(config.validate().is_err());
+        config.gain_thresholds = vec![threshold(
+            "both",
+            serde_json::json!({ "min_gain": 1.0, "max_rank": 1 }),
+        )];
+        assert!(config.validate().is_err());
+
+        // % of the oracle needs a binary, higher-is-better competition
+        config.gain_thresholds = vec![threshold(
+            "pct",
+            serde_json::json!({ "min_pct_of_oracle": 50.0 }),
+        )];
+        config.validate().unwrap();
+        config.metrics.primary = crate::config::MetricConfig::LogLoss;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_tracks_have_their_own_data_and_leaderboard() {
+        use crate::database::Database;
+        use crate::master_data::Split;
+        use crate::models::{GainResult, Submission};
+        use crate::track::{self, Track};
+
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        std::fs::write(dir.join("bonus.csv"), "id,clase_binaria\n1,1\n2,1\n3,0\n").unwrap();
+
+        let config = test_config(
+            dir,
+            "id,clase_binaria\n1,1\n2,0\n",
+            serde_json::json!({
+                "gain_matrix": { "tp": 10.0, "tn": 0.0, "fp": -2.0, "fn_": -1.0 },
+                "competition": {
+                    "tracks": [{
+                        "name": "bonus",
+                        "master_data": { "path": dir.join("bonus.csv") },
+                        "gain_matrix": { "tp": 1.0, "tn": 0.0, "fp": 0.0, "fn_": 0.0 },
+                        "gain_thresholds": [{ "min_gain": 1.0, "category": "bonus", "message": "ok" }]
+                    }]
+                }
+            }),
+        );
+        config.validate().unwrap();
+        assert_eq!(config.track_names(), vec!["main", "bonus"]);
+
+        let tracks = Track::load_all(&config).unwrap();
+        let bonus = track::find(&tracks, "BONUS").unwrap();
+        assert_eq!(bonus.master_data.total_count(), 3);
+        assert_eq!(bonus.config.gain_matrix.tp, 1.0);
+        assert_eq!(bonus.config.gain_thresholds[0].category, "bonus");
+        assert_eq!(tracks[0].config.gain_matrix.tp, 10.0);
+        assert!(track::find(&tracks, "missing").is_none());
+
+        // Each track ranks only its own submissions
+        let db = Database::new(&config.database.path).unwrap();
+        db.init().unwrap();
+        for (user_id, track, gain) in [(1, "main", 10.0), (2, "bonus", 2.0), (1, "bonus", 1.0)] {
+            let result = GainResult {
+                gain,
+                ..Default::default()
+            };
+            db.save_submission(&Submission {
+                id: None,
+                user_id,
+                user_email: format!("student{}@example.com", user_id),
+                user_full_name: format!("Student {}", user_id),
+                submission_name: "envio".to_string(),
+                timestamp: "2025-01-01T00:00:00+00:00".to_string(),
+                file_checksum: format!("{}-{}", track, user_id),
+                file_path: String::new(),
+                expected_gain: 0.0,
+                actual_gain: gain,
+                tp: 0,
+                tn: 0,
+                fp: 0,
+                fn_: 0,
+                positives_predicted: 0,
+                threshold_category: String::new(),
+                after_deadline: false,
+                public: result.clone(),
+                private: result,
+                cutoff: None,
+                best_gain: None,
+                best_cutoff: None,
+                confusion_matrix: None,
+                track: track.to_string(),
+                withdrawal: None,
+                description: None,
+            })
+            .unwrap();
+        }
+        let main = db
+            .get_leaderboard("main", "gain", Split::Public, true)
+            .unwrap();
+        assert_eq!(main.len(), 1);
+        assert_eq!(main[0].final_gain, 10.0);
+        let bonus = db
+            .get_leaderboard("bonus", "gain", Split::Public, true)
+            .unwrap();
+        let gains: Vec<f64> = bonus.iter().map(|e| e.final_gain).collect();
+        assert_eq!(gains, vec![2.0, 1.0]);
+
+        // Track names are single words, unique and not the reserved main one
+        for name in ["main", "bonus", "two words"] {
+            let mut invalid = config.clone();
+            let mut extra = invalid.competition.tracks[0].clone();
+            extra.name = name.to_string();
+            invalid.competition.tracks.push(extra);
+            assert!(invalid.validate().is_err(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_ensemble_rules() {
+        use crate::database::Database;
+        use crate::dense::BitSet;
+        use crate::ensemble::{combine, Rule};
+        use crate::models::{GainResult, Submission};
+        use crate::submission::process_ensemble;
+        use crate::track::Track;
+
+        assert_eq!(Rule::parse("Majority").unwrap(), Rule::Majority);
+        assert_eq!(Rule::parse("2-of-n").unwrap(), Rule::AtLeast(2));
+        assert!(Rule::parse("most").is_err());
+        assert_eq!(Rule::Majority.min_votes(4).unwrap(), 3);
+        assert_eq!(Rule::Majority.min_votes(3).unwrap(), 2);
+        assert!(Rule::AtLeast(4).min_votes(3).is_err());
+
+        let set = |rows: &[usize]| {
+            let mut set = BitSet::new(4);
+            rows.iter().for_each(|row| set.insert(*row));
+            set
+        };
+        let members = [set(&[0, 1]), set(&[0, 2]), set(&[0, 1, 3])];
+        let members: Vec<&BitSet> = members.iter().collect();
+        assert_eq!(combine(&members, 1), set(&[0, 1, 2, 3]));
+        assert_eq!(combine(&members, 2), set(&[0, 1]));
+        assert_eq!(combine(&members, 3), set(&[0]));
+
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        let config = test_config(
+            dir,
+            "id,clase_binaria\n1,1\n2,1\n3,0\n4,0\n",
+            serde_json::json!({ "gain_matrix": { "tp": 10.0, "tn": 0.0, "fp": -5.0, "fn_": 0.0 } }),
+        );
+        let tracks = vec![Track::load(&config, "main").unwrap()];
+        let db = Database::new(&config.database.path).unwrap();
+        db.init().unwrap();
+
+        // Each student gets one positive right and one wrong; they only agree on the right ones
+        let mut ids = Vec::new();
+        for (user_id, rows) in [(1, "1\n3\n"), (2, "2\n4\n"), (3, "1\n2\n")] {
+            let path = dir.join(format!("envio{}.csv", user_id));
+            std::fs::write(&path, rows).unwrap();
+            let id = db
+                .save_submission(&Submission {
+                    id: None,
+                    user_id,
+                    user_email: format!("student{}@example.com", user_id),
+                    user_full_name: format!("Student {}", user_id),
+                    submission_name: "envio".to_string(),
+                    timestamp: "2025-01-01T00:00:00+00:00".to_string(),
+                    file_checksum: user_id.to_string(),
+                    file_path: path.to_string_lossy().to_string(),
+                    expected_gain: 0.0,
+                    actual_gain: 0.0,
+                    tp: 0,
+                    tn: 0,
+                    fp: 0,
+                    fn_: 0,
+                    positives_predicted: 2,
+                    threshold_category: String::new(),
+                    after_deadline: false,
+                    public: GainResult::default(),
+                    private: GainResult::default(),
+                    cutoff: None,
+                    best_gain: None,
+                    best_cutoff: None,
+                    confusion_matrix: None,
+                    track: "main".to_string(),
+                    withdrawal: None,
+                    description: None,
+                })
+                .unwrap();
+            ids.push(id);
+        }
+        let before = db.get_all_submissions().unwrap().len();
+
+        // Majority keeps rows 1 and 2: 2 TP = 20
+        let report = process_ensemble(&ids, Rule::Majority, &db, &tracks);
+        assert!(
+            report.contains("| 🧩 | **Ensamble** | 2 | 20.00 |"),
+            "{}",
+            report
+        );
+        // Union adds both false positives: 20 - 10
+        let report = process_ensemble(&ids, Rule::Union, &db, &tracks);
+        assert!(
+            report.contains("| 🧩 | **Ensamble** | 4 | 10.00 |"),
+            "{}",
+            report
+        );
+        assert_eq!(db.get_all_submissions().unwrap().len(), before);
+
+        assert!(process_ensemble(&ids[..1], Rule::Union, &db, &tracks).starts_with("❌"));
+        assert!(process_ensemble(&ids, Rule::AtLeast(5), &db, &tracks).starts_with("❌"));
+    }
+
+    #[test]
+    fn test_csv_dialects_and_error_reports() {
+        use crate::config::IdType;
+        use crate::csv_dialect::CsvError;
+        use crate::models::{Id, Predictions};
+        use crate::submission::read_predictions;
+
+        let scores = |content: &[u8]| match read_predictions(content, IdType::Integer)
+            .unwrap()
+            .predictions
+        {
+            Predictions::Scores(scores) => scores,
+            _ => panic!("expected probability format"),
+        };
+        let expected = vec![(Id::from(1), 0.9), (Id::from(2), 0.25)];
+        // Header, semicolon and decimal comma, BOM and CRLF, quotes and padding, trailing column
+        assert_eq!(scores(b"id;probability\n1;0,9\n2;0,25\n"), expected);
+        assert_eq!(
+            scores(b"\xEF\xBB\xBFid,probability\r\n1,0.9\r\n\r\n2,0.25\r\n"),
+            expected
+        );
+        assert_eq!(
+            scores(b"\"id\",\"prob\"\n\" 1 \",\"0.9\"\n2 , 0.25\n"),
+            expected
+        );
+        assert_eq!(scores(b"1\t0.9\t\n2\t0.25\t\n"), expected);
+
+        let ids = match read_predictions(b"\xEF\xBB\xBFid,\n\"7\",\n8,\n", IdType::Integer)
+            .unwrap()
+            .predictions
+        {
+            Predictions::Ids(ids) => ids,
+            _ => panic!("expected a list of IDs"),
+        };
+        assert_eq!(ids, [7, 8].into_iter().map(Id::from).collect());
+
+        let error = |content: &[u8]| -> CsvError {
+            read_predictions(content, IdType::Integer)
+                .unwrap_err()
+                .downcast::<CsvError>()
+                .unwrap()
+        };
+        let bad_id = error(b"id\n1\n\n12.0\n");
+        assert_eq!((bad_id.line, bad_id.value.as_str()), (4, "12.0"));
+        assert!(bad_id.hint.contains("`12`"), "{}", bad_id.hint);
+        let bad_score = error(b"1,0.5\n2,alto\n");
+        assert_eq!((bad_score.line, bad_score.value.as_str()), (2, "alto"));
+        assert_eq!(error(b"1,0.5\n2,0.5,x\n").line, 2);
+        assert!(bad_id.report().contains("línea 4"));
+
+        // A first row is dropped only when it is the expected header
+        assert_eq!(
+            scores(b"ID_cliente,Probabilidad\n1,0.9\n2,0.25\n"),
+            expected
+        );
+        let bad_header = error(b"id,probabilty\n1,0.9\n");
+        assert_eq!(
+            (bad_header.line, bad_header.value.as_str()),
+            (1, "id,probabilty")
+        );
+        assert!(
+            bad_header.hint.contains("`id,probability`"),
+            "{}",
+            bad_header.hint
+        );
+        let bad_first = error(b"1,alto\n2,0.25\n");
+        assert_eq!((bad_first.line, bad_first.value.as_str()), (1, "alto"));
+    }
+
+    #[test]
+    fn test_compressed_attachments() {
+        use crate::attachment::{expand, ArchiveError, Compression, LimitError};
+        use flate2::{write::GzEncoder, Compression as Level};
+        use std::io::{Cursor, Write};
+        use zip::{write::FileOptions, ZipWriter};
+
+        let csv = b"id,probability\n1,0.9\n2,0.1\n".to_vec();
+        assert_eq!(Compression::of("Envio.CSV.GZ"), Some(Compression::Gzip));
+        assert_eq!(Compression::of("envio.xlsx"), None);
+
+        let mut gz = GzEncoder::new(Vec::new(), Level::default());
+        gz.write_all(&csv).unwrap();
+        let gz = gz.finish().unwrap();
+        let expanded = expand("envio.csv.gz", gz.clone(), 1024).unwrap();
+        assert_eq!(expanded.filename, "envio.csv");
+        assert_eq!(expanded.content, csv);
+        // Over the cap fails instead of truncating
+        let error = |result: anyhow::Result<_>| result.err().unwrap();
+        assert!(error(expand("envio.csv.gz", gz, 10)).is::<LimitError>());
+        let invalid = error(expand("envio.csv.gz", csv.clone(), 1024));
+        assert_eq!(invalid.downcast_ref(), Some(&ArchiveError::InvalidGzip));
+        assert!(invalid.to_string().contains("no es un gzip válido"));
+
+        let zip = |files: &[&str]| {
+            let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+            writer
+                .add_directory("__MACOSX/", FileOptions::default())
+                .unwrap();
+            for name in files {
+                writer.start_file(*name, FileOptions::default()).unwrap();
+                writer.write_all(&csv).unwrap();
+            }
+            writer.finish().unwrap().into_inner()
+        };
+        let expanded = expand(
+            "envio.zip",
+            zip(&["dir/pred.csv", "__MACOSX/dir/._pred.csv"]),
+            1024,
+        )
+        .unwrap();
+        assert_eq!(expanded.filename, "pred.csv");
+        assert_eq!(expanded.content, csv);
+        let two = error(expand("envio.zip", zip(&["a.csv", "b.csv"]), 1024));
+        assert_eq!(
+            two.downcast_ref(),
+            Some(&ArchiveError::EntryCount { files: 2 })
+        );
+        assert!(two.to_string().contains("exactamente un CSV (contiene 2)"));
+        let not_csv = error(expand("envio.zip", zip(&["notas.txt"]), 1024));
+        assert!(not_csv.to_string().contains("`notas.txt`"));
+        assert_eq!(
+            error(expand("envio.zip", csv.clone(), 1024)).downcast_ref(),
+            Some(&ArchiveError::InvalidZip)
+        );
+        assert!(expand("envio.zip", zip(&["pred.csv"]), 10).is_err());
+    }
+
+    #[test]
+    fn test_validation_report() {
+        use crate::config::{IdType, Severity, ValidationConfig};
+        use crate::master_data::MasterData;
+        use crate::submission::read_predictions;
+        use crate::validation::validate;
+
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("master_data.csv");
+        std::fs::write(&path, "id,clase_binaria\n1,1\n2,0\n3,1\n4,0\n").unwrap();
+        let master_data = MasterData::load(path.to_str().unwrap(), IdType::Integer).unwrap();
+
+        let file = read_predictions(b"1\n2\n2\n3\n9\n8\n7\n1\n", IdType::Integer).unwrap();
+        let ids: Vec<String> = file.duplicates.iter().map(|id| id.to_string()).collect();
+        assert_eq!(ids, vec!["2", "1"]);
+
+        let mut config = ValidationConfig {
+            listed_ids: 2,
+            max_positive_pct: Some(50.0),
+            ..Default::default()
+        };
+        // 1, 2 and 3 of 4 rows predicted positive
+        let report = validate(&file, &master_data, Some(3), &config);
+        assert!(report.has_errors());
+        assert_eq!(report.findings.len(), 3);
+        let rendered = report.render();
+        assert!(rendered.contains("3 de 4 (75.0%)"), "{}", rendered);
+        assert!(rendered.contains("`7`, `8` y 1 más"), "{}", rendered);
+        assert!(
+            rendered.contains("⚠️ **Predijiste 75.0% de positivos**"),
+            "{}",
+            rendered
+        );
+
+        config.duplicate_ids = Severity::Warning;
+        config.unknown_ids = Severity::Warning;
+        config.positive_share = Severity::Off;
+        let report = validate(&file, &master_data, Some(3), &config);
+        assert!(!report.has_errors());
+        assert_eq!(report.findings.len(), 2);
+
+        let clean = read_predictions(b"id\n1\n3\n", IdType::Integer).unwrap();
+        let report = validate(&clean, &master_data, Some(2), &ValidationConfig::default());
+        assert!(report.findings.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_download_and_row_limits() {
+        use crate::attachment::{download, LimitError};
+        use crate::csv_dialect::{read_rows, CsvError, Row};
+        use std::time::Duration;
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio::net::TcpListener;
+
+        // Serves `body` once, after `delay`, without announcing its length
+        async fn serve(body: Vec<u8>, delay: Duration) -> String {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = format!(
+                "http://{}/user_uploads/envio.csv",
+                listener.local_addr().unwrap()
+            );
+            tokio::spawn(async move {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut request = [0u8; 1024];
+                let _ = socket.read(&mut request).await;
+                tokio::time::sleep(delay).await;
+                let _ = socket
+                    .write_all(b"HTTP/1.1 200 OK\r\nConnection: close\r\n\r\n")
+                    .await;
+                let _ = socket.write_all(&body).await;
+            });
+            url
+        }
+
+        let client = reqwest::Client::new();
+        let second = Duration::from_secs(1);
+        let limit = |e: anyhow::Error| e.downcast::<LimitError>().unwrap();
+
+        let url = serve(b"id\n1\n2\n".to_vec(), Duration::ZERO).await;
+        let content = download(client.get(&url), 1024, 5 * second).await.unwrap();
+        assert_eq!(content, b"id\n1\n2\n");
+
+        let url = serve(vec![b'1'; 3 * 1024 * 1024], Duration::ZERO).await;
+        let error = download(client.get(&url), 2 * 1024 * 1024, 5 * second)
+            .await
+            .unwrap_err();
+        assert_eq!(limit(error), LimitError::Download { max_mb: 2 });
+
+        let url = serve(b"id\n1\n".to_vec(), 3 * second).await;
+        let error = download(client.get(&url), 1024, second).await.unwrap_err();
+        assert_eq!(limit(error), LimitError::Timeout { secs: 1 });
+
+        // Header plus `max_rows` rows, blank lines not counted
+        let rows = |content: &[u8]| -> Result<Vec<Row>, CsvError> {
+            let mut rows = read_rows(content).unwrap().limit(3);
+            rows.skip_header(&[], |row| row.fields[0].parse::<u64>().is_ok())?;
+            rows.collect()
+        };
+        assert_eq!(rows(b"id\n1\n\n2\n3\n").unwrap().len(), 3);
+        let error = rows(b"id\n1\n2\n3\n4\n5\n").unwrap_err();
+        assert_eq!(error.line, 5);
+        assert!(error.report().contains("máximo de 3 filas"));
+    }
+
+    #[tokio::test]
+    async fn test_submission_quotas() {
+        use crate::models::{GainResult, Message, Submission};
+        use crate::quota::{self, Exceeded};
+        use crate::submission::process_submit;
+
+        use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
+
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        let config = test_config(
+            dir,
+            "id,clase_binaria\n1,1\n2,0\n",
+            serde_json::json!({
+                "submissions": { "quota": { "daily": 2, "total": 3, "reset_time": "08:00" } },
+                "competition": { "timezone": "America/Argentina/Buenos_Aires" }
+            }),
+        );
+        let (tracks, db) = test_competition(&config);
+
+        // 07:00 in Buenos Aires (UTC-3) still counts against the previous day
+        let tz = config.competition.timezone().unwrap();
+        let reset = NaiveTime::from_hms_opt(8, 0, 0).unwrap();
+        let at = |ts: &str| {
+            DateTime::parse_from_rfc3339(ts)
+                .unwrap()
+                .with_timezone(&Utc)
+        };
+        assert_eq!(
+            quota::quota_day(at("2025-03-10T10:00:00Z"), tz, reset),
+            NaiveDate::from_ymd_opt(2025, 3, 9).unwrap()
+        );
+        assert_eq!(
+            quota::quota_day(at("2025-03-10T11:30:00Z"), tz, reset),
+            NaiveDate::from_ymd_opt(2025, 3, 10).unwrap()
+        );
+
+        let now = Utc::now();
+        for timestamp in [
+            now.to_rfc3339(),
+            now.to_rfc3339(),
+            "2025-01-01T12:00:00+00:00".to_string(),
+        ] {
+            db.save_submission(&Submission {
+                id: None,
+                user_id: 1,
+                user_email: "student@example.com".to_string(),
+                user_full_name: "Student".to_string(),
+                submission_name: "envio".to_string(),
+                timestamp,
+                file_checksum: String::new(),
+                file_path: String::new(),
+                expected_gain: 0.0,
+                actual_gain: 0.0,
+                tp: 0,
+                tn: 0,
+                fp: 0,
+                fn_: 0,
+                positives_predicted: 0,
+                threshold_category: String::new(),
+                after_deadline: false,
+                public: GainResult::default(),
+                private: GainResult::default(),
+                cutoff: None,
+                best_gain: None,
+                best_cutoff: None,
+                confusion_matrix: None,
+                track: "main".to_string(),
+                withdrawal: None,
+                description: None,
+            })
+            .unwrap();
+        }
+
+        let usage = quota::usage(&db, &config, 1, now).unwrap();
+        assert_eq!((usage.used_today, usage.used_total), (2, 3));
+        // Quotas follow the Zulip user, not the display name
+        assert_eq!(quota::usage(&db, &config, 2, now).unwrap().used_total, 0);
+        assert_eq!(usage.exceeded(), Some(Exceeded::Daily));
+        assert!(usage.next_reset > now);
+
+        // Over quota: recorded, not downloaded nor scored
+        let message = Message {
+            msg_type: "private".to_string(),
+            sender_email: "student@example.com".to_string(),
+            sender_id: 1,
+            sender_full_name: "Student".to_string(),
+            content: "submit otro 10 [envio.csv](/user_uploads/envio.csv)".to_string(),
+            timestamp: 0,
+        };
+        let response = process_submit(&message, &tracks, &db, false).await;
+        assert!(response.contains("cuota diaria"), "{}", response);
+        assert_eq!(db.get_rejected_attempts("Student").unwrap().len(), 1);
+        assert_eq!(db.get_all_submissions().unwrap().len(), 3);
+
+        // A grant raises today's quota and the total one
+        let usage =
+            quota::grant(&db, &config, 1, "Student", 1, "teacher@example.com", now).unwrap();
+        assert_eq!((usage.daily_limit, usage.total_limit), (Some(3), Some(4)));
+        assert_eq!(usage.exceeded(), None);
+        assert!(usage.render().contains("**Total:** 3 de 4"));
+    }
+
+    #[test]
+    fn test_final_selection_drives_leaderboard() {
+        use crate::database::Database;
+        use crate::master_data::Split;
+        use crate::models::{GainResult, Submission};
+        use crate::submission::process_select;
+
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        let config = test_config(
+            dir,
+            "id,clase_binaria\n1,1\n2,0\n",
+            serde_json::json!({ "competition": { "max_selected": 2 } }),
+        );
+        let (tracks, db) = test_competition(&config);
+
+        // Student 1 sends 50, 80, 30 and 10 (last); student 2 sends 40
+        let mut ids = Vec::new();
+        for (user_id, gain, day) in [
+            (1, 50.0, 1),
+            (1, 80.0, 2),
+            (1, 30.0, 3),
+            (1, 10.0, 4),
+            (2, 40.0, 1),
+        ] {
+            let id = db
+                .save_submission(&Submission {
+                    id: None,
+                    user_id,
+                    user_email: format!("student{}@example.com", user_id),
+                    user_full_name: format!("Student {}", user_id),
+                    submission_name: "envio".to_string(),
+                    timestamp: format!("2025-01-0{}T00:00:00+00:00", day),
+                    file_checksum: String::new(),
+                    file_path: String::new(),
+                    expected_gain: 0.0,
+                    actual_gain: gain,
+                    tp: 0,
+                    tn: 0,
+                    fp: 0,
+                    fn_: 0,
+                    positives_predicted: 0,
+                    threshold_category: String::new(),
+                    after_deadline: false,
+                    public: GainResult {
+                        gain,
+                        ..GainResult::default()
+                    },
+                    private: GainResult::default(),
+                    cutoff: None,
+                    best_gain: None,
+                    best_cutoff: None,
+                    confusion_matrix: None,
+                    track: "main".to_string(),
+                    withdrawal: None,
+                    description: None,
+                })
+                .unwrap();
+            ids.push(id);
+        }
+        let chosen = |db: &Database| -> Vec<(f64, bool)> {
+            db.get_leaderboard("main", "gain", Split::Public, true)
+                .unwrap()
+                .iter()
+                .map(|entry| (entry.final_gain, entry.selected))
+                .collect()
+        };
+
+        // Nothing selected: the last on-time submission counts
+        assert_eq!(chosen(&db), vec![(40.0, false), (10.0, false)]);
+
+        // The best selected one counts, even if it is not the last
+        assert!(process_select(1, &[ids[2], ids[0]], &db, &tracks).starts_with("✅"));
+        assert_eq!(chosen(&db), vec![(50.0, true), (40.0, false)]);
+        assert!(
+            process_select(1, &[], &db, &tracks).contains(&format!("`{}`, `{}`", ids[0], ids[2]))
+        );
+
+        // A new selection replaces the previous one
+        assert!(process_select(1, &[ids[1]], &db, &tracks).starts_with("✅"));
+        assert_eq!(chosen(&db), vec![(80.0, true), (40.0, false)]);
+
+        // Only up to `max_selected`, and only your own submissions
+        assert!(process_select(1, &ids[..3], &db, &tracks).contains("hasta 2"));
+        assert!(process_select(1, &[ids[4]], &db, &tracks).starts_with("❌"));
+        assert_eq!(db.get_selected_submissions(1).unwrap(), vec![ids[1]]);
+
+        // The row shows when the chosen submission was sent, not the best or last one
+        assert!(process_select(1, &[ids[2]], &db, &tracks).starts_with("✅"));
+        let leaderboard = db
+            .get_leaderboard("main", "datetime", Split::Public, true)
+            .unwrap();
+        let rows: Vec<(f64, &str)> = leaderboard
+            .iter()
+            .map(|entry| (entry.final_gain, entry.timestamp.as_str()))
+            .collect();
+        assert_eq!(
+            rows,
+            vec![
+                (30.0, "2025-01-03T00:00:00+00:00"),
+                (40.0, "2025-01-01T00:00:00+00:00")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_withdrawn_submissions_stop_counting() {
+        use crate::master_data::Split;
+        use crate::models::{GainResult, Submission};
+        use crate::quota;
+        use crate::submission::{process_select, process_user_submits, process_withdraw};
+
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        let config = test_config(
+            dir,
+            "id,clase_binaria\n1,1\n2,0\n",
+            serde_json::json!({ "submissions": { "quota": { "total": 5 } } }),
+        );
+        let (tracks, db) = test_competition(&config);
+
+        // Student 2 uploads the same file as the last one of student 1
+        let mut ids = Vec::new();
+        for (user_id, gain, day, checksum) in
+            [(1, 50.0, 1, "a"), (1, 10.0, 2, "b"), (2, 10.0, 3, "b")]
+        {
+            let id = db
+                .save_submission(&Submission {
+                    id: None,
+                    user_id,
+                    user_email: format!("student{}@example.com", user_id),
+                    user_full_name: format!("Student {}", user_id),
+                    submission_name: format!("envio{}", day),
+                    timestamp: format!("2025-01-0{}T00:00:00+00:00", day),
+                    file_checksum: checksum.to_string(),
+                    file_path: String::new(),
+                    expected_gain: 0.0,
+                    actual_gain: gain,
+                    tp: 0,
+                    tn: 0,
+                    fp: 0,
+                    fn_: 0,
+                    positives_predicted: 0,
+                    threshold_category: String::new(),
+                    after_deadline: false,
+                    public: GainResult {
+                        gain,
+                        ..GainResult::default()
+                    },
+                    private: GainResult::default(),
+                    cutoff: None,
+                    best_gain: None,
+                    best_cutoff: None,
+                    confusion_matrix: None,
+                    track: "main".to_string(),
+                    withdrawal: None,
+                    description: None,
+                })
+                .unwrap();
+            ids.push(id);
+        }
+        assert_eq!(db.get_duplicates().unwrap().len(), 1);
+
+        // Only the author can withdraw, with a reason, and only once
+        assert!(process_withdraw(2, ids[1], "no es mío", &db, &tracks).starts_with("❌"));
+        assert!(process_withdraw(1, ids[1], "  ", &db, &tracks).contains("motivo"));
+        assert!(process_withdraw(1, ids[1], "archivo equivocado", &db, &tracks).starts_with("🗑️"));
+        assert!(
+            process_withdraw(1, ids[1], "otra vez", &db, &tracks).contains("ya estaba retirado")
+        );
+        // A withdrawn submission cannot be chosen for the final ranking
+        let response = process_select(1, &[ids[1]], &db, &tracks);
+        assert!(response.contains("está retirado"), "{}", response);
+        assert!(db.get_selected_submissions(1).unwrap().is_empty());
+
+        let student = db
+            .get_leaderboard("main", "gain", Split::Public, true)
+            .unwrap()
+            .into_iter()
+            .find(|entry| entry.user_full_name == "Student 1")
+            .unwrap();
+        assert_eq!((student.final_gain, student.total_submissions), (50.0, 1));
+        assert!(db.get_duplicates().unwrap().is_empty());
+        let usage = quota::usage(&db, &config, 1, chrono::Utc::now()).unwrap();
+        assert_eq!(usage.used_total, 1);
+        assert_eq!(db.get_user_submissions("Student 1").unwrap().len(), 1);
+
+        // Teachers still see it, with the reason
+        let report = process_user_submits("Student 1", &db, &tracks);
+        assert!(
+            report.contains("🗑️") && report.contains("archivo equivocado"),
+            "{}",
+            report
+        );
+    }
+
+    #[tokio::test]
+    async fn test_submit_arguments_descriptions_and_rename() {
+        use crate::args::{parse_submit, split};
+
+        use crate::models::Message;
+        use crate::submission::{process_rename, process_submit};
+
+        assert_eq!(
+            split("a \"b c\"  “d e” \"\"").unwrap(),
+            vec!["a", "b c", "d e", ""]
+        );
+        assert!(split("submit \"sin cerrar 10").is_err());
+
+        let args = parse_submit("submit \"xgboost tuned\" 1200 0.4 [mis preds.csv](/user_uploads/1/mis preds.csv) --desc más árboles").unwrap();
+        assert_eq!(
+            (args.name.as_str(), args.expected_gain, args.cutoff),
+            ("xgboost tuned", 1200.0, Some(0.4))
+        );
+        assert_eq!(args.description.as_deref(), Some("más árboles"));
+        let args = parse_submit("submit rf 100 bonus probé con menos features").unwrap();
+        assert_eq!((args.cutoff, args.track, args.rest.len()), (None, None, 5));
+        let args = parse_submit("submit rf 100 0.3 track=bonus probé").unwrap();
+        assert_eq!((args.track.as_deref(), args.rest.len()), (Some("bonus"), 1));
+        assert!(parse_submit("submit rf 100 track=a track=b").is_err());
+        assert!(parse_submit("submit rf 100 1.5")
+            .unwrap_err()
+            .contains("entre 0 y 1"));
+        assert!(parse_submit("submit rf 100 -0.1")
+            .unwrap_err()
+            .contains("entre 0 y 1"));
+        assert!(parse_submit("submit xgboost tuned 1200")
+            .unwrap_err()
+            .contains("número"));
+        assert!(parse_submit("submit \"   \" 1200")
+            .unwrap_err()
+            .contains("vacío"));
+
+        // Zulip serves the attachment of every submit
+        let site = serve_attachments(b"1\n").await;
+
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        let config = test_config(
+            dir,
+            "id,clase_binaria\n1,1\n2,0\n",
+            serde_json::json!({ "zulip": { "site": site } }),
+        );
+        let (tracks, db) = test_competition(&config);
+
+        let submit = |content: &str| Message {
+            msg_type: "private".to_string(),
+            sender_email: "student@example.com".to_string(),
+            sender_id: 1,
+            sender_full_name: "Student".to_string(),
+            content: format!("{} [mis preds.csv](/user_uploads/1/mis preds.csv)", content),
+            timestamp: 0,
+        };
+        // The cut-off only applies to probability files; rejected files are not stored
+        let response = process_submit(&submit("submit corte 1 0.3"), &tracks, &db, false).await;
+        assert!(response.contains("el corte solo aplica"), "{}", response);
+        assert!(db.get_blobs().unwrap().is_empty());
+
+        let response = process_submit(
+            &submit("submit \"xgboost tuned\" 1 --desc más árboles"),
+            &tracks,
+            &db,
+            false,
+        )
+        .await;
+        assert!(
+            response.contains("📝 **Descripción:** más árboles"),
+            "{}",
+            response
+        );
+        let response = process_submit(
+            &submit("submit lineal 1 primera prueba"),
+            &tracks,
+            &db,
+            false,
+        )
+        .await;
+        assert!(response.contains("primera prueba"), "{}", response);
+        // A track name in the description is just a word
+        let response =
+            process_submit(&submit("submit árbol 1 main es mejor"), &tracks, &db, false).await;
+        assert!(
+            response.contains("📝 **Descripción:** main es mejor"),
+            "{}",
+            response
+        );
+        let response =
+            process_submit(&submit("submit otro 1 track=nada"), &tracks, &db, false).await;
+        assert!(
+            response.contains("No existe el track 'nada'"),
+            "{}",
+            response
+        );
+
+        let stored = db.get_user_submissions("Student").unwrap();
+        let xgboost = stored
+            .iter()
+            .find(|sub| sub.submission_name == "xgboost tuned")
+            .unwrap();
+        assert_eq!(xgboost.description.as_deref(), Some("más árboles"));
+        let xgboost_id = xgboost.id.unwrap();
+
+        // Names are unique per student, ignoring case
+        let response =
+            process_submit(&submit("submit \"XGBoost Tuned\" 1"), &tracks, &db, false).await;
+        assert!(
+            response.contains("Ya tienes un envío llamado"),
+            "{}",
+            response
+        );
+        // Another student with the same display name has their own names
+        let namesake = Message {
+            sender_id: 2,
+            sender_email: "namesake@example.com".to_string(),
+            ..submit("submit \"xgboost tuned\" 1")
+        };
+        let response = process_submit(&namesake, &tracks, &db, false).await;
+        assert!(response.contains("🆔"), "{}", response);
+        assert!(process_rename(1, xgboost_id, "Lineal", &db).starts_with("❌"));
+        assert!(process_rename(2, xgboost_id, "mío", &db).starts_with("❌"));
+        assert!(process_rename(1, xgboost_id, "xgboost  final", &db).starts_with("✏️"));
+        assert_eq!(
+            db.get_submission(xgboost_id)
+                .unwrap()
+                .unwrap()
+                .submission_name,
+            "xgboost final"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_batch_submit() {
+        use crate::args::{from_filename, parse_attachment_line};
+
+        use crate::models::Message;
+        use crate::submission::process_submit;
+
+        assert_eq!(from_filename("rf_900.csv"), ("rf".to_string(), Some(900.0)));
+        assert_eq!(
+            from_filename("xgb-tuned-12.5.csv.gz"),
+            ("xgb-tuned".to_string(), Some(12.5))
+        );
+        assert_eq!(from_filename("preds.zip"), ("preds".to_string(), None));
+        let args = parse_attachment_line(
+            "\"xgb a\" 10 0.4 [a.csv](/u/a.csv) --desc más profundo",
+            "a.csv",
+            false,
+        )
+        .unwrap();
+        assert_eq!(
+            (args.name.as_str(), args.expected_gain, args.cutoff),
+            ("xgb a", 10.0, Some(0.4))
+        );
+        assert_eq!(args.description.as_deref(), Some("más profundo"));
+        // Links sharing a line take their names from the files
+        let args = parse_attachment_line(
+            "submit 3 [rf.csv](/u/rf.csv) [gb.csv](/u/gb.csv)",
+            "gb.csv",
+            true,
+        )
+        .unwrap();
+        assert_eq!((args.name.as_str(), args.expected_gain), ("gb", 3.0));
+        assert!(
+            parse_attachment_line("[rf.csv](/u/rf.csv)", "rf.csv", false)
+                .unwrap_err()
+                .contains("rf_1200.csv")
+        );
+
+        let site = serve_attachments(b"1\n").await;
+
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        let config = test_config(
+            dir,
+            "id,clase_binaria\n1,1\n2,0\n",
+            serde_json::json!({
+                "zulip": { "site": site },
+                "submissions": { "quota": { "daily": 2 } }
+            }),
+        );
+        let (tracks, db) = test_competition(&config);
+
+        let message = Message {
+            msg_type: "private".to_string(),
+            sender_email: "student@example.com".to_string(),
+            sender_id: 1,
+            sender_full_name: "Student".to_string(),
+            content: "submit\n\
+                \"xgb a\" 10 [a.csv](/user_uploads/1/a.csv)\n\
+                [rf_5.csv](/user_uploads/1/rf_5.csv)\n\
+                [sin_gain.csv](/user_uploads/1/sin_gain.csv)\n\
+                [lgbm_7.csv](/user_uploads/1/lgbm_7.csv)"
+                .to_string(),
+            timestamp: 0,
+        };
+        let response = process_submit(&message, &tracks, &db, false).await;
+        assert!(
+            response.contains("2 de 4 archivos puntuados"),
+            "{}",
+            response
+        );
+        assert!(response.contains("| a.csv | xgb a |"), "{}", response);
+        assert!(
+            response.contains("Falta la ganancia esperada de `sin_gain.csv`"),
+            "{}",
+            response
+        );
+        // The daily quota of 2 runs out within the batch
+        assert!(
+            response.contains("| lgbm_7.csv | lgbm |  | 7.00 |  | 🚫 |"),
+            "{}",
+            response
+        );
+        assert!(response.contains("📦 **Hoy:** 2 de 2"), "{}", response);
+
+        let stored = db.get_user_submissions("Student").unwrap();
+        let mut names: Vec<(&str, f64)> = stored
+            .iter()
+            .map(|sub| (sub.submission_name.as_str(), sub.expected_gain))
+            .collect();
+        names.sort_by(|a, b| a.0.cmp(b.0));
+        assert_eq!(names, vec![("rf", 5.0), ("xgb a", 10.0)]);
+        assert_eq!(db.get_rejected_attempts("Student").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_content_addressed_storage() {
+        use crate::database::Database;
+        use crate::models::{GainResult, Submission};
+        use crate::storage;
+
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        let config = test_config(dir, "id,clase_binaria\n1,1\n", serde_json::json!({}));
+        let db = Database::new(&config.database.path).unwrap();
+        db.init().unwrap();
+
+        // Identical uploads share a blob named by their checksum
+        let first = storage::store(&db, &config, b"1\n2\n").unwrap();
+        let second = storage::store(&db, &config, b"1\n2\n").unwrap();
+        let other = storage::store(&db, &config, b"3\n").unwrap();
+        assert_eq!(first.checksum, second.checksum);
+        assert_eq!(first.checksum, storage::checksum(b"1\n2\n"));
+        let first_path = storage::blob_path(&storage::root(&config), &first.checksum);
+        assert!(first_path.ends_with(format!("{}/{}.csv", &first.checksum[..2], first.checksum)));
+        assert_eq!(std::fs::read(&first_path).unwrap(), b"1\n2\n");
+        assert_eq!(db.get_blobs().unwrap().len(), 2);
+
+        // A submission from before blobs, stored under the student's folder
+        let legacy = dir.join("students").join("Student");
+        std::fs::create_dir_all(&legacy).unwrap();
+        std::fs::write(legacy.join("envio.csv"), "4\n").unwrap();
+        // Blob paths follow from the checksum and are not recorded
+        for (checksum, path) in [
+            (&first.checksum, String::new()),
+            (&first.checksum, String::new()),
+            (&other.checksum, String::new()),
+            (
+                &storage::checksum(b"4\n"),
+                legacy.join("envio.csv").to_string_lossy().to_string(),
+            ),
+        ] {
+            db.save_submission(&Submission {
+                id: None,
+                user_id: 1,
+                user_email: "student@example.com".to_string(),
+                user_full_name: "Student".to_string(),
+                submission_name: "envio".to_string(),
+                timestamp: "2024-01-01T00:00:00+00:00".to_string(),
+                file_checksum: checksum.clone(),
+                file_path: path,
+                expected_gain: 0.0,
+                actual_gain: 0.0,
+                tp: 0,
+                tn: 0,
+                fp: 0,
+                fn_: 0,
+                positives_predicted: 0,
+                threshold_category: String::new(),
+                after_deadline: false,
+                public: GainResult::default(),
+                private: GainResult::default(),
+                cutoff: None,
+                best_gain: None,
+                best_cutoff: None,
+                confusion_matrix: None,
+                track: "main".to_string(),
+                withdrawal: None,
+                description: None,
+            })
+            .unwrap();
+        }
+
+        let report = storage::verify(&db, &config).unwrap();
+        assert_eq!(report.checked, 3);
+        assert!(report.is_ok(), "{}", report.render());
+
+        std::fs::write(&first_path, "1\n9\n").unwrap();
+        std::fs::remove_file(legacy.join("envio.csv")).unwrap();
+        let report = storage::verify(&db, &config).unwrap();
+        assert_eq!(report.corrupted.len(), 1);
+        assert_eq!(report.corrupted[0].submission_ids, vec![1, 2]);
+        assert_eq!(report.missing.len(), 1);
+        assert_eq!(report.missing[0].submission_ids, vec![4]);
+        assert!(report.render().contains("1 faltantes, 1 corruptos"));
+
+        // Storing the same content again repairs the blob
+        storage::store(&db, &config, b"1\n2\n").unwrap();
+        assert!(storage::verify(&db, &config).unwrap().corrupted.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_intervals_computed_after_the_reply() {
+        use crate::models::Message;
+        use crate::submission::process_submit;
+
+        let site = serve_attachments(b"1\n").await;
+
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        let config = test_config(
+            dir,
+            "id,clase_binaria\n1,1\n2,0\n3,1\n4,0\n",
+            serde_json::json!({
+                "zulip": { "site": site },
+                "bootstrap": { "iterations": 50 }
+            }),
+        );
+        let (tracks, db) = test_competition(&config);
+
+        let message = Message {
+            msg_type: "private".to_string(),
+            sender_email: "student@example.com".to_string(),
+            sender_id: 1,
+            sender_full_name: "Student".to_string(),
+            content: "submit envio 1 [envio.csv](/user_uploads/1/envio.csv)".to_string(),
+            timestamp: 0,
+        };
+        let response = process_submit(&message, &tracks, &db, false).await;
+        assert!(response.contains("🆔 **ID Envío:** 1"), "{}", response);
+
+        // The bootstrap runs on the blocking pool and lands in the database later
+        let deadline = std::time::Instant::now() + std::time::Duration::from_secs(30);
+        let mut intervals = db.get_submission_intervals(1).unwrap();
+        while intervals.is_empty() && std::time::Instant::now() < deadline {
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+            intervals = db.get_submission_intervals(1).unwrap();
+        }
+        assert_eq!(intervals.len(), 3);
+    }
 }
@@ -0,0 +1,68 @@
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+
+use crate::config::BotConfig;
+use crate::master_data::MasterData;
+use crate::metrics::MetricSet;
+use crate::reference::References;
+
+/// Everything needed to score and rank the submissions of one track.
+pub struct Track {
+    pub name: String,
+    /// Effective config, see [`BotConfig::for_track`]
+    pub config: BotConfig,
+    /// Shared with the bootstrap tasks that run off the async runtime
+    pub master_data: Arc<MasterData>,
+    pub metrics: MetricSet,
+    /// Oráculo y baselines del track
+    pub references: Option<References>,
+}
+
+impl Track {
+    pub fn load(config: &BotConfig, name: &str) -> Result<Self> {
+        let config = config
+            .for_track(name)
+            .with_context(|| format!("Unknown track '{}'", name))?;
+        let master_data = MasterData::load_for(&config)
+            .with_context(|| format!("Failed to load master data of track '{}'", name))?;
+        let metrics = MetricSet::from_config(&config);
+        let references = References::compute(&config, &master_data, &metrics);
+
+        Ok(Self {
+            name: name.to_string(),
+            config,
+            master_data: Arc::new(master_data),
+            metrics,
+            references,
+        })
+    }
+
+    /// Every track of the competition, the main one first.
+    pub fn load_all(config: &BotConfig) -> Result<Vec<Self>> {
+        config
+            .track_names()
+            .into_iter()
+            .map(|name| Self::load(config, name))
+            .collect()
+    }
+}
+
+/// Metrics are boxed trait objects, so a copy rebuilds them from the config;
+/// the master data is shared.
+impl Clone for Track {
+    fn clone(&self) -> Self {
+        Self {
+            name: self.name.clone(),
+            config: self.config.clone(),
+            master_data: Arc::clone(&self.master_data),
+            metrics: MetricSet::from_config(&self.config),
+            references: self.references.clone(),
+        }
+    }
+}
+
+/// Track called `name`, ignoring case.
+pub fn find<'a>(tracks: &'a [Track], name: &str) -> Option<&'a Track> {
+    tracks.iter().find(|t| t.name.eq_ignore_ascii_case(name))
+}
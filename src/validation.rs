@@ -0,0 +1,129 @@
+//! Checks on a parsed submission file before it is scored. Each check has a
+//! configurable [`Severity`]: errors reject the submission, warnings are
+//! reported alongside the score.
+
+use crate::config::{Severity, ValidationConfig};
+use crate::master_data::MasterData;
+use crate::models::{Id, PredictionFile};
+
+#[derive(Debug, Clone)]
+pub struct Finding {
+    pub severity: Severity,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ValidationReport {
+    pub findings: Vec<Finding>,
+    /// Rows predicted positive out of the dataset, when the task has positives
+    pub positive_share: Option<(usize, usize)>,
+}
+
+impl ValidationReport {
+    pub fn has_errors(&self) -> bool {
+        self.findings.iter().any(|f| f.severity == Severity::Error)
+    }
+
+    fn add(&mut self, severity: Severity, message: String) {
+        if severity != Severity::Off {
+            self.findings.push(Finding { severity, message });
+        }
+    }
+
+    /// Markdown lines for the bot reply, empty when there is nothing to say.
+    pub fn render(&self) -> String {
+        let mut response = String::new();
+        if let Some((positives, total)) = self.positive_share {
+            response.push_str(&format!(
+                "📈 **Positivos predichos:** {} de {} ({:.1}%)\n",
+                positives,
+                total,
+                pct(positives, total)
+            ));
+        }
+        for finding in &self.findings {
+            let icon = match finding.severity {
+                Severity::Error => "❌",
+                _ => "⚠️",
+            };
+            response.push_str(&format!("{} {}\n", icon, finding.message));
+        }
+        response
+    }
+}
+
+fn pct(part: usize, total: usize) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        part as f64 / total as f64 * 100.0
+    }
+}
+
+/// First `limit` IDs of `ids`, plus how many were left out.
+fn list_ids(ids: &[Id], limit: usize) -> String {
+    let listed: Vec<String> = ids.iter().take(limit).map(|id| format!("`{}`", id)).collect();
+    let mut list = listed.join(", ");
+    if ids.len() > limit {
+        list.push_str(&format!(" y {} más", ids.len() - limit));
+    }
+    list
+}
+
+/// Runs every check on `file`. `positives` is the number of dataset rows
+/// predicted positive, `None` when the task has no positives (regression).
+pub fn validate(
+    file: &PredictionFile,
+    master_data: &MasterData,
+    positives: Option<usize>,
+    config: &ValidationConfig,
+) -> ValidationReport {
+    let mut report = ValidationReport::default();
+
+    if !file.duplicates.is_empty() {
+        report.add(
+            config.duplicate_ids,
+            format!(
+                "**IDs repetidos ({}):** {}. Solo cuenta la primera fila de cada uno",
+                file.duplicates.len(),
+                list_ids(&file.duplicates, config.listed_ids)
+            ),
+        );
+    }
+
+    let mut unknown = master_data.validate_ids(file.predictions.ids());
+    if !unknown.is_empty() {
+        unknown.sort();
+        report.add(
+            config.unknown_ids,
+            format!(
+                "**IDs que no existen en el dataset ({}):** {}",
+                unknown.len(),
+                list_ids(&unknown, config.listed_ids)
+            ),
+        );
+    }
+
+    if let Some(positives) = positives {
+        let total = master_data.total_count();
+        let share = pct(positives, total);
+        report.positive_share = Some((positives, total));
+        if let Some(max) = config.max_positive_pct.filter(|max| share > *max) {
+            report.add(
+                config.positive_share,
+                format!(
+                    "**Predijiste {:.1}% de positivos** (más del {}%). ¿Seguro que el archivo tiene solo los positivos?",
+                    share, max
+                ),
+            );
+        }
+        if let Some(min) = config.min_positive_pct.filter(|min| share < *min) {
+            report.add(
+                config.positive_share,
+                format!("**Predijiste {:.1}% de positivos** (menos del {}%)", share, min),
+            );
+        }
+    }
+
+    report
+}
@@ -3,6 +3,7 @@ use anyhow::Result;
 use reqwest::Client;
 use serde_json::Value;
 
+#[derive(Clone)]
 pub struct ZulipClient {
     email: String,
     api_key: String,